@@ -11,6 +11,10 @@ fi
 SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
 cd "$SCRIPT_DIR"
 
-wasm-pack build --release --target web --features rope
+# `crdt` adds CrdtText/YjsText, used by YjsEditorAdapter when
+# NEXT_PUBLIC_EDITOR_CRDT=wasm. Override with KN_EDITOR_CORE_FEATURES=rope
+# for a smaller bundle without them.
+FEATURES="${KN_EDITOR_CORE_FEATURES:-rope,crdt}"
+wasm-pack build --release --target web --features "$FEATURES"
 echo "[kn-editor-core] Build done. Artifacts in pkg/"
 
@@ -0,0 +1,141 @@
+//! Anchors — UTF-16 positions that follow the text through edits.
+//!
+//! An anchor sits between two code units and is stuck to one of them: a
+//! `left` anchor to the unit before it, a `right` anchor to the unit after
+//! it. An insert exactly at the anchor lands on its free side, so a left
+//! anchor stays put and a right anchor moves past the new text. When the
+//! unit it is stuck to is deleted the anchor collapses to the edit point
+//! and is marked `deleted`; the ids of newly deleted anchors queue up for
+//! `takeDeletedAnchors`.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::ops::Splice;
+use crate::{CoreError, CoreText, Unit};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bias {
+    Left,
+    #[default]
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Anchor {
+    pub id: u32,
+    pub offset: usize,
+    pub bias: Bias,
+    pub deleted: bool,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Anchors {
+    map: HashMap<u32, Anchor>,
+    pub(crate) next_id: u32,
+    newly_deleted: Vec<u32>,
+}
+
+impl Default for Anchors {
+    fn default() -> Self {
+        Anchors { map: HashMap::new(), next_id: 1, newly_deleted: Vec::new() }
+    }
+}
+
+impl Anchors {
+    pub(crate) fn apply(&mut self, edit: &Splice) {
+        let (start, end) = (edit.start, edit.start + edit.removed);
+        for anchor in self.map.values_mut() {
+            let o = anchor.offset;
+            if o < start || (o == start && anchor.bias == Bias::Left) {
+                continue;
+            }
+            if o > end || (o == end && anchor.bias == Bias::Right) {
+                anchor.offset = o - edit.removed + edit.inserted;
+                continue;
+            }
+            anchor.offset = match anchor.bias {
+                Bias::Left => start,
+                Bias::Right => start + edit.inserted,
+            };
+            if edit.removed > 0 && !anchor.deleted {
+                anchor.deleted = true;
+                self.newly_deleted.push(anchor.id);
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Create an anchor at a UTF-16 offset. `bias` is `'left'` or
+    /// `'right'` (the default). Returns its id.
+    #[wasm_bindgen(js_name = createAnchor)]
+    pub fn create_anchor_js(&mut self, offset: usize, bias: JsValue) -> Result<u32, JsValue> {
+        let bias = if bias.is_undefined() || bias.is_null() {
+            Bias::default()
+        } else {
+            serde_wasm_bindgen::from_value(bias)?
+        };
+        Ok(self.create_anchor(offset, bias)?)
+    }
+
+    /// Move an existing anchor, clearing its `deleted` flag. Returns false
+    /// for an unknown id.
+    #[wasm_bindgen(js_name = setAnchor)]
+    pub fn set_anchor(&mut self, id: u32, offset: usize) -> Result<bool, CoreError> {
+        let offset = self.check_index(Unit::Utf16, offset, self.rope.len_utf16())?;
+        Ok(match self.anchors.map.get_mut(&id) {
+            Some(anchor) => {
+                anchor.offset = offset;
+                anchor.deleted = false;
+                true
+            }
+            None => false,
+        })
+    }
+
+    #[wasm_bindgen(js_name = removeAnchor)]
+    pub fn remove_anchor(&mut self, id: u32) -> bool {
+        self.anchors.map.remove(&id).is_some()
+    }
+
+    /// `{ id, offset, bias, deleted }` for one anchor, or `undefined`.
+    #[wasm_bindgen(js_name = anchor)]
+    pub fn anchor_js(&self, id: u32) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.anchor(id))?)
+    }
+
+    /// Look up many anchors at once; unknown ids come back as `undefined`.
+    #[wasm_bindgen(js_name = anchors)]
+    pub fn anchors_js(&self, ids: Vec<u32>) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.anchors(&ids))?)
+    }
+
+    /// Ids of anchors whose text was deleted since the last call.
+    #[wasm_bindgen(js_name = takeDeletedAnchors)]
+    pub fn take_deleted_anchors(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.anchors.newly_deleted)
+    }
+}
+
+impl CoreText {
+    pub fn create_anchor(&mut self, offset: usize, bias: Bias) -> Result<u32, CoreError> {
+        let offset = self.check_index(Unit::Utf16, offset, self.rope.len_utf16())?;
+        let id = self.anchors.next_id;
+        self.anchors.next_id += 1;
+        self.anchors.map.insert(id, Anchor { id, offset, bias, deleted: false });
+        Ok(id)
+    }
+
+    pub fn anchor(&self, id: u32) -> Option<Anchor> {
+        self.anchors.map.get(&id).copied()
+    }
+
+    pub fn anchors(&self, ids: &[u32]) -> Vec<Option<Anchor>> {
+        ids.iter().map(|id| self.anchor(*id)).collect()
+    }
+}
@@ -0,0 +1,342 @@
+//! Block segmentation — native port of `segmentText` from `model.ts`.
+//!
+//! The document is split into blocks at runs of two or more `\n`; each
+//! block owns the run that follows it as its `gap`. Offsets and hashes are
+//! over UTF-16 code units so they match `EditorBlock` and `hashText`
+//! exactly.
+//!
+//! After an edit only the blocks around it are resegmented. The region
+//! starts at the block holding the character before the edit (so a newline
+//! typed right after a gap is seen) and ends at the close of the gap of the
+//! block holding the edit's end; if the region no longer ends on a gap it
+//! grows one block at a time. Blocks past the region only shift.
+//!
+//! Block ids (`block-N`) are persistent: an edit never renames blocks
+//! outside its region, and inside it ids follow the rules in `carry_ids`.
+//! `exportBlockIds` / `importBlockIds` carry them across a reload.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::Buffer;
+use crate::ops::Splice;
+use crate::CoreText;
+
+#[derive(Clone, Debug)]
+struct Block {
+    id: String,
+    start: usize,
+    len: usize,
+    gap: usize,
+    hash: u32,
+}
+
+impl Block {
+    fn end(&self) -> usize {
+        self.start + self.len + self.gap
+    }
+}
+
+/// Same shape as `EditorBlock` in `model.ts`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EditorBlock {
+    pub id: String,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub gap: String,
+    pub hash: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockRef {
+    pub id: String,
+    pub hash: String,
+}
+
+/// Blocks that appeared, disappeared or changed hash since the last
+/// `takeBlockDelta`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct BlockDelta {
+    pub added: Vec<EditorBlock>,
+    pub removed: Vec<BlockRef>,
+    pub changed: Vec<EditorBlock>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Blocks {
+    list: Vec<Block>,
+    next_id: u64,
+    /// id → (hash last reported, hash now); `None` means absent.
+    pending: HashMap<String, (Option<u32>, Option<u32>)>,
+}
+
+impl Blocks {
+    pub(crate) fn new(buf: &Buffer) -> Blocks {
+        let text = buf.slice(0, buf.len_chars());
+        let mut list = segment(&text, 0, true).unwrap_or_default();
+        for (i, block) in list.iter_mut().enumerate() {
+            block.id = format!("block-{i}");
+        }
+        let next_id = list.len() as u64;
+        Blocks { list, next_id, pending: HashMap::new() }
+    }
+
+    /// Resegment around `edit`, which has already been applied to `buf`.
+    pub(crate) fn apply(&mut self, buf: &Buffer, edit: &Splice) {
+        let first = self.index_of(edit.start.max(1) - 1);
+        let mut last = self.index_of(edit.start + edit.removed);
+        let mut segs = loop {
+            let start = self.list[first].start;
+            let end = self.list[last].end() + edit.inserted - edit.removed;
+            let to_eof = last + 1 == self.list.len();
+            if let Some(segs) = segment(&buf.slice_utf16(start, end), start, to_eof) {
+                break segs;
+            }
+            last += 1;
+        };
+
+        let removed: Vec<Block> = self.list.drain(first..=last).collect();
+        self.carry_ids(&removed, &mut segs);
+        let added = segs.len();
+        self.list.splice(first..first, segs);
+        for old in removed {
+            self.note(old.id, Some(old.hash), None);
+        }
+        for i in first..first + added {
+            let block = &self.list[i];
+            self.note(block.id.clone(), None, Some(block.hash));
+        }
+        for block in &mut self.list[first + added..] {
+            block.start = block.start + edit.inserted - edit.removed;
+        }
+    }
+
+    /// Hand the ids of the blocks a region used to hold to the blocks it
+    /// holds now. Blocks whose content is unchanged keep their id, matched
+    /// inwards from both ends of the region. The rest pair up in order, so
+    /// a split leaves the id on the left fragment and a merge keeps the
+    /// leftmost block's id; any extra new block gets a fresh id.
+    fn carry_ids(&mut self, old: &[Block], new: &mut [Block]) {
+        let same = |o: &Block, n: &Block| o.hash == n.hash;
+        let prefix = old.iter().zip(new.iter()).take_while(|(o, n)| same(o, n)).count();
+        let room = old.len().min(new.len()) - prefix;
+        let suffix = old.iter().rev().zip(new.iter().rev()).take(room).take_while(|(o, n)| same(o, n)).count();
+
+        let (old_len, new_len) = (old.len(), new.len());
+        for (o, n) in old[..prefix].iter().zip(&mut new[..prefix]) {
+            n.id = o.id.clone();
+        }
+        for (o, n) in old[old_len - suffix..].iter().zip(&mut new[new_len - suffix..]) {
+            n.id = o.id.clone();
+        }
+        let mut rest = old[prefix..old_len - suffix].iter();
+        for n in &mut new[prefix..new_len - suffix] {
+            n.id = match rest.next() {
+                Some(o) => o.id.clone(),
+                None => self.fresh_id(),
+            };
+        }
+    }
+
+    fn fresh_id(&mut self) -> String {
+        let id = format!("block-{}", self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    pub(crate) fn export_ids(&self) -> Vec<BlockRef> {
+        self.list
+            .iter()
+            .map(|b| BlockRef { id: b.id.clone(), hash: hash_string(b.hash) })
+            .collect()
+    }
+
+    /// Restore ids saved by `export_ids` onto a freshly loaded document.
+    /// With the same number of blocks as at export they are restored in
+    /// order; otherwise each block takes the next saved id with a matching
+    /// hash. Unmatched blocks get fresh ids. Returns how many were restored.
+    pub(crate) fn import_ids(&mut self, refs: &[BlockRef]) -> usize {
+        let max_saved = refs.iter().filter_map(|r| r.id.strip_prefix("block-")?.parse::<u64>().ok()).max();
+        self.next_id = self.next_id.max(max_saved.map_or(0, |n| n + 1));
+
+        let in_order = refs.len() == self.list.len();
+        let (mut cursor, mut restored) = (0, 0);
+        for i in 0..self.list.len() {
+            let hash = hash_string(self.list[i].hash);
+            let found = if in_order {
+                Some(cursor)
+            } else {
+                refs[cursor..].iter().position(|r| r.hash == hash).map(|k| cursor + k)
+            };
+            self.list[i].id = match found {
+                Some(k) => {
+                    cursor = k + 1;
+                    restored += 1;
+                    refs[k].id.clone()
+                }
+                None => self.fresh_id(),
+            };
+        }
+        self.pending.clear();
+        restored
+    }
+
+    pub(crate) fn editor_blocks(&self, buf: &Buffer) -> Vec<EditorBlock> {
+        self.list.iter().map(|b| editor_block(buf, b)).collect()
+    }
+
+    pub(crate) fn take_delta(&mut self, buf: &Buffer) -> BlockDelta {
+        let mut delta = BlockDelta::default();
+        if self.pending.is_empty() {
+            return delta;
+        }
+        let by_id: HashMap<&str, &Block> = self.list.iter().map(|b| (b.id.as_str(), b)).collect();
+        for (id, (before, after)) in self.pending.drain() {
+            match (before, after) {
+                (None, Some(_)) => delta.added.push(editor_block(buf, by_id[id.as_str()])),
+                (Some(hash), None) => delta.removed.push(BlockRef { id, hash: hash_string(hash) }),
+                (Some(a), Some(b)) if a != b => delta.changed.push(editor_block(buf, by_id[id.as_str()])),
+                _ => {}
+            }
+        }
+        delta.added.sort_by_key(|b| b.start);
+        delta.changed.sort_by_key(|b| b.start);
+        delta.removed.sort_by(|a, b| a.id.cmp(&b.id));
+        delta
+    }
+
+    /// `(id, hash, start, end)` of each block's text, gap excluded.
+    pub(crate) fn spans(&self) -> impl Iterator<Item = (&str, u32, usize, usize)> + '_ {
+        self.list.iter().map(|b| (b.id.as_str(), b.hash, b.start, b.start + b.len))
+    }
+
+    fn index_of(&self, utf16: usize) -> usize {
+        self.list.partition_point(|b| b.start <= utf16) - 1
+    }
+
+    /// Fold one change into the pending delta. `reported` is the hash the
+    /// caller last saw for `id`, used only if `id` is not pending yet.
+    fn note(&mut self, id: String, reported: Option<u32>, now: Option<u32>) {
+        let entry = self.pending.entry(id).or_insert((reported, None));
+        entry.1 = now;
+    }
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// All blocks as `EditorBlock[]`.
+    #[wasm_bindgen(js_name = blocks)]
+    pub fn blocks_js(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.editor_blocks())?)
+    }
+
+    /// Blocks added, removed and changed since the previous call, as
+    /// `{ added: EditorBlock[], removed: {id, hash}[], changed: EditorBlock[] }`.
+    #[wasm_bindgen(js_name = takeBlockDelta)]
+    pub fn take_block_delta_js(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.take_block_delta())?)
+    }
+
+    /// Block ids in document order as `{id, hash}[]`, for persisting.
+    #[wasm_bindgen(js_name = exportBlockIds)]
+    pub fn export_block_ids_js(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.export_block_ids())?)
+    }
+
+    /// Rehydrate ids saved by `exportBlockIds` after reloading the text.
+    /// Clears the pending block delta; returns how many ids were restored.
+    #[wasm_bindgen(js_name = importBlockIds)]
+    pub fn import_block_ids_js(&mut self, ids: JsValue) -> Result<usize, JsValue> {
+        let ids: Vec<BlockRef> = serde_wasm_bindgen::from_value(ids)?;
+        Ok(self.import_block_ids(&ids))
+    }
+}
+
+impl CoreText {
+    pub fn editor_blocks(&self) -> Vec<EditorBlock> {
+        self.blocks.editor_blocks(&self.rope)
+    }
+
+    pub fn take_block_delta(&mut self) -> BlockDelta {
+        self.blocks.take_delta(&self.rope)
+    }
+
+    pub fn export_block_ids(&self) -> Vec<BlockRef> {
+        self.blocks.export_ids()
+    }
+
+    pub fn import_block_ids(&mut self, ids: &[BlockRef]) -> usize {
+        self.blocks.import_ids(ids)
+    }
+}
+
+fn editor_block(buf: &Buffer, block: &Block) -> EditorBlock {
+    let end = block.start + block.len;
+    EditorBlock {
+        id: block.id.clone(),
+        start: block.start,
+        end,
+        text: buf.slice_utf16(block.start, end),
+        gap: "\n".repeat(block.gap),
+        hash: hash_string(block.hash),
+    }
+}
+
+/// Split `text`, which begins at UTF-16 offset `base`, at runs of two or
+/// more `\n`. Ids are left empty. Unless `to_eof`, the text must end on a
+/// gap; `None` means it did not and the caller should widen the region.
+fn segment(text: &str, base: usize, to_eof: bool) -> Option<Vec<Block>> {
+    let mut blocks = Vec::new();
+    let (mut start, mut start_byte) = (0, 0);
+    let mut run: Option<usize> = None;
+    let mut pos = 0;
+    let mut close_run = |run_start: usize, pos: usize, byte: usize, start: &mut usize, start_byte: &mut usize| {
+        if pos - run_start >= 2 {
+            blocks.push(Block {
+                id: String::new(),
+                start: base + *start,
+                len: run_start - *start,
+                gap: pos - run_start,
+                hash: hash_text(&text[*start_byte..byte]),
+            });
+            *start = pos;
+            *start_byte = byte;
+        }
+    };
+    for (byte, c) in text.char_indices() {
+        if c == '\n' {
+            run.get_or_insert(pos);
+        } else if let Some(run_start) = run.take() {
+            close_run(run_start, pos, byte, &mut start, &mut start_byte);
+        }
+        pos += c.len_utf16();
+    }
+    if let Some(run_start) = run {
+        close_run(run_start, pos, text.len(), &mut start, &mut start_byte);
+    }
+    if to_eof {
+        blocks.push(Block {
+            id: String::new(),
+            start: base + start,
+            len: pos - start,
+            gap: 0,
+            hash: hash_text(&text[start_byte..]),
+        });
+    } else if start != pos {
+        return None;
+    }
+    Some(blocks)
+}
+
+/// Port of `hashText`: Java-style 31x string hash over UTF-16 code units.
+fn hash_text(s: &str) -> u32 {
+    s.encode_utf16()
+        .fold(0i32, |h, cu| h.wrapping_shl(5).wrapping_sub(h).wrapping_add(cu as i32)) as u32
+}
+
+fn hash_string(hash: u32) -> String {
+    format!("h{hash:x}")
+}
@@ -0,0 +1,273 @@
+//! Text storage backend shared by `CoreText` and friends.
+//!
+//! With the `rope` feature the text lives in a `ropey::Rope`; without it we
+//! fall back to a plain `String`. Every index conversion goes through here so
+//! both backends agree on char, byte, UTF-16 and line addressing. The
+//! fallback keeps a `LineIndex` alongside its string so line lookups and
+//! conversions stay proportional to one line, not the whole document.
+//!
+//! Cloning is cheap on both backends, which is what snapshots and
+//! transaction checkpoints rely on: ropey shares tree nodes between clones,
+//! and the fallback keeps its string and line table behind `Rc`s that are
+//! copied on the first write after a clone.
+//!
+//! Searches need the text in one piece; `text()` provides it. Ropey builds
+//! that string on first use and keeps it until the next edit, the fallback
+//! just shares its own. `folded()` is cached the same way on both.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[cfg(feature = "rope")]
+use ropey::Rope;
+
+use crate::fold::Folded;
+
+#[cfg(not(feature = "rope"))]
+use crate::lines::{LineIndex, Pos};
+
+#[derive(Clone)]
+pub(crate) struct Buffer {
+    #[cfg(feature = "rope")]
+    rope: Rope,
+    #[cfg(feature = "rope")]
+    flat: RefCell<Option<Rc<String>>>,
+    #[cfg(not(feature = "rope"))]
+    rope: Rc<String>,
+    #[cfg(not(feature = "rope"))]
+    lines: Rc<LineIndex>,
+    folded: RefCell<Option<Rc<Folded>>>,
+}
+
+impl Buffer {
+    pub(crate) fn new() -> Buffer {
+        Buffer::from_str("")
+    }
+
+    pub(crate) fn from_str(s: &str) -> Buffer {
+        #[cfg(feature = "rope")]
+        {
+            Buffer { rope: Rope::from_str(s), flat: RefCell::new(None), folded: RefCell::new(None) }
+        }
+        #[cfg(not(feature = "rope"))]
+        {
+            Buffer {
+                rope: Rc::new(s.to_string()),
+                lines: Rc::new(LineIndex::from_str(s)),
+                folded: RefCell::new(None),
+            }
+        }
+    }
+
+    pub(crate) fn len_chars(&self) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.len_chars() }
+        #[cfg(not(feature = "rope"))]
+        { self.end().ch }
+    }
+
+    pub(crate) fn len_bytes(&self) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.len_bytes() }
+        #[cfg(not(feature = "rope"))]
+        { self.rope.len() }
+    }
+
+    pub(crate) fn len_utf16(&self) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.len_utf16_cu() }
+        #[cfg(not(feature = "rope"))]
+        { self.end().utf16 }
+    }
+
+    pub(crate) fn len_lines(&self) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.len_lines() }
+        #[cfg(not(feature = "rope"))]
+        { self.lines.len_lines() }
+    }
+
+    pub(crate) fn insert(&mut self, char_idx: usize, text: &str) {
+        self.folded.get_mut().take();
+        #[cfg(feature = "rope")]
+        {
+            self.flat.get_mut().take();
+            self.rope.insert(char_idx, text);
+        }
+        #[cfg(not(feature = "rope"))]
+        {
+            let at = self.pos_of_char(char_idx);
+            Rc::make_mut(&mut self.rope).insert_str(at.byte, text);
+            Rc::make_mut(&mut self.lines).insert(at, text);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, start_char: usize, end_char: usize) {
+        self.folded.get_mut().take();
+        #[cfg(feature = "rope")]
+        {
+            self.flat.get_mut().take();
+            self.rope.remove(start_char..end_char);
+        }
+        #[cfg(not(feature = "rope"))]
+        {
+            let start = self.pos_of_char(start_char);
+            let end = self.pos_of_char(end_char);
+            Rc::make_mut(&mut self.rope).replace_range(start.byte..end.byte, "");
+            Rc::make_mut(&mut self.lines).remove(start, end);
+        }
+    }
+
+    pub(crate) fn slice(&self, start_char: usize, end_char: usize) -> String {
+        #[cfg(feature = "rope")]
+        {
+            self.rope.slice(start_char..end_char).to_string()
+        }
+        #[cfg(not(feature = "rope"))]
+        {
+            let sb = self.char_to_byte(start_char);
+            let eb = self.char_to_byte(end_char);
+            self.rope[sb..eb].to_string()
+        }
+    }
+
+    /// The whole text as one string (see the module docs).
+    pub(crate) fn text(&self) -> Rc<String> {
+        #[cfg(feature = "rope")]
+        {
+            self.flat.borrow_mut().get_or_insert_with(|| Rc::new(self.rope.to_string())).clone()
+        }
+        #[cfg(not(feature = "rope"))]
+        {
+            self.rope.clone()
+        }
+    }
+
+    /// `text()` case-folded and stripped of accents (see `fold.rs`).
+    pub(crate) fn folded(&self) -> Rc<Folded> {
+        self.folded.borrow_mut().get_or_insert_with(|| Rc::new(Folded::new(&self.text()))).clone()
+    }
+
+    /// The storage chunk holding `byte_idx` and the byte offset it starts
+    /// at. The fallback is a single chunk.
+    pub(crate) fn chunk_at_byte(&self, byte_idx: usize) -> (&str, usize) {
+        #[cfg(feature = "rope")]
+        {
+            let (chunk, start, _, _) = self.rope.chunk_at_byte(byte_idx);
+            (chunk, start)
+        }
+        #[cfg(not(feature = "rope"))]
+        {
+            let _ = byte_idx;
+            (self.rope.as_str(), 0)
+        }
+    }
+
+    pub(crate) fn slice_utf16(&self, start_utf16: usize, end_utf16: usize) -> String {
+        self.slice(self.utf16_to_char(start_utf16), self.utf16_to_char(end_utf16))
+    }
+
+    pub(crate) fn char_to_byte(&self, char_idx: usize) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.char_to_byte(char_idx) }
+        #[cfg(not(feature = "rope"))]
+        { self.pos_of_char(char_idx).byte }
+    }
+
+    pub(crate) fn byte_to_char(&self, byte_idx: usize) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.byte_to_char(byte_idx) }
+        #[cfg(not(feature = "rope"))]
+        { self.pos_of_byte(byte_idx).ch }
+    }
+
+    pub(crate) fn char_to_utf16(&self, char_idx: usize) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.char_to_utf16_cu(char_idx) }
+        #[cfg(not(feature = "rope"))]
+        { self.pos_of_char(char_idx).utf16 }
+    }
+
+    pub(crate) fn utf16_to_char(&self, utf16_idx: usize) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.utf16_cu_to_char(utf16_idx) }
+        #[cfg(not(feature = "rope"))]
+        { self.pos_of_utf16(utf16_idx).ch }
+    }
+
+    pub(crate) fn char_to_line(&self, char_idx: usize) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.char_to_line(char_idx) }
+        #[cfg(not(feature = "rope"))]
+        { self.lines.line_of_char(char_idx) }
+    }
+
+    pub(crate) fn line_to_char(&self, line_idx: usize) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.line_to_char(line_idx) }
+        #[cfg(not(feature = "rope"))]
+        {
+            if line_idx >= self.lines.len_lines() {
+                return self.len_chars();
+            }
+            self.lines.start(line_idx).ch
+        }
+    }
+
+    pub(crate) fn utf16_to_line(&self, utf16_idx: usize) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.char_to_line(self.rope.utf16_cu_to_char(utf16_idx)) }
+        #[cfg(not(feature = "rope"))]
+        { self.lines.line_of_utf16(utf16_idx) }
+    }
+
+    pub(crate) fn line_to_utf16(&self, line_idx: usize) -> usize {
+        #[cfg(feature = "rope")]
+        { self.rope.char_to_utf16_cu(self.rope.line_to_char(line_idx)) }
+        #[cfg(not(feature = "rope"))]
+        {
+            if line_idx >= self.lines.len_lines() {
+                return self.len_utf16();
+            }
+            self.lines.start(line_idx).utf16
+        }
+    }
+}
+
+// The fallback resolves a position by jumping to the start of its line and
+// scanning forward from there, so lookups cost one line rather than the
+// whole document. Indices past the end resolve to the end.
+#[cfg(not(feature = "rope"))]
+impl Buffer {
+    fn end(&self) -> Pos {
+        self.pos_of_byte(self.rope.len())
+    }
+
+    fn scan(&self, line_idx: usize, mut stop: impl FnMut(Pos, char) -> bool) -> Pos {
+        let mut pos = self.lines.start(line_idx);
+        for c in self.rope[pos.byte..].chars() {
+            if stop(pos, c) {
+                break;
+            }
+            pos.byte += c.len_utf8();
+            pos.ch += 1;
+            pos.utf16 += c.len_utf16();
+        }
+        pos
+    }
+
+    fn pos_of_char(&self, char_idx: usize) -> Pos {
+        self.scan(self.lines.line_of_char(char_idx), |p, _| p.ch >= char_idx)
+    }
+
+    /// A byte inside a multi-byte char resolves to that char, like ropey.
+    fn pos_of_byte(&self, byte_idx: usize) -> Pos {
+        self.scan(self.lines.line_of_byte(byte_idx), |p, c| p.byte + c.len_utf8() > byte_idx)
+    }
+
+    /// An offset between the halves of a surrogate pair resolves to that
+    /// char, like ropey.
+    fn pos_of_utf16(&self, utf16_idx: usize) -> Pos {
+        self.scan(self.lines.line_of_utf16(utf16_idx), |p, c| p.utf16 + c.len_utf16() > utf16_idx)
+    }
+}
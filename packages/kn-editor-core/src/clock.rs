@@ -0,0 +1,13 @@
+//! Wall-clock milliseconds for time budgets and typing-run heuristics.
+//! `std::time` panics on `wasm32-unknown-unknown`, so WASM asks JS instead.
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_ms() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,924 @@
+//! Replicated text — the Rust/WASM CRDT that ADR 0005 leaves room for.
+//!
+//! `CrdtText` is a sequence CRDT of the YATA family (the algorithm behind
+//! Yjs). Every UTF-16 unit ever inserted has an id `(agent, seq)`: each
+//! replica picks a unique `agent` and numbers its own units 0, 1, 2, ...
+//! An insert records the ids of its neighbours at the time (its left and
+//! right origin); a delete only marks units, which stay behind as
+//! tombstones (without their text) so later inserts can still find their
+//! origins. Concurrent inserts at the same spot are ordered by the YATA
+//! rules, so replicas that have seen the same ops hold the same text, in
+//! whatever order the ops arrived.
+//!
+//! Ops whose dependencies have not arrived yet (an origin, an earlier unit
+//! of the same agent, a unit to delete) wait in a buffer and are applied as
+//! soon as they can be.
+//!
+//! Local edits come in as UTF-16 positions (`insert`, `delete`, or
+//! `applyOps` with a `ChangeRecord`'s ops) and go out as compact binary
+//! updates from `takeUpdate`. `applyUpdate` merges a peer's update and
+//! returns the `EditOp[]` that brings a mirrored `CoreText` along, so an
+//! adapter can drive both without diffing. `stateVector` and
+//! `encodeStateAsUpdate` catch up a replica that missed updates.
+//!
+//! The merge logic lives in `Replica`, which `yjs.rs` shares; this module
+//! adds the update format below. Items live in one `Vec` in document order
+//! and are found by linear scans; consecutive typing by one agent stays
+//! one item.
+//!
+//! Update format (integers are `varUint`, see `encoding.rs`):
+//!
+//! ```text
+//! update = count insert* count delete*
+//! insert = agent seq flags origin? right_origin? content
+//!          flags: 1 = has origin, 2 = has right origin, 4 = deleted
+//!          origin, right_origin = agent seq
+//!          content = deleted ? len : string
+//! delete = agent seq len
+//! state  = count (agent next_seq)*
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use wasm_bindgen::prelude::*;
+
+use crate::encoding::{Decoder, Encoder};
+use crate::ops::{utf16_len, validate_ops, EditOp, TextRange};
+use crate::{CoreError, Unit};
+
+const HAS_ORIGIN: u8 = 1;
+const HAS_RIGHT_ORIGIN: u8 = 2;
+const DELETED: u8 = 4;
+
+/// Stands in for an embed in the visible text, so offsets line up with
+/// Yjs's, where an embed counts as one unit.
+const EMBED: &str = "\u{FFFC}";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Id {
+    pub(crate) agent: u64,
+    pub(crate) seq: u64,
+}
+
+impl Id {
+    pub(crate) fn plus(self, n: u64) -> Id {
+        Id { agent: self.agent, seq: self.seq + n }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Content {
+    Text(String),
+    /// A one-unit embed from Yjs: its content ref and encoded content.
+    Embed { kind: u8, raw: Vec<u8> },
+    /// A Yjs formatting mark (encoded key and value); takes a seq but no
+    /// room in the text.
+    Format(Vec<u8>),
+    Deleted,
+}
+
+/// A run of units with consecutive seqs, inserted together.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Item {
+    pub(crate) id: Id,
+    pub(crate) origin: Option<Id>,
+    pub(crate) right_origin: Option<Id>,
+    pub(crate) len: u64,
+    pub(crate) content: Content,
+}
+
+impl Item {
+    pub(crate) fn end(&self) -> u64 {
+        self.id.seq + self.len
+    }
+
+    fn contains(&self, id: Id) -> bool {
+        id.agent == self.id.agent && self.id.seq <= id.seq && id.seq < self.end()
+    }
+
+    fn text(&self) -> &str {
+        match &self.content {
+            Content::Text(text) => text,
+            Content::Embed { .. } => EMBED,
+            Content::Format(_) | Content::Deleted => "",
+        }
+    }
+
+    fn visible(&self) -> usize {
+        match &self.content {
+            Content::Text(_) => self.len as usize,
+            Content::Embed { .. } => 1,
+            Content::Format(_) | Content::Deleted => 0,
+        }
+    }
+
+    /// The same item without its first `n` units (`0 < n < len`).
+    pub(crate) fn skip(self, n: u64) -> Item {
+        let content = match self.content {
+            Content::Text(text) => Content::Text(split_utf16(&text, n as usize).1),
+            content => content,
+        };
+        Item { id: self.id.plus(n), origin: Some(self.id.plus(n - 1)), right_origin: self.right_origin, len: self.len - n, content }
+    }
+}
+
+/// Units `id.seq..id.seq + len` of `id.agent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Delete {
+    pub(crate) id: Id,
+    pub(crate) len: u64,
+}
+
+/// Units that exist but are not part of this text: structs of other Yjs
+/// types in the same document, or garbage-collected ones (`gc`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Other {
+    pub(crate) id: Id,
+    pub(crate) len: u64,
+    pub(crate) gc: bool,
+    /// The struct as encoded, when it came whole, so it can be passed on.
+    pub(crate) raw: Option<Vec<u8>>,
+}
+
+/// A struct from a peer's update.
+#[derive(Clone, Debug)]
+pub(crate) enum Incoming {
+    /// `in_text` places an item with neither origin: whether its parent is
+    /// this text. Items with an origin follow it. `raw` is kept should the
+    /// item turn out to belong elsewhere.
+    Item { item: Item, in_text: bool, raw: Option<Vec<u8>> },
+    Other(Other),
+}
+
+impl Incoming {
+    fn id(&self) -> Id {
+        match self {
+            Incoming::Item { item, .. } => item.id,
+            Incoming::Other(other) => other.id,
+        }
+    }
+}
+
+/// A stored struct, for encoding the state.
+pub(crate) enum Stored<'a> {
+    Item(&'a Item),
+    Other(&'a Other),
+}
+
+impl Stored<'_> {
+    pub(crate) fn id(&self) -> Id {
+        match self {
+            Stored::Item(item) => item.id,
+            Stored::Other(other) => other.id,
+        }
+    }
+
+    pub(crate) fn end(&self) -> u64 {
+        match self {
+            Stored::Item(item) => item.end(),
+            Stored::Other(other) => other.id.seq + other.len,
+        }
+    }
+}
+
+/// The YATA merge state behind `CrdtText` and `YjsText`, independent of
+/// the update format.
+pub(crate) struct Replica {
+    agent: u64,
+    items: Vec<Item>,
+    others: Vec<Other>,
+    /// agent → seq of its next unit; every unit below it is integrated.
+    clock: HashMap<u64, u64>,
+    pending: Vec<Incoming>,
+    pending_deletes: Vec<Delete>,
+    /// Deleted units of `others` that are not GC, sorted and merged.
+    deleted_others: Vec<Delete>,
+    /// Local ops not yet taken by `take_local`.
+    local_items: Vec<Item>,
+    local_deletes: Vec<Delete>,
+}
+
+impl Replica {
+    pub(crate) fn new(agent: u64) -> Replica {
+        Replica {
+            agent,
+            items: Vec::new(),
+            others: Vec::new(),
+            clock: HashMap::new(),
+            pending: Vec::new(),
+            pending_deletes: Vec::new(),
+            deleted_others: Vec::new(),
+            local_items: Vec::new(),
+            local_deletes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn agent(&self) -> u64 {
+        self.agent
+    }
+
+    pub(crate) fn text(&self) -> String {
+        self.items.iter().map(Item::text).collect()
+    }
+
+    pub(crate) fn len_utf16(&self) -> usize {
+        self.items.iter().map(Item::visible).sum()
+    }
+
+    pub(crate) fn pending_count(&self) -> usize {
+        self.pending.len() + self.pending_deletes.len()
+    }
+
+    /// Returns where the text went: an offset inside a surrogate pair
+    /// moves back before it.
+    pub(crate) fn insert(&mut self, at: usize, text: &str) -> Result<TextRange, CoreError> {
+        let len = self.len_utf16();
+        if at > len {
+            return Err(CoreError::OutOfBounds { unit: Unit::Utf16, index: at, len });
+        }
+        Ok(self.local_insert(at, text))
+    }
+
+    /// Returns the range actually deleted: ends inside a surrogate pair
+    /// move back before it, so the pair goes whole or not at all.
+    pub(crate) fn delete(&mut self, at: usize, len: usize) -> Result<TextRange, CoreError> {
+        let end = at.saturating_add(len);
+        let doc_len = self.len_utf16();
+        if end > doc_len {
+            return Err(CoreError::OutOfBounds { unit: Unit::Utf16, index: end, len: doc_len });
+        }
+        Ok(self.local_delete(at, end))
+    }
+
+    pub(crate) fn apply_ops(&mut self, ops: &[EditOp]) -> Result<(), CoreError> {
+        validate_ops(self.len_utf16(), ops)?;
+        for op in ops {
+            match op {
+                EditOp::Ins { at, text } => self.local_insert(*at, text),
+                EditOp::Del { at, len } => self.local_delete(*at, at + len),
+            };
+        }
+        Ok(())
+    }
+
+    /// Local ops since the last call: inserts in seq order and deleted
+    /// ranges, each merged where contiguous.
+    pub(crate) fn take_local(&mut self) -> (Vec<Item>, Vec<Delete>) {
+        (std::mem::take(&mut self.local_items), std::mem::take(&mut self.local_deletes))
+    }
+
+    /// Merge a peer's structs and deletes. Returns the ops the visible text
+    /// went through, in order.
+    pub(crate) fn receive(&mut self, structs: Vec<Incoming>, deletes: Vec<Delete>) -> Vec<EditOp> {
+        let mut ops = Vec::new();
+        self.pending.extend(structs);
+        self.pending_deletes.extend(deletes);
+        self.flush_pending(&mut ops);
+        ops
+    }
+
+    /// Next expected seq per agent, encoded (the same layout as Yjs's state
+    /// vector, highest agent first like Yjs writes it).
+    pub(crate) fn state_vector(&self) -> Vec<u8> {
+        let mut enc = Encoder::new();
+        let clock: BTreeMap<_, _> = self.clock.iter().collect();
+        enc.var_uint(clock.len() as u64);
+        for (agent, seq) in clock.into_iter().rev() {
+            enc.var_uint(*agent);
+            enc.var_uint(*seq);
+        }
+        enc.finish()
+    }
+
+    /// Every stored struct, sorted by id.
+    pub(crate) fn structs(&self) -> Vec<Stored<'_>> {
+        let mut structs: Vec<Stored> =
+            self.items.iter().map(Stored::Item).chain(self.others.iter().map(Stored::Other)).collect();
+        structs.sort_by_key(Stored::id);
+        structs
+    }
+
+    /// Every deleted range: tombstones, garbage-collected units and deleted
+    /// units of other types, sorted and merged.
+    pub(crate) fn delete_set(&self) -> Vec<Delete> {
+        let mut deleted: Vec<Delete> = self
+            .items
+            .iter()
+            .filter(|item| item.content == Content::Deleted)
+            .map(|item| Delete { id: item.id, len: item.len })
+            .chain(self.others.iter().filter(|other| other.gc).map(|other| Delete { id: other.id, len: other.len }))
+            .chain(self.deleted_others.iter().copied())
+            .collect();
+        deleted.sort_by_key(|delete| delete.id);
+        let mut merged = Vec::with_capacity(deleted.len());
+        for delete in deleted {
+            push_delete(&mut merged, delete);
+        }
+        merged
+    }
+
+    fn next_seq(&self, agent: u64) -> u64 {
+        self.clock.get(&agent).copied().unwrap_or(0)
+    }
+
+    fn has(&self, id: Id) -> bool {
+        id.seq < self.next_seq(id.agent)
+    }
+
+    fn position(&self, id: Id) -> Option<usize> {
+        self.items.iter().position(|item| item.contains(id))
+    }
+
+    fn other(&self, id: Id) -> Option<&Other> {
+        self.others.iter().find(|other| other.id.agent == id.agent && other.id.seq <= id.seq && id.seq < other.id.seq + other.len)
+    }
+
+    /// Split item `i` after its first `at` units (`0 < at < len`).
+    fn split(&mut self, i: usize, at: u64) {
+        let item = &mut self.items[i];
+        let (left, right) = match &item.content {
+            Content::Text(text) => {
+                let (left, right) = split_utf16(text, at as usize);
+                (Content::Text(left), Content::Text(right))
+            }
+            content => (content.clone(), content.clone()),
+        };
+        let tail = Item {
+            id: item.id.plus(at),
+            origin: Some(item.id.plus(at - 1)),
+            right_origin: item.right_origin,
+            len: item.len - at,
+            content: right,
+        };
+        item.len = at;
+        item.content = left;
+        self.items.insert(i + 1, tail);
+    }
+
+    /// Make `id` the last unit of its item, if it is in the text.
+    fn split_after(&mut self, id: Id) {
+        let Some(i) = self.position(id) else { return };
+        let at = id.seq - self.items[i].id.seq + 1;
+        if at < self.items[i].len {
+            self.split(i, at);
+        }
+    }
+
+    /// Make `id` the first unit of its item, if it is in the text.
+    fn split_before(&mut self, id: Id) {
+        let Some(i) = self.position(id) else { return };
+        let at = id.seq - self.items[i].id.seq;
+        if at > 0 {
+            self.split(i, at);
+        }
+    }
+
+    /// Visible UTF-16 offset where item `i` starts.
+    fn offset_of(&self, i: usize) -> usize {
+        self.items[..i].iter().map(Item::visible).sum()
+    }
+
+    /// Item index and offset into it of the visible unit at `at`; `None`
+    /// past the last one.
+    fn locate(&self, at: usize) -> Option<(usize, usize)> {
+        let mut offset = 0;
+        for (i, item) in self.items.iter().enumerate() {
+            let len = item.visible();
+            if at < offset + len {
+                return Some((i, at - offset));
+            }
+            offset += len;
+        }
+        None
+    }
+
+    fn can_integrate(&self, incoming: &Incoming) -> bool {
+        let id = incoming.id();
+        id.seq <= self.next_seq(id.agent)
+            && match incoming {
+                Incoming::Item { item, .. } => {
+                    item.origin.is_none_or(|id| self.has(id)) && item.right_origin.is_none_or(|id| self.has(id))
+                }
+                Incoming::Other(_) => true,
+            }
+    }
+
+    /// Apply what the buffer allows, repeating while structs land, since
+    /// each may unblock others.
+    fn flush_pending(&mut self, ops: &mut Vec<EditOp>) {
+        loop {
+            let (ready, waiting): (Vec<Incoming>, Vec<Incoming>) =
+                std::mem::take(&mut self.pending).into_iter().partition(|incoming| self.can_integrate(incoming));
+            self.pending = waiting;
+            if ready.is_empty() {
+                break;
+            }
+            for incoming in ready {
+                match incoming {
+                    Incoming::Item { item, in_text, raw } => self.integrate(item, in_text, raw, ops),
+                    Incoming::Other(other) => self.integrate_other(other),
+                }
+            }
+        }
+        for delete in std::mem::take(&mut self.pending_deletes) {
+            if let Some(rest) = self.apply_delete(delete, ops) {
+                self.pending_deletes.push(rest);
+            }
+        }
+    }
+
+    /// YATA integration (as in Yjs's `Item.integrate`) of a remote item
+    /// whose dependencies are all present.
+    fn integrate(&mut self, item: Item, in_text: bool, raw: Option<Vec<u8>>, ops: &mut Vec<EditOp>) {
+        let have = self.next_seq(item.id.agent);
+        if item.end() <= have {
+            return;
+        }
+        let skip = have.saturating_sub(item.id.seq);
+        let item = if skip > 0 { item.skip(skip) } else { item };
+
+        // Like Yjs, an item next to a garbage-collected struct is collected
+        // too, and one goes where its origin (else its right origin) is: an
+        // item next to another type's struct belongs to that type.
+        let gc = [item.origin, item.right_origin].into_iter().flatten().any(|id| self.other(id).is_some_and(|o| o.gc));
+        let outside = match item.origin.or(item.right_origin) {
+            Some(id) => self.position(id).is_none(),
+            None => !in_text,
+        };
+        if gc || outside {
+            let raw = raw.filter(|_| skip == 0 && !gc);
+            self.integrate_other(Other { id: item.id, len: item.len, gc, raw });
+            return;
+        }
+
+        // Only a malformed update has a right origin outside the text of
+        // the origin; as in Yjs, it then bounds nothing.
+        let right_origin = item.right_origin.filter(|id| self.position(*id).is_some());
+        if let Some(id) = item.origin {
+            self.split_after(id);
+        }
+        if let Some(id) = right_origin {
+            self.split_before(id);
+        }
+        let mut left = item.origin.and_then(|id| self.position(id));
+        let right = right_origin.and_then(|id| self.position(id)).unwrap_or(self.items.len());
+
+        // Walk the items between the origins and settle where concurrent
+        // inserts at the same spot go: ties on the same origin are broken
+        // by agent, and an item whose origin lies inside the conflicting
+        // run stays with that run.
+        let mut conflicting = HashSet::new();
+        let mut before_origin = HashSet::new();
+        let mut o = left.map_or(0, |i| i + 1);
+        while o < right {
+            let other = &self.items[o];
+            before_origin.insert(other.id);
+            conflicting.insert(other.id);
+            if other.origin == item.origin {
+                if other.id.agent < item.id.agent {
+                    left = Some(o);
+                    conflicting.clear();
+                } else if other.right_origin == item.right_origin {
+                    break;
+                }
+            } else if let Some(origin) = other.origin.and_then(|id| self.position(id)).map(|i| self.items[i].id) {
+                if !before_origin.contains(&origin) {
+                    break;
+                }
+                if !conflicting.contains(&origin) {
+                    left = Some(o);
+                    conflicting.clear();
+                }
+            } else {
+                break;
+            }
+            o += 1;
+        }
+
+        let at = left.map_or(0, |i| i + 1);
+        if item.visible() > 0 {
+            ops.push(EditOp::Ins { at: self.offset_of(at), text: item.text().to_string() });
+        }
+        self.clock.insert(item.id.agent, item.end());
+        self.items.insert(at, item);
+    }
+
+    fn integrate_other(&mut self, other: Other) {
+        let have = self.next_seq(other.id.agent);
+        let end = other.id.seq + other.len;
+        if end <= have {
+            return;
+        }
+        let from = other.id.seq.max(have);
+        let raw = other.raw.filter(|_| from == other.id.seq);
+        self.clock.insert(other.id.agent, end);
+        match self.others.last_mut() {
+            Some(last)
+                if last.id.agent == other.id.agent
+                    && last.id.seq + last.len == from
+                    && last.gc == other.gc
+                    && last.raw.is_none()
+                    && raw.is_none() =>
+            {
+                last.len += end - from;
+            }
+            _ => self.others.push(Other { id: Id { agent: other.id.agent, seq: from }, len: end - from, gc: other.gc, raw }),
+        }
+    }
+
+    /// Delete the units of `delete` that are known; returns the rest.
+    fn apply_delete(&mut self, delete: Delete, ops: &mut Vec<EditOp>) -> Option<Delete> {
+        let end = delete.id.seq + delete.len;
+        let known_end = end.min(self.next_seq(delete.id.agent));
+        let mut seq = delete.id.seq;
+        while seq < known_end {
+            let id = Id { agent: delete.id.agent, seq };
+            if self.position(id).is_none() {
+                // Outside this text: remembered for `delete_set`.
+                let Some(other) = self.other(id) else { break };
+                let (gc, to) = (other.gc, (other.id.seq + other.len).min(known_end));
+                if !gc {
+                    mark_deleted(&mut self.deleted_others, Delete { id, len: to - seq });
+                }
+                seq = to;
+                continue;
+            }
+            self.split_before(id);
+            let Some(i) = self.position(id) else { break };
+            if known_end < self.items[i].end() {
+                self.split(i, known_end - seq);
+            }
+            let at = self.offset_of(i);
+            let item = &mut self.items[i];
+            let len = item.visible();
+            if len > 0 {
+                ops.push(EditOp::Del { at, len });
+            }
+            item.content = Content::Deleted;
+            seq += item.len;
+        }
+        let from = known_end.max(delete.id.seq);
+        (from < end).then_some(Delete { id: Id { agent: delete.id.agent, seq: from }, len: end - from })
+    }
+
+    fn local_insert(&mut self, at: usize, text: &str) -> TextRange {
+        let start = self.floor_boundary(at);
+        if text.is_empty() {
+            return TextRange { start, end: start };
+        }
+        // Insert after the unit before `start`, ahead of whatever follows it.
+        let (at, origin) = match start.checked_sub(1).and_then(|prev| self.locate(prev)) {
+            Some((i, inner)) => {
+                let id = self.items[i].id.plus(inner as u64);
+                self.split_after(id);
+                (i + 1, Some(id))
+            }
+            None => (0, None),
+        };
+        let right_origin = self.items.get(at).map(|item| item.id);
+        let id = Id { agent: self.agent, seq: self.next_seq(self.agent) };
+        let len = utf16_len(text) as u64;
+        let item = Item { id, origin, right_origin, len, content: Content::Text(text.to_string()) };
+
+        let extends = |prev: &Item| {
+            matches!(prev.content, Content::Text(_))
+                && prev.id.agent == id.agent
+                && prev.end() == id.seq
+                && prev.right_origin == right_origin
+        };
+        match at.checked_sub(1).map(|i| &mut self.items[i]) {
+            Some(prev) if extends(prev) => {
+                if let Content::Text(prev_text) = &mut prev.content {
+                    prev_text.push_str(text);
+                }
+                prev.len += len;
+            }
+            _ => self.items.insert(at, item.clone()),
+        }
+        self.clock.insert(id.agent, id.seq + len);
+
+        match self.local_items.last_mut() {
+            Some(last) if last.end() == id.seq && origin == Some(last.id.plus(last.len - 1)) && last.right_origin == right_origin => {
+                if let Content::Text(last_text) = &mut last.content {
+                    last_text.push_str(text);
+                }
+                last.len += len;
+            }
+            _ => self.local_items.push(item),
+        }
+        TextRange { start, end: start + len as usize }
+    }
+
+    fn local_delete(&mut self, start: usize, end: usize) -> TextRange {
+        let (start, end) = (self.floor_boundary(start), self.floor_boundary(end));
+        let mut targets = Vec::new();
+        let mut offset = 0;
+        for item in &self.items {
+            let len = item.visible();
+            let (from, to) = (start.max(offset), end.min(offset + len));
+            if from < to {
+                let id = item.id.plus((from - offset) as u64);
+                targets.push(Delete { id, len: (to - from) as u64 });
+            }
+            offset += len;
+            if offset >= end {
+                break;
+            }
+        }
+        for delete in targets {
+            self.apply_delete(delete, &mut Vec::new());
+            push_delete(&mut self.local_deletes, delete);
+        }
+        TextRange { start, end }
+    }
+
+    /// `at` moved back out of a surrogate pair.
+    fn floor_boundary(&self, at: usize) -> usize {
+        match self.locate(at) {
+            Some((i, inner)) if lands_in_pair(self.items[i].text(), inner) => at - 1,
+            _ => at,
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub struct CrdtText {
+    replica: Replica,
+}
+
+#[wasm_bindgen]
+impl CrdtText {
+    /// An empty replica. `agent` must be unique among the replicas that
+    /// will ever exchange updates (a random `u32` will do, as in Yjs).
+    #[wasm_bindgen(constructor)]
+    pub fn new(agent: u32) -> CrdtText {
+        CrdtText { replica: Replica::new(u64::from(agent)) }
+    }
+
+    #[wasm_bindgen(js_name = agent)]
+    pub fn agent(&self) -> u32 {
+        self.replica.agent() as u32
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn text(&self) -> String {
+        self.replica.text()
+    }
+
+    #[wasm_bindgen(js_name = lenUtf16)]
+    pub fn len_utf16(&self) -> usize {
+        self.replica.len_utf16()
+    }
+
+    /// Insert at a UTF-16 offset as this replica. Returns the `{ start,
+    /// end }` the text landed at: an offset inside a surrogate pair moves
+    /// back before it.
+    #[wasm_bindgen(js_name = insert)]
+    pub fn insert_js(&mut self, at: usize, text: &str) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.insert(at, text)?)?)
+    }
+
+    /// Delete `len` UTF-16 units at `at` as this replica. Returns the
+    /// `{ start, end }` actually deleted: a surrogate pair goes whole or
+    /// not at all, with ends inside one moved back before it.
+    #[wasm_bindgen(js_name = delete)]
+    pub fn delete_js(&mut self, at: usize, len: usize) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.delete(at, len)?)?)
+    }
+
+    /// Apply local `EditOp[]` (e.g. `ChangeRecord.ops` from the mirrored
+    /// `CoreText`). Checked up front like `CoreText.applyOps`.
+    #[wasm_bindgen(js_name = applyOps)]
+    pub fn apply_ops_js(&mut self, ops: JsValue) -> Result<(), JsValue> {
+        let ops: Vec<EditOp> = serde_wasm_bindgen::from_value(ops)?;
+        Ok(self.apply_ops(&ops)?)
+    }
+
+    /// Local ops since the last call as a binary update, or an empty array
+    /// when there are none.
+    #[wasm_bindgen(js_name = takeUpdate)]
+    pub fn take_update(&mut self) -> Vec<u8> {
+        let (items, deletes) = self.replica.take_local();
+        if items.is_empty() && deletes.is_empty() {
+            return Vec::new();
+        }
+        encode_update(&items, &deletes)
+    }
+
+    /// Merge a peer's update. Returns the `EditOp[]` the visible text went
+    /// through, in order.
+    #[wasm_bindgen(js_name = applyUpdate)]
+    pub fn apply_update_js(&mut self, update: &[u8]) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.apply_update(update)?)?)
+    }
+
+    /// Next expected seq per agent, encoded for `encodeStateAsUpdate`.
+    #[wasm_bindgen(js_name = stateVector)]
+    pub fn state_vector(&self) -> Vec<u8> {
+        self.replica.state_vector()
+    }
+
+    /// Everything a replica at `state_vector` is missing (everything, if
+    /// omitted), plus all deletes, as one update.
+    #[wasm_bindgen(js_name = encodeStateAsUpdate)]
+    pub fn encode_state_as_update(&self, state_vector: Option<Vec<u8>>) -> Result<Vec<u8>, CoreError> {
+        let known = match state_vector {
+            Some(bytes) => decode_state_vector(&bytes)?,
+            None => HashMap::new(),
+        };
+        let mut items = Vec::new();
+        for stored in self.replica.structs() {
+            // A `CrdtText` only ever holds items.
+            let Stored::Item(item) = stored else { continue };
+            let have = known.get(&item.id.agent).copied().unwrap_or(0);
+            if item.end() > have {
+                let skip = have.saturating_sub(item.id.seq);
+                items.push(if skip > 0 { item.clone().skip(skip) } else { item.clone() });
+            }
+        }
+        Ok(encode_update(&items, &self.replica.delete_set()))
+    }
+
+    /// Ops received but still waiting for ops they depend on.
+    #[wasm_bindgen(js_name = pendingCount)]
+    pub fn pending_count(&self) -> usize {
+        self.replica.pending_count()
+    }
+}
+
+impl CrdtText {
+    pub fn insert(&mut self, at: usize, text: &str) -> Result<TextRange, CoreError> {
+        self.replica.insert(at, text)
+    }
+
+    pub fn delete(&mut self, at: usize, len: usize) -> Result<TextRange, CoreError> {
+        self.replica.delete(at, len)
+    }
+
+    pub fn apply_ops(&mut self, ops: &[EditOp]) -> Result<(), CoreError> {
+        self.replica.apply_ops(ops)
+    }
+
+    /// An empty update (what `takeUpdate` returns when there is nothing to
+    /// send) changes nothing.
+    pub fn apply_update(&mut self, update: &[u8]) -> Result<Vec<EditOp>, CoreError> {
+        if update.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (items, deletes) = decode_update(update)?;
+        let structs = items.into_iter().map(|item| Incoming::Item { item, in_text: true, raw: None }).collect();
+        Ok(self.replica.receive(structs, deletes))
+    }
+}
+
+/// Append to a delete list, merging with the last range when contiguous
+/// or overlapping.
+pub(crate) fn push_delete(deletes: &mut Vec<Delete>, delete: Delete) {
+    match deletes.last_mut() {
+        Some(last)
+            if last.id.agent == delete.id.agent
+                && last.id.seq <= delete.id.seq
+                && delete.id.seq <= last.id.seq + last.len =>
+        {
+            last.len = last.len.max(delete.id.seq + delete.len - last.id.seq);
+        }
+        _ => deletes.push(delete),
+    }
+}
+
+/// Add a range to a sorted, merged delete list.
+fn mark_deleted(deletes: &mut Vec<Delete>, delete: Delete) {
+    let at = deletes.partition_point(|d| d.id < delete.id);
+    deletes.insert(at, delete);
+    let mut merged = Vec::with_capacity(deletes.len());
+    for delete in deletes.drain(..) {
+        push_delete(&mut merged, delete);
+    }
+    *deletes = merged;
+}
+
+/// Split `text` after `at` UTF-16 units. A split inside a surrogate pair
+/// turns each half into U+FFFD, which keeps both lengths right (Yjs does
+/// the same).
+fn split_utf16(text: &str, at: usize) -> (String, String) {
+    let mut units = 0;
+    for (byte, c) in text.char_indices() {
+        if units == at {
+            return (text[..byte].to_string(), text[byte..].to_string());
+        }
+        units += c.len_utf16();
+        if units > at {
+            let next = byte + c.len_utf8();
+            return (format!("{}\u{FFFD}", &text[..byte]), format!("\u{FFFD}{}", &text[next..]));
+        }
+    }
+    (text.to_string(), String::new())
+}
+
+/// Whether UTF-16 offset `at` falls between the halves of a surrogate pair.
+fn lands_in_pair(text: &str, at: usize) -> bool {
+    let mut units = 0;
+    for c in text.chars() {
+        if units >= at {
+            break;
+        }
+        units += c.len_utf16();
+    }
+    units > at
+}
+
+fn encode_update(items: &[Item], deletes: &[Delete]) -> Vec<u8> {
+    let mut enc = Encoder::new();
+    enc.var_uint(items.len() as u64);
+    for item in items {
+        enc.var_uint(item.id.agent);
+        enc.var_uint(item.id.seq);
+        let text = match &item.content {
+            Content::Text(text) => Some(text),
+            _ => None,
+        };
+        let mut flags = 0;
+        if item.origin.is_some() {
+            flags |= HAS_ORIGIN;
+        }
+        if item.right_origin.is_some() {
+            flags |= HAS_RIGHT_ORIGIN;
+        }
+        if text.is_none() {
+            flags |= DELETED;
+        }
+        enc.u8(flags);
+        for id in [item.origin, item.right_origin].into_iter().flatten() {
+            enc.var_uint(id.agent);
+            enc.var_uint(id.seq);
+        }
+        match text {
+            Some(text) => enc.string(text),
+            None => enc.var_uint(item.len),
+        }
+    }
+    enc.var_uint(deletes.len() as u64);
+    for delete in deletes {
+        enc.var_uint(delete.id.agent);
+        enc.var_uint(delete.id.seq);
+        enc.var_uint(delete.len);
+    }
+    enc.finish()
+}
+
+fn decode_update(update: &[u8]) -> Result<(Vec<Item>, Vec<Delete>), CoreError> {
+    let mut dec = Decoder::new(update);
+    let mut items = Vec::new();
+    for _ in 0..dec.var_uint()? {
+        let start = dec.offset();
+        let id = decode_id(&mut dec)?;
+        let flags = dec.u8()?;
+        let origin = if flags & HAS_ORIGIN != 0 { Some(decode_id(&mut dec)?) } else { None };
+        let right_origin = if flags & HAS_RIGHT_ORIGIN != 0 { Some(decode_id(&mut dec)?) } else { None };
+        let (len, content) = match flags & DELETED != 0 {
+            true => (dec.var_uint()?, Content::Deleted),
+            false => {
+                let text = dec.string()?;
+                (utf16_len(text) as u64, Content::Text(text.to_string()))
+            }
+        };
+        if flags > 7 || len == 0 || id.seq.checked_add(len).is_none() {
+            return Err(CoreError::InvalidUpdate { offset: start });
+        }
+        items.push(Item { id, origin, right_origin, len, content });
+    }
+    let deletes = decode_deletes(&mut dec)?;
+    if !dec.is_done() {
+        return Err(CoreError::InvalidUpdate { offset: dec.offset() });
+    }
+    Ok((items, deletes))
+}
+
+pub(crate) fn decode_id(dec: &mut Decoder) -> Result<Id, CoreError> {
+    Ok(Id { agent: dec.var_uint()?, seq: dec.var_uint()? })
+}
+
+fn decode_deletes(dec: &mut Decoder) -> Result<Vec<Delete>, CoreError> {
+    let mut deletes = Vec::new();
+    for _ in 0..dec.var_uint()? {
+        let start = dec.offset();
+        let delete = Delete { id: decode_id(dec)?, len: dec.var_uint()? };
+        if delete.id.seq.checked_add(delete.len).is_none() {
+            return Err(CoreError::InvalidUpdate { offset: start });
+        }
+        deletes.push(delete);
+    }
+    Ok(deletes)
+}
+
+pub(crate) fn decode_state_vector(bytes: &[u8]) -> Result<HashMap<u64, u64>, CoreError> {
+    let mut dec = Decoder::new(bytes);
+    let mut clock = HashMap::new();
+    for _ in 0..dec.var_uint()? {
+        clock.insert(dec.var_uint()?, dec.var_uint()?);
+    }
+    if !dec.is_done() {
+        return Err(CoreError::InvalidUpdate { offset: dec.offset() });
+    }
+    Ok(clock)
+}
@@ -0,0 +1,236 @@
+//! Decoration store — the interval tree the performance doc plans for
+//! decorations, replacing `EditorModel`'s per-block `Map`.
+//!
+//! Decorations live in one `Vec` sorted by start, read as an implicit
+//! balanced tree (each subrange's root is its midpoint) where every node
+//! also knows the largest end in its subtree. Queries prune on both, so
+//! they cost O(log n + hits). Edits remap every range; mapping never
+//! reorders starts, so only the max-end column goes stale and it is
+//! rebuilt on the next query.
+//!
+//! Everything but the query is O(n) in the number of decorations: adding
+//! and removing shift the `Vec` (and find ids by scanning it), every edit
+//! remaps every range, and the first query after either rebuilds `max_end`.
+//! Those are flat passes over one array, cheap at the few thousand
+//! decorations a document carries; `tests/decorations.rs` holds typing
+//! to the keystroke budget with 12k of them. A tree with relative offsets
+//! and an id index would make them O(log n) if that stops being enough.
+//!
+//! Ranges are half-open UTF-16 offsets. Text typed at either edge lands
+//! outside the decoration; a decoration whose text is deleted entirely is
+//! dropped. An empty range (`start == end`) is a point decoration.
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::ops::Splice;
+use crate::{CoreError, CoreText, Unit};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Decoration {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub start: usize,
+    pub end: usize,
+    /// Opaque to the core (JS typically stores JSON here).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+}
+
+impl Decoration {
+    fn hits(&self, start: usize, end: usize) -> bool {
+        if self.start == self.end {
+            return (start <= self.start && self.start < end) || (start == end && self.start == start);
+        }
+        if start == end {
+            return self.start <= start && start < self.end;
+        }
+        self.start < end && start < self.end
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Decorations {
+    list: Vec<Decoration>,
+    /// Largest `end` in the implicit subtree rooted at each index.
+    max_end: Vec<usize>,
+    stale: bool,
+}
+
+impl Decorations {
+    pub(crate) fn apply(&mut self, edit: &Splice) {
+        if self.list.is_empty() {
+            return;
+        }
+        let (start, old_end, new_end) = (edit.start, edit.start + edit.removed, edit.start + edit.inserted);
+        let map = |p: usize, after: bool| {
+            if p < start || (p == start && !after) {
+                p
+            } else if p > old_end || (p == old_end && after) {
+                p - edit.removed + edit.inserted
+            } else if after {
+                new_end
+            } else {
+                start
+            }
+        };
+        self.list.retain_mut(|d| {
+            let was_empty = d.start == d.end;
+            if was_empty {
+                d.start = map(d.start, true);
+                d.end = d.start;
+                return true;
+            }
+            d.start = map(d.start, true);
+            d.end = map(d.end, false).max(d.start);
+            d.start < d.end
+        });
+        self.stale = true;
+    }
+
+    fn insert(&mut self, decoration: Decoration) {
+        self.remove(&decoration.id);
+        let at = self.list.partition_point(|d| d.start <= decoration.start);
+        self.list.insert(at, decoration);
+        self.stale = true;
+    }
+
+    fn remove(&mut self, id: &str) -> bool {
+        match self.list.iter().position(|d| d.id == id) {
+            Some(i) => {
+                self.list.remove(i);
+                self.stale = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove_kind(&mut self, kind: &str) -> usize {
+        let before = self.list.len();
+        self.list.retain(|d| d.kind != kind);
+        self.stale = true;
+        before - self.list.len()
+    }
+
+    fn rebuild(&mut self) {
+        self.max_end = self.list.iter().map(|d| d.end).collect();
+        fn fill(max_end: &mut [usize], lo: usize, hi: usize) -> usize {
+            if lo >= hi {
+                return 0;
+            }
+            let mid = lo + (hi - lo) / 2;
+            let left = fill(max_end, lo, mid);
+            let right = fill(max_end, mid + 1, hi);
+            max_end[mid] = max_end[mid].max(left).max(right);
+            max_end[mid]
+        }
+        let n = self.list.len();
+        fill(&mut self.max_end, 0, n);
+        self.stale = false;
+    }
+
+    fn query(&mut self, start: usize, end: usize) -> Vec<Decoration> {
+        if self.stale {
+            self.rebuild();
+        }
+        let mut out = Vec::new();
+        self.collect(0, self.list.len(), start, end, &mut out);
+        out
+    }
+
+    fn collect(&self, lo: usize, hi: usize, start: usize, end: usize, out: &mut Vec<Decoration>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_end[mid] < start {
+            return;
+        }
+        self.collect(lo, mid, start, end, out);
+        let d = &self.list[mid];
+        if d.start > end {
+            return;
+        }
+        if d.hits(start, end) {
+            out.push(d.clone());
+        }
+        self.collect(mid + 1, hi, start, end, out);
+    }
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Add `{ id, type, start, end, payload? }`, replacing any decoration
+    /// with the same id.
+    #[wasm_bindgen(js_name = addDecoration)]
+    pub fn add_decoration_js(&mut self, decoration: JsValue) -> Result<(), JsValue> {
+        let decoration: Decoration = serde_wasm_bindgen::from_value(decoration)?;
+        Ok(self.add_decoration(decoration)?)
+    }
+
+    #[wasm_bindgen(js_name = removeDecoration)]
+    pub fn remove_decoration(&mut self, id: &str) -> bool {
+        self.decorations.remove(id)
+    }
+
+    /// Same as `EditorModel.clearDecorationsByType`; returns how many went.
+    #[wasm_bindgen(js_name = clearDecorationsByType)]
+    pub fn clear_decorations_by_type(&mut self, kind: &str) -> usize {
+        self.decorations.remove_kind(kind)
+    }
+
+    #[wasm_bindgen(js_name = clearDecorations)]
+    pub fn clear_decorations(&mut self) {
+        self.decorations = Default::default();
+    }
+
+    /// Decorations overlapping `[start, end)`, by start offset.
+    #[wasm_bindgen(js_name = decorationsInRange)]
+    pub fn decorations_in_range_js(&mut self, start: usize, end: usize) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.decorations_in_range(start, end)?)?)
+    }
+
+    /// Decorations covering the code unit at `offset`, plus point
+    /// decorations sitting there.
+    #[wasm_bindgen(js_name = decorationsAt)]
+    pub fn decorations_at_js(&mut self, offset: usize) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.decorations_at(offset)?)?)
+    }
+
+    /// Decorations on lines `first..=last`, for the rendered viewport.
+    #[wasm_bindgen(js_name = decorationsInLines)]
+    pub fn decorations_in_lines_js(&mut self, first: usize, last: usize) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.decorations_in_lines(first, last)?)?)
+    }
+}
+
+impl CoreText {
+    pub fn add_decoration(&mut self, mut decoration: Decoration) -> Result<(), CoreError> {
+        let len = self.rope.len_utf16();
+        (decoration.start, decoration.end) = self.check_range(Unit::Utf16, decoration.start, decoration.end, len)?;
+        self.decorations.insert(decoration);
+        Ok(())
+    }
+
+    pub fn decorations_in_range(&mut self, start: usize, end: usize) -> Result<Vec<Decoration>, CoreError> {
+        let (start, end) = self.check_range(Unit::Utf16, start, end, self.rope.len_utf16())?;
+        Ok(self.decorations.query(start, end))
+    }
+
+    pub fn decorations_at(&mut self, offset: usize) -> Result<Vec<Decoration>, CoreError> {
+        let offset = self.check_index(Unit::Utf16, offset, self.rope.len_utf16())?;
+        Ok(self.decorations.query(offset, offset))
+    }
+
+    pub fn decorations_in_lines(&mut self, first: usize, last: usize) -> Result<Vec<Decoration>, CoreError> {
+        let start = self.line_start(first)?;
+        let end = self.line_end(last)?;
+        if start > end {
+            return Err(CoreError::InvertedRange { start: first, end: last });
+        }
+        // Include point decorations at the very end of the last line.
+        Ok(self.decorations.query(start, end + 1).into_iter().filter(|d| d.start <= end).collect())
+    }
+}
@@ -0,0 +1,214 @@
+//! Text diffing — replaces the single-hunk `computeDiff` in `model.ts`.
+//!
+//! Myers' O(ND) algorithm over chars or whole lines, after trimming the
+//! common prefix and suffix. The output is a minimal `EditOp` list in UTF-16
+//! offsets that turns the old text into the new one when applied in order,
+//! so it can go straight into `applyOps` or a `Y.Text`. If the search runs
+//! past its time budget, or past `MAX_EDIT_DISTANCE` edits, the middle is
+//! replaced wholesale instead, which is what the TS diff always did.
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::clock::now_ms;
+use crate::ops::{utf16_len, EditOp};
+use crate::CoreText;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Char,
+    Line,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiffOptions {
+    pub granularity: Granularity,
+    /// Budget for the Myers search; past it (or past `MAX_EDIT_DISTANCE`)
+    /// the diff falls back to one delete + insert over the differing middle.
+    pub timeout_ms: f64,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions { granularity: Granularity::Char, timeout_ms: 16.0 }
+    }
+}
+
+/// Ops turning `old` into `new`.
+pub fn diff(old: &str, new: &str, opts: &DiffOptions) -> Vec<EditOp> {
+    match opts.granularity {
+        Granularity::Char => {
+            let a: Vec<&str> = split_chars(old).collect();
+            let b: Vec<&str> = split_chars(new).collect();
+            diff_tokens(&a, &b, opts.timeout_ms)
+        }
+        Granularity::Line => {
+            let a: Vec<&str> = old.split_inclusive('\n').collect();
+            let b: Vec<&str> = new.split_inclusive('\n').collect();
+            diff_tokens(&a, &b, opts.timeout_ms)
+        }
+    }
+}
+
+/// Diff two texts from JS. `options` is `{ granularity?: 'char' | 'line',
+/// timeoutMs?: number }`; the result is an `EditOp[]`.
+#[wasm_bindgen(js_name = diffText)]
+pub fn diff_text_js(prev: &str, next: &str, options: JsValue) -> Result<JsValue, JsValue> {
+    let opts = diff_options(options)?;
+    Ok(serde_wasm_bindgen::to_value(&diff(prev, next, &opts))?)
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Ops that turn this document into `next`; see `diffText`.
+    #[wasm_bindgen(js_name = diffTo)]
+    pub fn diff_to_js(&self, next: &str, options: JsValue) -> Result<JsValue, JsValue> {
+        let opts = diff_options(options)?;
+        Ok(serde_wasm_bindgen::to_value(&self.diff_to(next, &opts))?)
+    }
+}
+
+impl CoreText {
+    pub fn diff_to(&self, next: &str, opts: &DiffOptions) -> Vec<EditOp> {
+        diff(&self.rope.slice(0, self.rope.len_chars()), next, opts)
+    }
+}
+
+pub(crate) fn diff_options(options: JsValue) -> Result<DiffOptions, JsValue> {
+    if options.is_undefined() || options.is_null() {
+        return Ok(DiffOptions::default());
+    }
+    Ok(serde_wasm_bindgen::from_value(options)?)
+}
+
+fn split_chars(s: &str) -> impl Iterator<Item = &str> {
+    s.char_indices().map(move |(i, c)| &s[i..i + c.len_utf8()])
+}
+
+/// Longest edit script the Myers search looks for. The backtrack keeps
+/// (D + 1)² frontier cells, so this bounds its memory at about a million
+/// whatever the time budget.
+pub const MAX_EDIT_DISTANCE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Keep,
+    Delete,
+    Insert,
+}
+
+fn diff_tokens(a: &[&str], b: &[&str], timeout_ms: f64) -> Vec<EditOp> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let room = a.len().min(b.len()) - prefix;
+    let suffix = a.iter().rev().zip(b.iter().rev()).take(room).take_while(|(x, y)| x == y).count();
+    let (mid_a, mid_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let steps = myers(mid_a, mid_b, now_ms() + timeout_ms).unwrap_or_else(|| {
+        let mut coarse = vec![Step::Delete; mid_a.len()];
+        coarse.resize(mid_a.len() + mid_b.len(), Step::Insert);
+        coarse
+    });
+    let base: usize = a[..prefix].iter().map(|t| utf16_len(t)).sum();
+    to_ops(base, mid_a, mid_b, &steps)
+}
+
+/// Shortest edit script from `a` to `b`, or `None` once `deadline` passes
+/// or the script would be longer than `MAX_EDIT_DISTANCE`. Keeps one
+/// trimmed copy of the frontier per edit distance for the backtrack, so
+/// memory is O(D²).
+fn myers(a: &[&str], b: &[&str], deadline: f64) -> Option<Vec<Step>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    // Diagonals only reach ±d, and d stops at the cap, so `v` is sized by
+    // that rather than by n + m.
+    let bound = ((n + m) as usize).min(MAX_EDIT_DISTANCE);
+    let offset = bound as isize + 1;
+    let mut v = vec![0isize; 2 * bound + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=bound as isize {
+        if d % 32 == 0 && d > 0 && now_ms() > deadline {
+            return None;
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]) {
+                v[(offset + k + 1) as usize]
+            } else {
+                v[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+    }
+    // Only reached past the cap: a path of length n + m always exists.
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().skip(1).rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            steps.push(Step::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        steps.push(if x == prev_x { Step::Insert } else { Step::Delete });
+        x = prev_x;
+        y = prev_y;
+    }
+    steps.extend(std::iter::repeat_n(Step::Keep, x as usize));
+    steps.reverse();
+    steps
+}
+
+/// Fold an edit script into ops, merging runs of deletes and inserts.
+fn to_ops(base: usize, a: &[&str], b: &[&str], steps: &[Step]) -> Vec<EditOp> {
+    let mut ops: Vec<EditOp> = Vec::new();
+    let (mut pos, mut i, mut j) = (base, 0, 0);
+    let mut prev = Step::Keep;
+    for &step in steps {
+        match (step, prev, ops.last_mut()) {
+            (Step::Keep, ..) => {
+                pos += utf16_len(a[i]);
+                i += 1;
+                j += 1;
+            }
+            (Step::Delete, Step::Delete, Some(EditOp::Del { len, .. })) => {
+                *len += utf16_len(a[i]);
+                i += 1;
+            }
+            (Step::Delete, ..) => {
+                ops.push(EditOp::Del { at: pos, len: utf16_len(a[i]) });
+                i += 1;
+            }
+            (Step::Insert, Step::Insert, Some(EditOp::Ins { text, .. })) => {
+                text.push_str(b[j]);
+                pos += utf16_len(b[j]);
+                j += 1;
+            }
+            (Step::Insert, ..) => {
+                ops.push(EditOp::Ins { at: pos, text: b[j].to_string() });
+                pos += utf16_len(b[j]);
+                j += 1;
+            }
+        }
+        prev = step;
+    }
+    ops
+}
@@ -0,0 +1,109 @@
+//! Variable-length integer encoding for binary updates.
+//!
+//! Unsigned integers are LEB128 (seven bits per byte, low bits first, high
+//! bit set on every byte but the last), the same `varUint` as Yjs's `lib0`.
+//! Strings are a `varUint` byte length followed by UTF-8.
+
+use crate::CoreError;
+
+#[derive(Default)]
+pub(crate) struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Encoder {
+        Encoder::default()
+    }
+
+    pub(crate) fn u8(&mut self, byte: u8) {
+        self.buf.push(byte);
+    }
+
+    pub(crate) fn var_uint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.buf.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.buf.push(n as u8);
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn string(&mut self, s: &str) {
+        self.var_uint(s.len() as u64);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub(crate) struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Decoder<'a> {
+        Decoder { buf, pos: 0 }
+    }
+
+    pub(crate) fn offset(&self) -> usize {
+        self.pos
+    }
+
+    /// Bytes read since `offset`.
+    pub(crate) fn since(&self, offset: usize) -> &'a [u8] {
+        &self.buf[offset..self.pos]
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    fn error(&self) -> CoreError {
+        CoreError::InvalidUpdate { offset: self.pos }
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, CoreError> {
+        let byte = *self.buf.get(self.pos).ok_or_else(|| self.error())?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    pub(crate) fn var_uint(&mut self) -> Result<u64, CoreError> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(n);
+            }
+        }
+        Err(self.error())
+    }
+
+    /// A `var_uint` that must fit in `usize`, e.g. a length.
+    pub(crate) fn len(&mut self) -> Result<usize, CoreError> {
+        let n = self.var_uint()?;
+        usize::try_from(n).map_err(|_| self.error())
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], CoreError> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.buf.len()).ok_or_else(|| self.error())?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn string(&mut self) -> Result<&'a str, CoreError> {
+        let start = self.pos;
+        let len = self.len()?;
+        let bytes = self.bytes(len)?;
+        std::str::from_utf8(bytes).map_err(|_| CoreError::InvalidUpdate { offset: start })
+    }
+}
@@ -0,0 +1,145 @@
+//! Structured errors for out-of-range edits and queries.
+//!
+//! Ropey panics on a bad index, which in WASM takes the whole module
+//! instance down; the String fallback used to clamp silently instead.
+//! `CoreText` now checks every index up front and returns a `CoreError`
+//! from either backend. Across the JS boundary it becomes a thrown `Error`
+//! with `name = "CoreTextError"`, a machine-readable `code` and the
+//! offending indices as properties.
+
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+/// Unit an index was expressed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Char,
+    Byte,
+    Utf16,
+    Line,
+}
+
+impl Unit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unit::Char => "char",
+            Unit::Byte => "byte",
+            Unit::Utf16 => "utf16",
+            Unit::Line => "line",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CoreError {
+    /// `index` lies past `len`, the largest valid index in `unit`.
+    OutOfBounds { unit: Unit, index: usize, len: usize },
+    /// A range whose start comes after its end.
+    InvertedRange { start: usize, end: usize },
+    /// Op `op` of an `applyOps` batch failed; nothing was applied.
+    InvalidOp { op: usize, error: Box<CoreError> },
+    /// `commit` or `rollback` without an open transaction.
+    NoTransaction,
+    /// A search pattern that does not compile; `message` says why.
+    InvalidPattern { message: String },
+    /// A binary update that is cut short or malformed at byte `offset`.
+    InvalidUpdate { offset: usize },
+    /// An op log version older than its base or newer than its head.
+    UnknownVersion { version: u64, oldest: u64, latest: u64 },
+}
+
+impl CoreError {
+    /// Stable code exposed to JS as `error.code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CoreError::OutOfBounds { .. } => "OUT_OF_BOUNDS",
+            CoreError::InvertedRange { .. } => "INVERTED_RANGE",
+            CoreError::InvalidOp { .. } => "INVALID_OP",
+            CoreError::NoTransaction => "NO_TRANSACTION",
+            CoreError::InvalidPattern { .. } => "INVALID_PATTERN",
+            CoreError::InvalidUpdate { .. } => "INVALID_UPDATE",
+            CoreError::UnknownVersion { .. } => "UNKNOWN_VERSION",
+        }
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoreError::OutOfBounds { unit, index, len } => {
+                write!(f, "{} index {index} out of bounds (len {len})", unit.as_str())
+            }
+            CoreError::InvertedRange { start, end } => {
+                write!(f, "range start {start} is after end {end}")
+            }
+            CoreError::InvalidOp { op, error } => write!(f, "op {op}: {error}"),
+            CoreError::NoTransaction => write!(f, "no open transaction"),
+            CoreError::InvalidPattern { message } => write!(f, "invalid pattern: {message}"),
+            CoreError::InvalidUpdate { offset } => write!(f, "invalid update at byte {offset}"),
+            CoreError::UnknownVersion { version, oldest, latest } => {
+                write!(f, "version {version} is not in the log ({oldest}..={latest})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CoreError {}
+
+/// A property value of the thrown JS `Error`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsProp {
+    Str(&'static str),
+    Num(f64),
+}
+
+impl CoreError {
+    /// The properties set on the thrown JS `Error` besides `name` and
+    /// `message`: `code`, then the offending indices. For `InvalidOp`, the
+    /// failing op's position and `reason` (its error's code) come first,
+    /// and the indices are those of that error.
+    pub fn js_props(&self) -> Vec<(&'static str, JsProp)> {
+        use JsProp::{Num, Str};
+        let mut props = vec![("code", Str(self.code()))];
+        let mut inner = self;
+        if let CoreError::InvalidOp { op, error } = self {
+            props.push(("op", Num(*op as f64)));
+            props.push(("reason", Str(error.code())));
+            inner = error;
+        }
+        match inner {
+            CoreError::OutOfBounds { unit, index, len } => {
+                props.push(("unit", Str(unit.as_str())));
+                props.push(("index", Num(*index as f64)));
+                props.push(("len", Num(*len as f64)));
+            }
+            CoreError::InvertedRange { start, end } => {
+                props.push(("start", Num(*start as f64)));
+                props.push(("end", Num(*end as f64)));
+            }
+            CoreError::InvalidUpdate { offset } => props.push(("offset", Num(*offset as f64))),
+            CoreError::UnknownVersion { version, oldest, latest } => {
+                props.push(("version", Num(*version as f64)));
+                props.push(("oldest", Num(*oldest as f64)));
+                props.push(("latest", Num(*latest as f64)));
+            }
+            CoreError::InvalidOp { .. } | CoreError::NoTransaction | CoreError::InvalidPattern { .. } => {}
+        }
+        props
+    }
+}
+
+impl From<CoreError> for JsValue {
+    fn from(err: CoreError) -> JsValue {
+        let js = js_sys::Error::new(&err.to_string());
+        js.set_name("CoreTextError");
+        for (key, value) in err.js_props() {
+            let value = match value {
+                JsProp::Str(s) => JsValue::from_str(s),
+                JsProp::Num(n) => JsValue::from_f64(n),
+            };
+            let _ = js_sys::Reflect::set(&js, &JsValue::from_str(key), &value);
+        }
+        js.into()
+    }
+}
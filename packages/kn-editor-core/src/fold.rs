@@ -0,0 +1,106 @@
+//! Normalized text for accent- and case-insensitive matching.
+//!
+//! Each char is decomposed (NFD), fully case-folded (so `ß` and `ẞ` become
+//! `ss`) and decomposed again, and nonspacing marks (`\p{Mn}`) are dropped:
+//! `Résumé` folds to `resume`, `STRASSE` and `straße` both to `strasse`.
+//! Folding one char never looks at its neighbours, so the folded text
+//! keeps a map back to the original: a sorted list of segments, one per
+//! run of chars that fold to a single char of the same UTF-8 length (all
+//! of plain ASCII is one run), and one per char that changes length.
+
+use std::sync::OnceLock;
+
+use caseless::Caseless;
+use regex::Regex;
+use unicode_normalization::char::decompose_canonical;
+
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    folded: usize,
+    orig: usize,
+    /// `None` for a run that maps byte for byte, else the lengths of the
+    /// one char it covers, folded and original.
+    lens: Option<(usize, usize)>,
+}
+
+/// `text` folded, with byte offsets mapped both ways.
+#[derive(Clone, Debug)]
+pub(crate) struct Folded {
+    pub(crate) text: String,
+    segments: Vec<Segment>,
+}
+
+impl Folded {
+    pub(crate) fn new(orig: &str) -> Folded {
+        let mut text = String::with_capacity(orig.len());
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut buf = String::new();
+        for (at, c) in orig.char_indices() {
+            buf.clear();
+            fold_char(c, &mut buf);
+            let same = buf.len() == c.len_utf8() && buf.chars().count() == 1;
+            let in_run = segments.last().is_some_and(|s| s.lens.is_none());
+            if !same || !in_run {
+                let lens = (!same).then_some((buf.len(), c.len_utf8()));
+                segments.push(Segment { folded: text.len(), orig: at, lens });
+            }
+            text.push_str(&buf);
+        }
+        segments.push(Segment { folded: text.len(), orig: orig.len(), lens: None });
+        Folded { text, segments }
+    }
+
+    /// Original byte for a folded one. Inside the folding of a single char,
+    /// `ceil` picks the end of that char, else its start. A position shared
+    /// by dropped marks and the char after them maps past the marks.
+    pub(crate) fn to_orig(&self, folded: usize, ceil: bool) -> usize {
+        let i = self.segments.partition_point(|s| s.folded <= folded) - 1;
+        let s = self.segments[i];
+        match s.lens {
+            None => s.orig + (folded - s.folded),
+            Some(_) if folded == s.folded => s.orig,
+            Some((_, orig_len)) => s.orig + if ceil { orig_len } else { 0 },
+        }
+    }
+
+    /// Folded byte for an original char boundary.
+    pub(crate) fn to_folded(&self, orig: usize) -> usize {
+        let i = self.segments.partition_point(|s| s.orig <= orig) - 1;
+        let s = self.segments[i];
+        match s.lens {
+            None => s.folded + (orig - s.orig),
+            Some(_) => s.folded,
+        }
+    }
+}
+
+/// `s` folded, without the offset map.
+pub(crate) fn fold_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        fold_char(c, &mut out);
+    }
+    out
+}
+
+fn fold_char(c: char, out: &mut String) {
+    if c.is_ascii() {
+        out.push(c.to_ascii_lowercase());
+        return;
+    }
+    let mut nfd = Vec::new();
+    decompose_canonical(c, |d| nfd.push(d));
+    for folded in nfd.into_iter().default_case_fold() {
+        decompose_canonical(folded, |d| {
+            if !is_nonspacing_mark(d) {
+                out.push(d);
+            }
+        });
+    }
+}
+
+fn is_nonspacing_mark(c: char) -> bool {
+    static MN: OnceLock<Regex> = OnceLock::new();
+    let mn = MN.get_or_init(|| Regex::new(r"\A\p{Mn}\z").unwrap());
+    !c.is_ascii() && mn.is_match(c.encode_utf8(&mut [0; 4]))
+}
@@ -0,0 +1,93 @@
+//! Selection geometry — port of `selectionLineSegmentsMonospace` from
+//! `geometry.ts`.
+//!
+//! Produces the same `{ row, x, width }` segments (one per line the range
+//! touches, every width at least 1), but rows come from the line index and
+//! only the selected lines are scanned. `x` and `width` are in monospace
+//! columns rather than UTF-16 units, measured per grapheme cluster: a tab
+//! advances to the next tab stop, East Asian wide chars and emoji take two
+//! columns (ZWJ families, flags and VS16 sequences included, as one
+//! cluster), and combining marks add nothing to their base. A range end
+//! inside a cluster counts from the start of that cluster.
+
+use serde::Serialize;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use wasm_bindgen::prelude::*;
+
+use crate::{CoreError, CoreText, Unit};
+
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Segment {
+    pub row: usize,
+    pub x: usize,
+    pub width: usize,
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Segments covering the UTF-16 range between `start` and `end` (either
+    /// order), for local or remote selections. `tab_width` defaults to 4.
+    #[wasm_bindgen(js_name = selectionSegments)]
+    pub fn selection_segments_js(&self, start: usize, end: usize, tab_width: Option<usize>) -> Result<JsValue, JsValue> {
+        let segments = self.selection_segments(start, end, tab_width.unwrap_or(DEFAULT_TAB_WIDTH))?;
+        Ok(serde_wasm_bindgen::to_value(&segments)?)
+    }
+}
+
+impl CoreText {
+    pub fn selection_segments(&self, start: usize, end: usize, tab_width: usize) -> Result<Vec<Segment>, CoreError> {
+        let len = self.rope.len_utf16();
+        let start = self.check_index(Unit::Utf16, start, len)?;
+        let end = self.check_index(Unit::Utf16, end, len)?;
+        let (a, b) = (self.rope.utf16_to_char(start.min(end)), self.rope.utf16_to_char(start.max(end)));
+        let tab = tab_width.max(1);
+        let (row_a, row_b) = (self.rope.char_to_line(a), self.rope.char_to_line(b));
+
+        let x = self.column(row_a, a, tab);
+        if row_a == row_b {
+            let width = self.column(row_a, b, tab) - x;
+            return Ok(vec![Segment { row: row_a, x, width: width.max(1) }]);
+        }
+        let mut segments = Vec::with_capacity(row_b - row_a + 1);
+        let width = self.column(row_a, self.line_end_char(row_a), tab) - x;
+        segments.push(Segment { row: row_a, x, width: width.max(1) });
+        for row in row_a + 1..row_b {
+            let width = self.column(row, self.line_end_char(row), tab);
+            segments.push(Segment { row, x: 0, width: width.max(1) });
+        }
+        let width = self.column(row_b, b, tab);
+        segments.push(Segment { row: row_b, x: 0, width: width.max(1) });
+        Ok(segments)
+    }
+
+    /// Column of char index `idx` on `line`, laying the line out cluster by
+    /// cluster from its start.
+    fn column(&self, line: usize, idx: usize, tab: usize) -> usize {
+        let mut at = self.rope.line_to_char(line);
+        let text = self.rope.slice(at, self.line_end_char(line));
+        let mut col = 0;
+        for cluster in text.graphemes(true) {
+            at += cluster.chars().count();
+            if at > idx {
+                break;
+            }
+            col = match cluster {
+                "\t" => col + tab - col % tab,
+                cluster => col + cluster.width(),
+            };
+        }
+        col
+    }
+
+    /// Char index of a line's `\n`, or the end of the text on the last line.
+    fn line_end_char(&self, line: usize) -> usize {
+        if line + 1 < self.rope.len_lines() {
+            self.rope.line_to_char(line + 1) - 1
+        } else {
+            self.rope.len_chars()
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Grapheme cluster boundaries (UAX #29, extended clusters).
+//!
+//! The cursor walks the buffer chunk by chunk, so a lookup costs a few
+//! chunks around the offset, never a copy of the document. Flags, ZWJ
+//! sequences, combining marks and `\r\n` are each one cluster; the
+//! selection-wide `deleteBackward` / `deleteForward` remove whole clusters.
+
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::Buffer;
+use crate::{CoreError, CoreText, Unit};
+
+#[wasm_bindgen]
+impl CoreText {
+    /// First cluster boundary after a UTF-16 offset (the text length at the end).
+    #[wasm_bindgen(js_name = nextGraphemeBoundary)]
+    pub fn next_grapheme_boundary(&self, offset: usize) -> Result<usize, CoreError> {
+        let offset = self.check_index(Unit::Utf16, offset, self.rope.len_utf16())?;
+        Ok(self.next_grapheme_utf16(offset))
+    }
+
+    /// Last cluster boundary before a UTF-16 offset (0 at the start).
+    #[wasm_bindgen(js_name = prevGraphemeBoundary)]
+    pub fn prev_grapheme_boundary(&self, offset: usize) -> Result<usize, CoreError> {
+        let offset = self.check_index(Unit::Utf16, offset, self.rope.len_utf16())?;
+        Ok(self.prev_grapheme_utf16(offset))
+    }
+
+    #[wasm_bindgen(js_name = isGraphemeBoundary)]
+    pub fn is_grapheme_boundary(&self, offset: usize) -> Result<bool, CoreError> {
+        let offset = self.check_index(Unit::Utf16, offset, self.rope.len_utf16())?;
+        let ch = self.rope.utf16_to_char(offset);
+        if self.rope.char_to_utf16(ch) != offset {
+            return Ok(false);
+        }
+        let byte = self.rope.char_to_byte(ch);
+        let mut cursor = GraphemeCursor::new(byte, self.rope.len_bytes(), true);
+        loop {
+            let (chunk, start) = self.rope.chunk_at_byte(byte);
+            match cursor.is_boundary(chunk, start) {
+                Ok(at) => return Ok(at),
+                Err(GraphemeIncomplete::PreContext(n)) => provide_context(&self.rope, &mut cursor, n),
+                Err(e) => unreachable!("is_boundary asked for {e:?}"),
+            }
+        }
+    }
+}
+
+impl CoreText {
+    pub(crate) fn next_grapheme_utf16(&self, offset: usize) -> usize {
+        let byte = self.rope.char_to_byte(self.rope.utf16_to_char(offset));
+        let len = self.rope.len_bytes();
+        let mut cursor = GraphemeCursor::new(byte, len, true);
+        let (mut chunk, mut start) = self.rope.chunk_at_byte(byte);
+        let next = loop {
+            match cursor.next_boundary(chunk, start) {
+                Ok(next) => break next.unwrap_or(len),
+                Err(GraphemeIncomplete::NextChunk) => {
+                    start += chunk.len();
+                    chunk = self.rope.chunk_at_byte(start).0;
+                }
+                Err(GraphemeIncomplete::PreContext(n)) => provide_context(&self.rope, &mut cursor, n),
+                Err(e) => unreachable!("next_boundary asked for {e:?}"),
+            }
+        };
+        self.rope.char_to_utf16(self.rope.byte_to_char(next))
+    }
+
+    pub(crate) fn prev_grapheme_utf16(&self, offset: usize) -> usize {
+        // From inside a surrogate pair, look back from the pair's end.
+        let mut ch = self.rope.utf16_to_char(offset);
+        if self.rope.char_to_utf16(ch) < offset {
+            ch += 1;
+        }
+        let byte = self.rope.char_to_byte(ch);
+        let mut cursor = GraphemeCursor::new(byte, self.rope.len_bytes(), true);
+        let (mut chunk, mut start) = self.rope.chunk_at_byte(byte);
+        let prev = loop {
+            match cursor.prev_boundary(chunk, start) {
+                Ok(prev) => break prev.unwrap_or(0),
+                Err(GraphemeIncomplete::PrevChunk) => {
+                    (chunk, start) = self.rope.chunk_at_byte(start - 1);
+                }
+                Err(GraphemeIncomplete::PreContext(n)) => provide_context(&self.rope, &mut cursor, n),
+                Err(e) => unreachable!("prev_boundary asked for {e:?}"),
+            }
+        };
+        self.rope.char_to_utf16(self.rope.byte_to_char(prev))
+    }
+}
+
+/// Hand the cursor the chunk that ends at byte `n`.
+fn provide_context(rope: &Buffer, cursor: &mut GraphemeCursor, n: usize) {
+    let (chunk, start) = rope.chunk_at_byte(n - 1);
+    cursor.provide_context(&chunk[..n - start], start);
+}
@@ -0,0 +1,333 @@
+//! Undo/redo history.
+//!
+//! Every splice is recorded with the text it removed, which is all its
+//! inverse needs. Changes collect into undo units: everything between
+//! `beginUndoGroup` and `endUndoGroup` is one unit, and outside a group a
+//! change joins the previous unit when it continues a typing run (an insert
+//! right after the last one, or a backspace / forward-delete next to the
+//! last one) within `mergeIntervalMs`. Newlines and `sealUndo` end a run.
+//!
+//! Edits made elsewhere (remote ops from `SyncClient`) are not the user's
+//! to undo: they are kept out of the history, which is moved past them
+//! instead. A recorded change they overlap can no longer be undone
+//! cleanly, so it is dropped with everything older.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::clock::now_ms;
+use crate::ops::{utf16_len, TextRange};
+use crate::CoreText;
+
+/// One applied replacement in UTF-16 offsets, with enough text to invert.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Change {
+    pub at: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Change {
+    fn inverse(&self) -> Change {
+        Change { at: self.at, removed: self.inserted.clone(), inserted: self.removed.clone() }
+    }
+
+    fn is_insert(&self) -> bool {
+        self.removed.is_empty() && !self.inserted.is_empty()
+    }
+
+    fn is_delete(&self) -> bool {
+        self.inserted.is_empty() && !self.removed.is_empty()
+    }
+
+    /// Whether `next` carries on the typing run this change is part of.
+    fn continues_with(&self, next: &Change) -> bool {
+        if self.is_insert() && next.is_insert() {
+            return next.at == self.at + utf16_len(&self.inserted) && !next.inserted.contains('\n');
+        }
+        if self.is_delete() && next.is_delete() {
+            // Backspace walks left onto us; forward-delete stays put.
+            return (next.at + utf16_len(&next.removed) == self.at || next.at == self.at)
+                && !next.removed.contains('\n');
+        }
+        false
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Unit {
+    changes: Vec<Change>,
+    /// When the last change joined; drives the merge interval.
+    touched: f64,
+    /// Typing runs may still grow this unit.
+    open: bool,
+}
+
+/// What an undo or redo did: the range it touched in the resulting text,
+/// and where to put the selection (the text it put back, or a caret where
+/// it took text away).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct UndoResult {
+    pub range: TextRange,
+    pub selection: TextRange,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct History {
+    undo: Vec<Unit>,
+    redo: Vec<Unit>,
+    group_depth: usize,
+    merge_interval_ms: f64,
+    limit: usize,
+    /// Redo stack set aside while a transaction is open, so one that ends
+    /// up changing nothing can give it back.
+    stashed_redo: Option<Vec<Unit>>,
+    /// Changes are remote: rebase past them instead of recording them.
+    remote: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History { undo: Vec::new(), redo: Vec::new(), group_depth: 0, merge_interval_ms: 500.0, limit: 1000, stashed_redo: None, remote: false }
+    }
+}
+
+impl History {
+    pub(crate) fn record(&mut self, change: Change) {
+        self.redo.clear();
+        if self.remote {
+            return self.rebase(&change);
+        }
+        let now = now_ms();
+        if let Some(unit) = self.undo.last_mut() {
+            let joins = if self.group_depth > 0 {
+                unit.open
+            } else {
+                unit.open
+                    && now - unit.touched <= self.merge_interval_ms
+                    && unit.changes.last().is_some_and(|last| last.continues_with(&change))
+            };
+            if joins {
+                unit.changes.push(change);
+                unit.touched = now;
+                return;
+            }
+            if self.group_depth == 0 {
+                unit.open = false;
+            }
+        }
+        self.undo.push(Unit { changes: vec![change], touched: now, open: true });
+        if self.undo.len() > self.limit {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Start a new unit that every change joins until the matching `end_group`.
+    pub(crate) fn begin_group(&mut self) {
+        if self.group_depth == 0 {
+            self.seal();
+            self.undo.push(Unit { changes: Vec::new(), touched: now_ms(), open: true });
+        }
+        self.group_depth += 1;
+    }
+
+    /// Returns whether the outermost group closed without recording anything.
+    pub(crate) fn end_group(&mut self) -> bool {
+        if self.group_depth == 0 {
+            return false;
+        }
+        self.group_depth -= 1;
+        if self.group_depth > 0 {
+            return false;
+        }
+        let empty = self.undo.last().is_some_and(|u| u.changes.is_empty());
+        if empty {
+            self.undo.pop();
+        }
+        self.seal();
+        empty
+    }
+
+    /// Open a transaction's group. The returned mark is what `rollback_txn`
+    /// truncates back to.
+    pub(crate) fn begin_txn(&mut self) -> usize {
+        if self.group_depth == 0 {
+            self.stashed_redo = Some(std::mem::take(&mut self.redo));
+        }
+        self.begin_group();
+        self.undo.last().map_or(0, |u| u.changes.len())
+    }
+
+    pub(crate) fn rollback_txn(&mut self, mark: usize) {
+        if let Some(unit) = self.undo.last_mut() {
+            unit.changes.truncate(mark);
+        }
+        self.end_txn();
+    }
+
+    pub(crate) fn end_txn(&mut self) {
+        let empty = self.end_group();
+        if self.group_depth == 0 {
+            if let Some(redo) = self.stashed_redo.take() {
+                if empty {
+                    self.redo = redo;
+                }
+            }
+        }
+    }
+
+    /// Treat the changes that follow as remote, or local again. Either way
+    /// the typing run ends here.
+    pub(crate) fn set_remote(&mut self, remote: bool) {
+        self.seal();
+        self.remote = remote;
+    }
+
+    /// Move the undo stack past `edit`, made to the current text: changes
+    /// entirely before it shift, changes after it stay, and the first one
+    /// it overlaps goes with all older units.
+    fn rebase(&mut self, edit: &Change) {
+        let (mut at, removed, inserted) = (edit.at, utf16_len(&edit.removed), utf16_len(&edit.inserted));
+        for u in (0..self.undo.len()).rev() {
+            for change in self.undo[u].changes.iter_mut().rev() {
+                // Where `change` put its text, in the text after it.
+                let (start, end) = (change.at, change.at + utf16_len(&change.inserted));
+                if at + removed <= start {
+                    change.at = start + inserted - removed;
+                } else if at >= end {
+                    // The same edit, addressed in the text before `change`.
+                    at = at - (end - start) + utf16_len(&change.removed);
+                } else {
+                    self.undo.drain(..=u);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Stop the current unit from absorbing further changes. Inside a group
+    /// the group decides, so this does nothing.
+    pub(crate) fn seal(&mut self) {
+        if self.group_depth > 0 {
+            return;
+        }
+        if let Some(unit) = self.undo.last_mut() {
+            unit.open = false;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.group_depth = 0;
+        self.stashed_redo = None;
+    }
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Revert the latest undo unit. Returns `{ range, selection }` or
+    /// `undefined` when there is nothing to undo.
+    #[wasm_bindgen(js_name = undo)]
+    pub fn undo_js(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.undo())?)
+    }
+
+    /// Reapply the latest undone unit; same result shape as `undo`.
+    #[wasm_bindgen(js_name = redo)]
+    pub fn redo_js(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.redo())?)
+    }
+
+    #[wasm_bindgen(js_name = canUndo)]
+    pub fn can_undo(&self) -> bool {
+        self.history.group_depth == 0 && !self.history.undo.is_empty()
+    }
+
+    #[wasm_bindgen(js_name = canRedo)]
+    pub fn can_redo(&self) -> bool {
+        self.history.group_depth == 0 && !self.history.redo.is_empty()
+    }
+
+    /// Everything until the matching `endUndoGroup` undoes as one unit.
+    /// Groups nest; only the outermost pair counts.
+    #[wasm_bindgen(js_name = beginUndoGroup)]
+    pub fn begin_undo_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    #[wasm_bindgen(js_name = endUndoGroup)]
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
+
+    /// End the current typing run, e.g. when the caret is moved.
+    #[wasm_bindgen(js_name = sealUndo)]
+    pub fn seal_undo(&mut self) {
+        self.history.seal();
+    }
+
+    #[wasm_bindgen(js_name = clearHistory)]
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Max gap between keystrokes that still merge into one unit (default 500ms).
+    #[wasm_bindgen(js_name = setUndoMergeInterval)]
+    pub fn set_undo_merge_interval(&mut self, ms: f64) {
+        self.history.merge_interval_ms = ms;
+    }
+}
+
+impl CoreText {
+    pub fn undo(&mut self) -> Option<UndoResult> {
+        if self.history.group_depth > 0 {
+            return None;
+        }
+        let unit = self.history.undo.pop()?;
+        self.history.seal();
+        let inverse: Vec<Change> = unit.changes.iter().rev().map(Change::inverse).collect();
+        self.changes.open();
+        let result = self.replay(&inverse);
+        self.changes.close();
+        self.history.redo.push(unit);
+        Some(result)
+    }
+
+    pub fn redo(&mut self) -> Option<UndoResult> {
+        if self.history.group_depth > 0 {
+            return None;
+        }
+        let mut unit = self.history.redo.pop()?;
+        self.changes.open();
+        let result = self.replay(&unit.changes);
+        self.changes.close();
+        unit.open = false;
+        self.history.undo.push(unit);
+        Some(result)
+    }
+
+    /// Apply recorded changes without recording them again.
+    fn replay(&mut self, changes: &[Change]) -> UndoResult {
+        let history = std::mem::take(&mut self.history);
+        let mut range: Option<TextRange> = None;
+        for change in changes {
+            let removed = utf16_len(&change.removed);
+            let inserted = utf16_len(&change.inserted);
+            self.splice_utf16(change.at, change.at + removed, &change.inserted);
+            let span = TextRange { start: change.at, end: change.at + inserted };
+            range = Some(match range {
+                None => span,
+                Some(r) => {
+                    let map = |p: usize| if p <= change.at { p } else { (p.max(change.at + removed) + inserted) - removed };
+                    TextRange { start: map(r.start).min(span.start), end: map(r.end).max(span.end) }
+                }
+            });
+        }
+        self.history = history;
+        let range = range.unwrap_or_default();
+        let restored = changes.iter().any(|c| !c.inserted.is_empty());
+        let selection = if restored { range } else { TextRange { start: range.start, end: range.start } };
+        UndoResult { range, selection }
+    }
+}
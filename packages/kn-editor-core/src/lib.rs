@@ -3,107 +3,349 @@
 //! Build targets:
 //! - Native (rlib) for testing
 //! - WASM (cdylib) for browser integration via wasm-bindgen
+//!
+//! Addressing: the plain `insert`/`delete`/`slice` methods take char
+//! (Unicode scalar) indices. Everything coming from JS (DOM selections,
+//! `EditOp.at`, `EditorModel.replaceRange`) is a UTF-16 offset, so each of
+//! them has a `*Utf16` twin, plus explicit conversions between char, byte,
+//! UTF-16 and line indices.
+//!
+//! The line API (`lenLines`, `lineOfOffset`, `lineStart`, `lineEnd`,
+//! `lineSlice`) speaks UTF-16 too, so `geometry.ts` can drop its full-string
+//! scans. Lines break on `\n` only, on both backends.
+//!
+//! Bad indices never panic: every method checks its input and fails with a
+//! `CoreError` (thrown to JS with a `code`), unless clamp mode is on, in
+//! which case indices are pulled back into range instead.
 
 use wasm_bindgen::prelude::*;
 
-#[cfg(feature = "rope")]
-use ropey::Rope;
+mod anchors;
+mod blocks;
+mod buffer;
+mod clock;
+#[cfg(feature = "crdt")]
+mod crdt;
+mod decorations;
+mod diff;
+#[cfg(feature = "crdt")]
+mod encoding;
+mod error;
+mod fold;
+mod geometry;
+mod graphemes;
+mod history;
+#[cfg(not(feature = "rope"))]
+mod lines;
+mod ops;
+mod oplog;
+mod ot;
+mod replace;
+mod search;
+mod selections;
+mod snapshot;
+mod sync;
+mod tokens;
+mod txn;
+mod words;
+#[cfg(feature = "crdt")]
+mod yjs;
+
+use anchors::Anchors;
+use blocks::Blocks;
+use buffer::Buffer;
+use decorations::Decorations;
+use history::{Change, History};
+use ops::{utf16_len, Splice};
+use selections::Selections;
+use tokens::TokenIndex;
+use txn::{Changes, Checkpoint};
+
+pub use anchors::{Anchor, Bias};
+pub use blocks::{BlockDelta, BlockRef, EditorBlock};
+#[cfg(feature = "crdt")]
+pub use crdt::CrdtText;
+pub use decorations::Decoration;
+pub use diff::{diff, DiffOptions, Granularity, MAX_EDIT_DISTANCE};
+pub use error::{CoreError, JsProp, Unit};
+pub use geometry::{Segment, DEFAULT_TAB_WIDTH};
+pub use history::UndoResult;
+pub use ops::{ops_range, EditOp, TextRange};
+pub use oplog::{LogEntry, OpLog};
+pub use ot::{compose, invert, transform};
+pub use search::{SearchCursor, SearchOptions, SearchPage};
+pub use selections::Selection;
+pub use snapshot::Snapshot;
+pub use sync::{Outgoing, SyncClient};
+pub use tokens::{TokenEntry, TokenPosting};
+pub use txn::ChangeRecord;
+#[cfg(feature = "crdt")]
+pub use yjs::YjsText;
 
 #[wasm_bindgen]
 pub struct CoreText {
-    #[cfg(feature = "rope")]
-    rope: Rope,
-    #[cfg(not(feature = "rope"))]
-    rope: String,
+    rope: Buffer,
+    clamp: bool,
+    blocks: Blocks,
+    history: History,
+    anchors: Anchors,
+    decorations: Decorations,
+    selections: Selections,
+    tokens: TokenIndex,
+    changes: Changes,
+    txns: Vec<Checkpoint>,
+}
+
+impl Default for CoreText {
+    fn default() -> Self {
+        CoreText::new()
+    }
 }
 
 #[wasm_bindgen]
 impl CoreText {
     #[wasm_bindgen(constructor)]
     pub fn new() -> CoreText {
-        #[cfg(feature = "rope")]
-        {
-            CoreText { rope: Rope::new() }
-        }
-        #[cfg(not(feature = "rope"))]
-        {
-            CoreText { rope: String::new() }
-        }
+        CoreText::from_buffer(Buffer::new())
     }
 
     #[wasm_bindgen(js_name = fromString)]
     pub fn from_string(s: &str) -> CoreText {
-        #[cfg(feature = "rope")]
-        {
-            CoreText { rope: Rope::from_str(s) }
-        }
-        #[cfg(not(feature = "rope"))]
-        {
-            CoreText { rope: s.to_string() }
-        }
+        CoreText::from_buffer(Buffer::from_str(s))
+    }
+
+    /// When on, out-of-range indices are clamped to the document (and
+    /// inverted ranges swapped) instead of raising a `CoreError`.
+    #[wasm_bindgen(js_name = setClampMode)]
+    pub fn set_clamp_mode(&mut self, clamp: bool) {
+        self.clamp = clamp;
+    }
+
+    #[wasm_bindgen(js_name = clampMode)]
+    pub fn clamp_mode(&self) -> bool {
+        self.clamp
     }
 
     #[wasm_bindgen(js_name = lenChars)]
     pub fn len_chars(&self) -> usize {
-        #[cfg(feature = "rope")]
-        { self.rope.len_chars() }
-        #[cfg(not(feature = "rope"))]
-        { self.rope.chars().count() }
+        self.rope.len_chars()
+    }
+
+    #[wasm_bindgen(js_name = lenBytes)]
+    pub fn len_bytes(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    #[wasm_bindgen(js_name = lenUtf16)]
+    pub fn len_utf16(&self) -> usize {
+        self.rope.len_utf16()
     }
 
     #[wasm_bindgen(js_name = insert)]
-    pub fn insert(&mut self, char_idx: usize, text: &str) {
-        #[cfg(feature = "rope")]
-        {
-            self.rope.insert(char_idx, text);
-        }
-        #[cfg(not(feature = "rope"))]
-        {
-            let mut acc = String::with_capacity(self.rope.len() + text.len());
-            let (head, tail) = self.rope.split_at(self.byte_index(char_idx));
-            acc.push_str(head);
-            acc.push_str(text);
-            acc.push_str(tail);
-            self.rope = acc;
-        }
+    pub fn insert(&mut self, char_idx: usize, text: &str) -> Result<(), CoreError> {
+        let at = self.check_index(Unit::Char, char_idx, self.rope.len_chars())?;
+        self.splice(at, at, text);
+        Ok(())
     }
 
     #[wasm_bindgen(js_name = delete)]
-    pub fn delete(&mut self, char_idx: usize, len_chars: usize) {
-        #[cfg(feature = "rope")]
-        {
-            let end = char_idx.saturating_add(len_chars);
-            self.rope.remove(char_idx..end);
-        }
-        #[cfg(not(feature = "rope"))]
-        {
-            let start_b = self.byte_index(char_idx);
-            let end_b = self.byte_index(char_idx.saturating_add(len_chars));
-            self.rope.replace_range(start_b..end_b, "");
-        }
+    pub fn delete(&mut self, char_idx: usize, len_chars: usize) -> Result<(), CoreError> {
+        let end = char_idx.saturating_add(len_chars);
+        let (start, end) = self.check_range(Unit::Char, char_idx, end, self.rope.len_chars())?;
+        self.splice(start, end, "");
+        Ok(())
     }
 
     #[wasm_bindgen(js_name = slice)]
-    pub fn slice(&self, start_char: usize, end_char: usize) -> String {
-        #[cfg(feature = "rope")]
-        {
-            self.rope.slice(start_char..end_char).to_string()
+    pub fn slice(&self, start_char: usize, end_char: usize) -> Result<String, CoreError> {
+        let (start, end) = self.check_range(Unit::Char, start_char, end_char, self.rope.len_chars())?;
+        Ok(self.rope.slice(start, end))
+    }
+
+    /// Insert at a UTF-16 offset (what JS strings and DOM ranges use).
+    #[wasm_bindgen(js_name = insertUtf16)]
+    pub fn insert_utf16(&mut self, utf16_idx: usize, text: &str) -> Result<(), CoreError> {
+        let at = self.check_index(Unit::Utf16, utf16_idx, self.rope.len_utf16())?;
+        let char_idx = self.rope.utf16_to_char(at);
+        self.splice(char_idx, char_idx, text);
+        Ok(())
+    }
+
+    /// Delete `len_utf16` code units starting at a UTF-16 offset.
+    #[wasm_bindgen(js_name = deleteUtf16)]
+    pub fn delete_utf16(&mut self, utf16_idx: usize, len_utf16: usize) -> Result<(), CoreError> {
+        let end = utf16_idx.saturating_add(len_utf16);
+        let (start, end) = self.check_range(Unit::Utf16, utf16_idx, end, self.rope.len_utf16())?;
+        let (start, end) = (self.rope.utf16_to_char(start), self.rope.utf16_to_char(end));
+        self.splice(start, end, "");
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = sliceUtf16)]
+    pub fn slice_utf16(&self, start_utf16: usize, end_utf16: usize) -> Result<String, CoreError> {
+        let (start, end) = self.check_range(Unit::Utf16, start_utf16, end_utf16, self.rope.len_utf16())?;
+        Ok(self.rope.slice(self.rope.utf16_to_char(start), self.rope.utf16_to_char(end)))
+    }
+
+    #[wasm_bindgen(js_name = charToByte)]
+    pub fn char_to_byte(&self, char_idx: usize) -> Result<usize, CoreError> {
+        let char_idx = self.check_index(Unit::Char, char_idx, self.rope.len_chars())?;
+        Ok(self.rope.char_to_byte(char_idx))
+    }
+
+    /// A byte inside a multi-byte char maps to that char.
+    #[wasm_bindgen(js_name = byteToChar)]
+    pub fn byte_to_char(&self, byte_idx: usize) -> Result<usize, CoreError> {
+        let byte_idx = self.check_index(Unit::Byte, byte_idx, self.rope.len_bytes())?;
+        Ok(self.rope.byte_to_char(byte_idx))
+    }
+
+    #[wasm_bindgen(js_name = charToUtf16)]
+    pub fn char_to_utf16(&self, char_idx: usize) -> Result<usize, CoreError> {
+        let char_idx = self.check_index(Unit::Char, char_idx, self.rope.len_chars())?;
+        Ok(self.rope.char_to_utf16(char_idx))
+    }
+
+    /// An offset that falls between the halves of a surrogate pair maps to
+    /// the char that pair encodes.
+    #[wasm_bindgen(js_name = utf16ToChar)]
+    pub fn utf16_to_char(&self, utf16_idx: usize) -> Result<usize, CoreError> {
+        let utf16_idx = self.check_index(Unit::Utf16, utf16_idx, self.rope.len_utf16())?;
+        Ok(self.rope.utf16_to_char(utf16_idx))
+    }
+
+    #[wasm_bindgen(js_name = charToLine)]
+    pub fn char_to_line(&self, char_idx: usize) -> Result<usize, CoreError> {
+        let char_idx = self.check_index(Unit::Char, char_idx, self.rope.len_chars())?;
+        Ok(self.rope.char_to_line(char_idx))
+    }
+
+    /// `line_idx` may equal `lenLines()`, which maps to the end of the text.
+    #[wasm_bindgen(js_name = lineToChar)]
+    pub fn line_to_char(&self, line_idx: usize) -> Result<usize, CoreError> {
+        let line_idx = self.check_index(Unit::Line, line_idx, self.rope.len_lines())?;
+        Ok(self.rope.line_to_char(line_idx))
+    }
+
+    /// Number of lines; a trailing `\n` opens an empty last line.
+    #[wasm_bindgen(js_name = lenLines)]
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// Line containing a UTF-16 offset.
+    #[wasm_bindgen(js_name = lineOfOffset)]
+    pub fn line_of_offset(&self, utf16_idx: usize) -> Result<usize, CoreError> {
+        let utf16_idx = self.check_index(Unit::Utf16, utf16_idx, self.rope.len_utf16())?;
+        Ok(self.rope.utf16_to_line(utf16_idx))
+    }
+
+    /// UTF-16 offset of the first code unit of a line.
+    #[wasm_bindgen(js_name = lineStart)]
+    pub fn line_start(&self, line_idx: usize) -> Result<usize, CoreError> {
+        let line_idx = self.check_line(line_idx)?;
+        Ok(self.rope.line_to_utf16(line_idx))
+    }
+
+    /// UTF-16 offset just past the last code unit of a line, excluding its `\n`.
+    #[wasm_bindgen(js_name = lineEnd)]
+    pub fn line_end(&self, line_idx: usize) -> Result<usize, CoreError> {
+        let line_idx = self.check_line(line_idx)?;
+        Ok(if line_idx + 1 < self.rope.len_lines() {
+            self.rope.line_to_utf16(line_idx + 1) - 1
+        } else {
+            self.rope.len_utf16()
+        })
+    }
+
+    /// Text of a line, without its `\n`.
+    #[wasm_bindgen(js_name = lineSlice)]
+    pub fn line_slice(&self, line_idx: usize) -> Result<String, CoreError> {
+        let line_idx = self.check_line(line_idx)?;
+        let start = self.rope.line_to_char(line_idx);
+        let end = if line_idx + 1 < self.rope.len_lines() {
+            self.rope.line_to_char(line_idx + 1) - 1
+        } else {
+            self.rope.len_chars()
+        };
+        Ok(self.rope.slice(start, end))
+    }
+}
+
+impl CoreText {
+    fn from_buffer(rope: Buffer) -> CoreText {
+        let blocks = Blocks::new(&rope);
+        CoreText {
+            rope,
+            clamp: false,
+            blocks,
+            history: History::default(),
+            anchors: Anchors::default(),
+            decorations: Decorations::default(),
+            selections: Selections::default(),
+            tokens: TokenIndex::default(),
+            changes: Changes::default(),
+            txns: Vec::new(),
+        }
+    }
+
+    /// Every mutation funnels through here so derived state (blocks,
+    /// history, ...) follows the text. Takes validated char indices.
+    fn splice(&mut self, start: usize, end: usize, text: &str) {
+        if start == end && text.is_empty() {
+            return;
+        }
+        let start_utf16 = self.rope.char_to_utf16(start);
+        let removed = self.rope.slice(start, end);
+        let edit = Splice {
+            start: start_utf16,
+            removed: utf16_len(&removed),
+            inserted: utf16_len(text),
+        };
+        if start < end {
+            self.rope.remove(start, end);
         }
-        #[cfg(not(feature = "rope"))]
-        {
-            let sb = self.byte_index(start_char);
-            let eb = self.byte_index(end_char);
-            self.rope[sb..eb].to_string()
+        if !text.is_empty() {
+            self.rope.insert(start, text);
         }
+        self.blocks.apply(&self.rope, &edit);
+        self.anchors.apply(&edit);
+        self.decorations.apply(&edit);
+        self.selections.apply(&edit);
+        self.changes.record(&edit, text);
+        self.history.record(Change { at: start_utf16, removed, inserted: text.to_string() });
     }
 
-    #[cfg(not(feature = "rope"))]
-    fn byte_index(&self, char_idx: usize) -> usize {
-        // Fallback: convert char index to byte index (UTF-8)
-        self.rope.char_indices().nth(char_idx).map(|(i, _)| i).unwrap_or(self.rope.len())
+    /// `splice` addressed in validated UTF-16 offsets.
+    fn splice_utf16(&mut self, start: usize, end: usize, text: &str) {
+        let (start, end) = (self.rope.utf16_to_char(start), self.rope.utf16_to_char(end));
+        self.splice(start, end, text);
+    }
+
+    /// Validate a position in `unit`, where `len` is the largest valid value.
+    fn check_index(&self, unit: Unit, index: usize, len: usize) -> Result<usize, CoreError> {
+        if index <= len {
+            Ok(index)
+        } else if self.clamp {
+            Ok(len)
+        } else {
+            Err(CoreError::OutOfBounds { unit, index, len })
+        }
+    }
+
+    fn check_range(&self, unit: Unit, start: usize, end: usize, len: usize) -> Result<(usize, usize), CoreError> {
+        let (start, end) = match (start <= end, self.clamp) {
+            (true, _) => (start, end),
+            (false, true) => (end, start),
+            (false, false) => return Err(CoreError::InvertedRange { start, end }),
+        };
+        Ok((self.check_index(unit, start, len)?, self.check_index(unit, end, len)?))
+    }
+
+    fn check_line(&self, line_idx: usize) -> Result<usize, CoreError> {
+        self.check_index(Unit::Line, line_idx, self.rope.len_lines() - 1)
     }
 }
 
 #[wasm_bindgen]
 pub fn version() -> String { "kn-editor-core/0.1.0".to_string() }
-
@@ -0,0 +1,97 @@
+//! Line-start table for the `String` fallback backend.
+//!
+//! Ropey keeps line metadata in its tree nodes; a flat `String` has nothing,
+//! so without the `rope` feature we keep one entry per line recording where
+//! it starts in bytes, chars and UTF-16 code units. Edits splice the table
+//! instead of rescanning the document, and every index conversion narrows to
+//! a single line by binary search before scanning.
+
+/// A position expressed in every unit the buffer cares about.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Pos {
+    pub byte: usize,
+    pub ch: usize,
+    pub utf16: usize,
+}
+
+impl Pos {
+    fn advance(self, text: &str) -> Pos {
+        Pos {
+            byte: self.byte + text.len(),
+            ch: self.ch + text.chars().count(),
+            utf16: self.utf16 + text.chars().map(char::len_utf16).sum::<usize>(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct LineIndex {
+    /// `starts[0]` is always the origin; one entry per `\n` follows.
+    starts: Vec<Pos>,
+}
+
+impl LineIndex {
+    pub(crate) fn from_str(s: &str) -> LineIndex {
+        let mut index = LineIndex { starts: vec![Pos::default()] };
+        index.starts.extend(line_starts(Pos::default(), s));
+        index
+    }
+
+    pub(crate) fn len_lines(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub(crate) fn start(&self, line_idx: usize) -> Pos {
+        self.starts[line_idx]
+    }
+
+    pub(crate) fn line_of_byte(&self, byte_idx: usize) -> usize {
+        self.starts.partition_point(|p| p.byte <= byte_idx) - 1
+    }
+
+    pub(crate) fn line_of_char(&self, char_idx: usize) -> usize {
+        self.starts.partition_point(|p| p.ch <= char_idx) - 1
+    }
+
+    pub(crate) fn line_of_utf16(&self, utf16_idx: usize) -> usize {
+        self.starts.partition_point(|p| p.utf16 <= utf16_idx) - 1
+    }
+
+    /// Record `text` inserted at `at`.
+    pub(crate) fn insert(&mut self, at: Pos, text: &str) {
+        let line = self.line_of_byte(at.byte);
+        let delta = Pos::default().advance(text);
+        for p in &mut self.starts[line + 1..] {
+            p.byte += delta.byte;
+            p.ch += delta.ch;
+            p.utf16 += delta.utf16;
+        }
+        let added: Vec<Pos> = line_starts(at, text).collect();
+        self.starts.splice(line + 1..line + 1, added);
+    }
+
+    /// Record the removal of everything between `start` and `end`.
+    pub(crate) fn remove(&mut self, start: Pos, end: Pos) {
+        // A line starting inside (start, end] lost the `\n` that opened it.
+        let first = self.starts.partition_point(|p| p.byte <= start.byte);
+        let last = self.starts.partition_point(|p| p.byte <= end.byte);
+        self.starts.drain(first..last);
+        for p in &mut self.starts[first..] {
+            p.byte -= end.byte - start.byte;
+            p.ch -= end.ch - start.ch;
+            p.utf16 -= end.utf16 - start.utf16;
+        }
+    }
+}
+
+/// Start positions of the lines opened by each `\n` in `text`, assuming
+/// `text` begins at `base`.
+fn line_starts(base: Pos, text: &str) -> impl Iterator<Item = Pos> + '_ {
+    let mut pos = base;
+    text.chars().filter_map(move |c| {
+        pos.byte += c.len_utf8();
+        pos.ch += 1;
+        pos.utf16 += c.len_utf16();
+        (c == '\n').then_some(pos)
+    })
+}
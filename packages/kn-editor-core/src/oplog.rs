@@ -0,0 +1,293 @@
+//! Append-only op log with Lamport timestamps, coalescing and compaction
+//! (the "batch ops at 50–100ms" and "compact ops in background" items of
+//! `performance-optimization-spec.md`).
+//!
+//! Each entry is one `EditOp` batch with its author and a Lamport stamp;
+//! version `n` is the text after the `n`th entry. Local appends tick the
+//! clock; remote ones keep the stamp they were sent with and pull the
+//! clock up to it, so every later local entry orders after everything
+//! seen. A remote stamp can be behind the entries before it: the log is in
+//! arrival order, `(lamport, author)` is the causal one.
+//!
+//! The last entry stays open while its author keeps typing: a single
+//! insert or delete at the caret it left, within `coalesceMs` (100 by
+//! default, 0 turns it off), is composed into it instead of making a new
+//! version. Composing is where insert-then-delete pairs cancel: typing a
+//! word and backspacing over it leaves nothing to replay, and an entry
+//! that cancels out entirely stays as an empty, sealed one, so its
+//! version is never handed out twice. A newline, a pause, another
+//! author or `seal` closes it, and so does handing it out: once
+//! `entriesSince`, `snapshotAt` or `checkpoint` has seen the head, the
+//! entries and text they returned never change.
+//!
+//! Checkpoints keep the text at a version (cheap with the rope backend, as
+//! for `Snapshot`); `snapshotAt` replays from the nearest one at or before
+//! the version asked for. `compact(v)` makes the text at `v` the new base
+//! and drops older entries and checkpoints, so anything from `v` on can
+//! still be replayed.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::Buffer;
+use crate::clock::now_ms;
+use crate::ops::{utf16_len, validate_ops, EditOp};
+use crate::ot::compose;
+use crate::{CoreError, Snapshot};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Log version after this entry.
+    pub version: u64,
+    pub lamport: u64,
+    pub author: u32,
+    pub ops: Vec<EditOp>,
+}
+
+#[wasm_bindgen]
+pub struct OpLog {
+    /// Text at each retained version; always holds `base`.
+    checkpoints: BTreeMap<u64, Buffer>,
+    base: u64,
+    entries: Vec<LogEntry>,
+    head: Buffer,
+    clock: u64,
+    coalesce_ms: f64,
+    /// When the last entry last grew; `None` once it is sealed.
+    open_since: Option<f64>,
+}
+
+#[wasm_bindgen]
+impl OpLog {
+    /// A log whose version 0 is `text`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(text: &str) -> OpLog {
+        let head = Buffer::from_str(text);
+        OpLog {
+            checkpoints: BTreeMap::from([(0, head.clone())]),
+            base: 0,
+            entries: Vec::new(),
+            head,
+            clock: 0,
+            coalesce_ms: 100.0,
+            open_since: None,
+        }
+    }
+
+    #[wasm_bindgen(js_name = version)]
+    pub fn version_js(&self) -> f64 {
+        self.version() as f64
+    }
+
+    /// Oldest version still in the log.
+    #[wasm_bindgen(js_name = baseVersion)]
+    pub fn base_version_js(&self) -> f64 {
+        self.base as f64
+    }
+
+    /// Current Lamport time.
+    #[wasm_bindgen(js_name = clock)]
+    pub fn clock_js(&self) -> f64 {
+        self.clock as f64
+    }
+
+    #[wasm_bindgen(js_name = setCoalesceMs)]
+    pub fn set_coalesce_ms(&mut self, ms: f64) {
+        self.coalesce_ms = ms;
+    }
+
+    /// Append a local batch. Returns the entry it ended up in (the last
+    /// one, grown, if it coalesced; with no ops if it cancelled out), or
+    /// `undefined` if the batch was empty.
+    #[wasm_bindgen(js_name = append)]
+    pub fn append_js(&mut self, author: u32, ops: JsValue) -> Result<JsValue, JsValue> {
+        let ops: Vec<EditOp> = serde_wasm_bindgen::from_value(ops)?;
+        Ok(serde_wasm_bindgen::to_value(&self.append(author, &ops)?)?)
+    }
+
+    /// Append a batch stamped elsewhere; same result as `append`.
+    #[wasm_bindgen(js_name = appendRemote)]
+    pub fn append_remote_js(&mut self, author: u32, ops: JsValue, lamport: f64) -> Result<JsValue, JsValue> {
+        let ops: Vec<EditOp> = serde_wasm_bindgen::from_value(ops)?;
+        Ok(serde_wasm_bindgen::to_value(&self.append_remote(author, &ops, lamport as u64)?)?)
+    }
+
+    /// Stop the last entry from coalescing further.
+    #[wasm_bindgen(js_name = seal)]
+    pub fn seal(&mut self) {
+        self.open_since = None;
+    }
+
+    /// Entries after `version`, oldest first.
+    #[wasm_bindgen(js_name = entriesSince)]
+    pub fn entries_since_js(&mut self, version: f64) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(self.entries_since(version as u64)?)?)
+    }
+
+    /// Retain the text at `version` for replay.
+    #[wasm_bindgen(js_name = checkpoint)]
+    pub fn checkpoint_js(&mut self, version: f64) -> Result<(), JsValue> {
+        Ok(self.checkpoint(version as u64)?)
+    }
+
+    /// Retained versions, oldest first.
+    #[wasm_bindgen(js_name = checkpoints)]
+    pub fn checkpoints_js(&self) -> Vec<f64> {
+        self.checkpoints.keys().map(|v| *v as f64).collect()
+    }
+
+    #[wasm_bindgen(js_name = snapshotAt)]
+    pub fn snapshot_at_js(&mut self, version: f64) -> Result<Snapshot, JsValue> {
+        Ok(self.snapshot_at(version as u64)?)
+    }
+
+    /// Make the text at `version` the new base; see the module docs.
+    #[wasm_bindgen(js_name = compact)]
+    pub fn compact_js(&mut self, version: f64) -> Result<(), JsValue> {
+        Ok(self.compact(version as u64)?)
+    }
+}
+
+impl OpLog {
+    pub fn version(&self) -> u64 {
+        self.base + self.entries.len() as u64
+    }
+
+    pub fn base_version(&self) -> u64 {
+        self.base
+    }
+
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn append(&mut self, author: u32, ops: &[EditOp]) -> Result<Option<LogEntry>, CoreError> {
+        let lamport = if ops.is_empty() { self.clock } else { self.clock + 1 };
+        self.push(author, ops, lamport)
+    }
+
+    pub fn append_remote(&mut self, author: u32, ops: &[EditOp], lamport: u64) -> Result<Option<LogEntry>, CoreError> {
+        self.push(author, ops, lamport)
+    }
+
+    pub fn entries_since(&mut self, version: u64) -> Result<&[LogEntry], CoreError> {
+        let version = self.check_version(version)?;
+        if version < self.version() {
+            self.seal();
+        }
+        Ok(&self.entries[(version - self.base) as usize..])
+    }
+
+    pub fn checkpoint(&mut self, version: u64) -> Result<(), CoreError> {
+        let text = self.text_at(version)?;
+        if version == self.version() {
+            self.seal();
+        }
+        self.checkpoints.insert(version, text);
+        Ok(())
+    }
+
+    pub fn checkpoints(&self) -> Vec<u64> {
+        self.checkpoints.keys().copied().collect()
+    }
+
+    pub fn snapshot_at(&mut self, version: u64) -> Result<Snapshot, CoreError> {
+        let text = self.text_at(version)?;
+        if version == self.version() {
+            self.seal();
+        }
+        Ok(Snapshot::new(text, version))
+    }
+
+    pub fn compact(&mut self, version: u64) -> Result<(), CoreError> {
+        self.checkpoint(version)?;
+        self.checkpoints = self.checkpoints.split_off(&version);
+        self.entries.drain(..(version - self.base) as usize);
+        self.base = version;
+        Ok(())
+    }
+
+    fn push(&mut self, author: u32, ops: &[EditOp], lamport: u64) -> Result<Option<LogEntry>, CoreError> {
+        apply(&mut self.head, ops)?;
+        self.clock = self.clock.max(lamport);
+        if ops.is_empty() {
+            return Ok(None);
+        }
+        let now = now_ms();
+        let open = self.open_since.is_some_and(|since| self.coalesce_ms > 0.0 && now - since <= self.coalesce_ms);
+        match self.entries.last_mut() {
+            Some(last) if open && last.author == author && continues(&last.ops, ops) => {
+                last.ops = compose(&last.ops, ops);
+                last.lamport = lamport;
+                if last.ops.is_empty() {
+                    self.open_since = None;
+                    return Ok(Some(last.clone()));
+                }
+            }
+            _ => {
+                let version = self.version() + 1;
+                self.entries.push(LogEntry { version, lamport, author, ops: ops.to_vec() });
+            }
+        }
+        self.open_since = Some(now);
+        Ok(self.entries.last().cloned())
+    }
+
+    fn check_version(&self, version: u64) -> Result<u64, CoreError> {
+        if version < self.base || version > self.version() {
+            return Err(CoreError::UnknownVersion { version, oldest: self.base, latest: self.version() });
+        }
+        Ok(version)
+    }
+
+    /// Replay from the nearest checkpoint at or before `version`.
+    fn text_at(&self, version: u64) -> Result<Buffer, CoreError> {
+        let version = self.check_version(version)?;
+        if version == self.version() {
+            return Ok(self.head.clone());
+        }
+        let (from, text) = self.checkpoints.range(..=version).next_back().expect("base is always retained");
+        let mut text = text.clone();
+        for entry in &self.entries[(from - self.base) as usize..(version - self.base) as usize] {
+            apply(&mut text, &entry.ops)?;
+        }
+        Ok(text)
+    }
+}
+
+/// Whether `next` carries on typing where `prev` left the caret: a lone
+/// insert there (no newline), or a lone backspace or forward delete next
+/// to it.
+fn continues(prev: &[EditOp], next: &[EditOp]) -> bool {
+    let caret = match prev.last() {
+        Some(EditOp::Ins { at, text }) => at + utf16_len(text),
+        Some(EditOp::Del { at, .. }) => *at,
+        None => return false,
+    };
+    match next {
+        [EditOp::Ins { at, text }] => *at == caret && !text.contains('\n'),
+        [EditOp::Del { at, len }] => at + len == caret || *at == caret,
+        _ => false,
+    }
+}
+
+/// `CoreText::apply_ops` for a bare buffer: checked first, so a bad batch
+/// changes nothing.
+fn apply(text: &mut Buffer, ops: &[EditOp]) -> Result<(), CoreError> {
+    validate_ops(text.len_utf16(), ops)?;
+    for op in ops {
+        match op {
+            EditOp::Ins { at, text: s } => {
+                let at = text.utf16_to_char(*at);
+                text.insert(at, s);
+            }
+            EditOp::Del { at, len } => {
+                let (start, end) = (text.utf16_to_char(*at), text.utf16_to_char(at + len));
+                text.remove(start, end);
+            }
+        }
+    }
+    Ok(())
+}
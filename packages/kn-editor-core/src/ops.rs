@@ -0,0 +1,135 @@
+//! `EditOp` batches — the Rust side of `rope-text.ts`'s `applyOps`.
+//!
+//! Ops use the same wire shape as TS (`{t:'ins',at,text}` /
+//! `{t:'del',at,len}`) and the same UTF-16 offsets. Each op addresses the
+//! document as left by the ops before it in the batch.
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{CoreError, CoreText, Unit};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "t")]
+pub enum EditOp {
+    #[serde(rename = "ins")]
+    Ins { at: usize, text: String },
+    #[serde(rename = "del")]
+    Del { at: usize, len: usize },
+}
+
+/// Half-open UTF-16 range, serialized as `{ start, end }`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Port of `computeOpsRange` from `model.ts`: the smallest range covering
+/// every op's own span, or `None` for an empty batch.
+pub fn ops_range(ops: &[EditOp]) -> Option<TextRange> {
+    let mut range: Option<TextRange> = None;
+    for op in ops {
+        let (at, end) = match op {
+            EditOp::Ins { at, text } => (*at, at + utf16_len(text)),
+            EditOp::Del { at, len } => (*at, at + len),
+        };
+        let r = range.get_or_insert(TextRange { start: at, end });
+        r.start = r.start.min(at);
+        r.end = r.end.max(end);
+    }
+    range
+}
+
+/// One applied replacement, in UTF-16 units, handed to everything that
+/// tracks positions so it can follow the edit.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Splice {
+    pub start: usize,
+    pub removed: usize,
+    pub inserted: usize,
+}
+
+pub(crate) fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Apply a whole `EditOp[]` in one call. The batch is checked against
+    /// the document before anything changes, so a bad op leaves the text
+    /// untouched (in clamp mode each op is clamped instead). Returns the
+    /// affected `{ start, end }`, like `computeOpsRange`.
+    #[wasm_bindgen(js_name = applyOps)]
+    pub fn apply_ops_js(&mut self, ops: JsValue) -> Result<JsValue, JsValue> {
+        let ops: Vec<EditOp> = serde_wasm_bindgen::from_value(ops)?;
+        let range = self.apply_ops(&ops)?;
+        Ok(serde_wasm_bindgen::to_value(&range)?)
+    }
+}
+
+impl CoreText {
+    pub fn apply_ops(&mut self, ops: &[EditOp]) -> Result<Option<TextRange>, CoreError> {
+        if !self.clamp {
+            validate_ops(self.len_utf16(), ops)?;
+        }
+        // One change record for the whole batch. In clamp mode the range
+        // comes from the ops as clamped, which is what actually changed.
+        self.changes.open();
+        let mut clamped = Vec::new();
+        let applied = ops.iter().try_for_each(|op| {
+            let op = if self.clamp {
+                clamped.push(clamp_op(self.len_utf16(), op));
+                &clamped[clamped.len() - 1]
+            } else {
+                op
+            };
+            match op {
+                EditOp::Ins { at, text } => self.insert_utf16(*at, text),
+                EditOp::Del { at, len } => self.delete_utf16(*at, *len),
+            }
+        });
+        self.changes.close();
+        applied?;
+        Ok(ops_range(if self.clamp { &clamped } else { ops }))
+    }
+
+    /// `apply_ops` for a batch made elsewhere: always checked, even in
+    /// clamp mode, since clamping would silently fork the text from its
+    /// source, and kept out of the undo history (see `history.rs`).
+    pub(crate) fn apply_remote_ops(&mut self, ops: &[EditOp]) -> Result<Option<TextRange>, CoreError> {
+        validate_ops(self.len_utf16(), ops)?;
+        self.history.set_remote(true);
+        let applied = self.apply_ops(ops);
+        self.history.set_remote(false);
+        applied
+    }
+}
+
+/// Walk the batch tracking only the document length, so validation never
+/// touches the text.
+pub(crate) fn validate_ops(mut len: usize, ops: &[EditOp]) -> Result<(), CoreError> {
+    for (i, op) in ops.iter().enumerate() {
+        let (index, grow, shrink) = match op {
+            EditOp::Ins { at, text } => (*at, utf16_len(text), 0),
+            EditOp::Del { at, len: n } => (at.saturating_add(*n), 0, *n),
+        };
+        if index > len {
+            let error = Box::new(CoreError::OutOfBounds { unit: Unit::Utf16, index, len });
+            return Err(CoreError::InvalidOp { op: i, error });
+        }
+        len = len + grow - shrink;
+    }
+    Ok(())
+}
+
+/// What clamp mode makes of `op` against a document `len` units long.
+fn clamp_op(len: usize, op: &EditOp) -> EditOp {
+    match op {
+        EditOp::Ins { at, text } => EditOp::Ins { at: (*at).min(len), text: text.clone() },
+        EditOp::Del { at, len: n } => {
+            let at = (*at).min(len);
+            EditOp::Del { at, len: (*n).min(len - at) }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Operational transform over `EditOp` batches: the OT interop half of the
+//! position-based op format in `performance-optimization-spec.md`, used by
+//! the server relay to rebase clients that are behind.
+//!
+//! `transform(a, b)` takes two batches made concurrently against the same
+//! document and returns `(a', b')` with `b` then `a'` and `a` then `b'`
+//! ending in the same text (TP1). Inserts at the same spot keep `a`'s text
+//! first. `compose(a, b)` is one batch doing `a` then `b`, and
+//! `invert(a, base)` undoes `a` given the text it applied to.
+//!
+//! Internally a batch is rewritten as one pass over the document (retain,
+//! insert and delete runs), the form these algorithms work on, and turned
+//! back into `EditOp`s at the end; so the results are normalized: deletes
+//! of text the same batch inserted cancel out. Ops that reach past the end
+//! of the document imply more of it: only `invert` knows the text and
+//! checks the batch against it.
+//!
+//! Inserted text is never split inside a surrogate pair: an offset into
+//! one moves back before it, as `CoreText` does when it applies the op.
+//! `invert` does the same against the base text; other offsets into the
+//! base are taken as they are, since only the document knows its pairs.
+
+use wasm_bindgen::prelude::*;
+
+use crate::ops::{utf16_len, validate_ops, EditOp};
+use crate::CoreError;
+
+/// One run of a single pass over the document, in UTF-16 units. Inserted
+/// text is kept as UTF-16 so runs can be cut anywhere.
+#[derive(Clone, Debug)]
+enum Run {
+    Retain(usize),
+    Insert(Vec<u16>),
+    Delete(usize),
+}
+
+/// Batches made concurrently against the same document, rewritten to apply
+/// after each other: `(a', b')` for applying after `b` and `a`.
+pub fn transform(a: &[EditOp], b: &[EditOp]) -> (Vec<EditOp>, Vec<EditOp>) {
+    let (mut a, mut b) = (runs(a), runs(b));
+    pad(&mut a, base_len(&b));
+    pad(&mut b, base_len(&a));
+    let (mut a2, mut b2) = (Vec::new(), Vec::new());
+    let (mut a, mut b) = (a.into_iter(), b.into_iter());
+    let (mut x, mut y) = (a.next(), b.next());
+    loop {
+        match (x.take(), y.take()) {
+            (None, None) => break,
+            (Some(Run::Insert(text)), rest) => {
+                push(&mut b2, Run::Retain(text.len()));
+                push(&mut a2, Run::Insert(text));
+                (x, y) = (a.next(), rest);
+            }
+            (rest, Some(Run::Insert(text))) => {
+                push(&mut a2, Run::Retain(text.len()));
+                push(&mut b2, Run::Insert(text));
+                (x, y) = (rest, b.next());
+            }
+            (Some(run_a), Some(run_b)) => {
+                let n = run_len(&run_a).min(run_len(&run_b));
+                match (&run_a, &run_b) {
+                    (Run::Retain(_), Run::Retain(_)) => {
+                        push(&mut a2, Run::Retain(n));
+                        push(&mut b2, Run::Retain(n));
+                    }
+                    (Run::Delete(_), Run::Retain(_)) => push(&mut a2, Run::Delete(n)),
+                    (Run::Retain(_), Run::Delete(_)) => push(&mut b2, Run::Delete(n)),
+                    // Both deleted it; nothing left to do on either side.
+                    _ => {}
+                }
+                x = rest_of(run_a, n).or_else(|| a.next());
+                y = rest_of(run_b, n).or_else(|| b.next());
+            }
+            // Both sides span the same (padded) document.
+            (Some(_), None) | (None, Some(_)) => unreachable!("runs cover the same length"),
+        }
+    }
+    (to_ops(a2), to_ops(b2))
+}
+
+/// One batch with the effect of `a` followed by `b`.
+pub fn compose(a: &[EditOp], b: &[EditOp]) -> Vec<EditOp> {
+    to_ops(runs(&[a, b].concat()))
+}
+
+/// The batch that undoes `ops`, which were applied to `base`.
+pub fn invert(ops: &[EditOp], base: &str) -> Result<Vec<EditOp>, CoreError> {
+    validate_ops(utf16_len(base), ops)?;
+    let base: Vec<u16> = base.encode_utf16().collect();
+    let mut at = 0;
+    let mut out = Vec::new();
+    for run in runs(&floor_ops(&base, ops)) {
+        match run {
+            Run::Retain(n) => {
+                push(&mut out, Run::Retain(n));
+                at += n;
+            }
+            Run::Insert(text) => push(&mut out, Run::Delete(text.len())),
+            Run::Delete(n) => {
+                push(&mut out, Run::Insert(base[at..at + n].to_vec()));
+                at += n;
+            }
+        }
+    }
+    Ok(to_ops(out))
+}
+
+#[wasm_bindgen(js_name = transformOps)]
+pub fn transform_js(a: JsValue, b: JsValue) -> Result<JsValue, JsValue> {
+    let (a, b): (Vec<EditOp>, Vec<EditOp>) = (serde_wasm_bindgen::from_value(a)?, serde_wasm_bindgen::from_value(b)?);
+    Ok(serde_wasm_bindgen::to_value(&transform(&a, &b))?)
+}
+
+#[wasm_bindgen(js_name = composeOps)]
+pub fn compose_js(a: JsValue, b: JsValue) -> Result<JsValue, JsValue> {
+    let (a, b): (Vec<EditOp>, Vec<EditOp>) = (serde_wasm_bindgen::from_value(a)?, serde_wasm_bindgen::from_value(b)?);
+    Ok(serde_wasm_bindgen::to_value(&compose(&a, &b))?)
+}
+
+#[wasm_bindgen(js_name = invertOps)]
+pub fn invert_js(ops: JsValue, base: &str) -> Result<JsValue, JsValue> {
+    let ops: Vec<EditOp> = serde_wasm_bindgen::from_value(ops)?;
+    Ok(serde_wasm_bindgen::to_value(&invert(&ops, base)?)?)
+}
+
+/// A piece of the document while a batch is replayed: original text kept
+/// or deleted, or text the batch inserted.
+enum Piece {
+    Kept(usize),
+    Gone(usize),
+    Added(Vec<u16>),
+}
+
+impl Piece {
+    fn visible(&self) -> usize {
+        match self {
+            Piece::Kept(n) => *n,
+            Piece::Gone(_) => 0,
+            Piece::Added(text) => text.len(),
+        }
+    }
+}
+
+/// Replay `ops` over a document of unknown length and read off the runs.
+fn runs(ops: &[EditOp]) -> Vec<Run> {
+    let mut pieces = Vec::new();
+    for op in ops {
+        match op {
+            EditOp::Ins { at, text } => {
+                let i = cut(&mut pieces, *at);
+                pieces.insert(i, Piece::Added(text.encode_utf16().collect()));
+            }
+            EditOp::Del { at, len } => {
+                let start = cut(&mut pieces, *at);
+                let end = cut(&mut pieces, at + len);
+                let removed: Vec<Piece> = pieces.drain(start..end).collect();
+                let kept = removed.into_iter().filter_map(|piece| match piece {
+                    Piece::Kept(n) | Piece::Gone(n) => Some(Piece::Gone(n)),
+                    Piece::Added(_) => None,
+                });
+                pieces.splice(start..start, kept.collect::<Vec<_>>());
+            }
+        }
+    }
+    let mut out = Vec::new();
+    for piece in pieces {
+        push(
+            &mut out,
+            match piece {
+                Piece::Kept(n) => Run::Retain(n),
+                Piece::Gone(n) => Run::Delete(n),
+                Piece::Added(text) => Run::Insert(text),
+            },
+        );
+    }
+    if let Some(Run::Retain(_)) = out.last() {
+        out.pop();
+    }
+    out
+}
+
+/// Split `pieces` so one starts at visible offset `at`, growing the kept
+/// tail if the document has to be longer; returns its index.
+fn cut(pieces: &mut Vec<Piece>, at: usize) -> usize {
+    let mut offset = 0;
+    for i in 0..pieces.len() {
+        if offset == at {
+            return i;
+        }
+        let len = pieces[i].visible();
+        if at < offset + len {
+            let k = at - offset;
+            let tail = match &mut pieces[i] {
+                Piece::Kept(n) => {
+                    let tail = Piece::Kept(*n - k);
+                    *n = k;
+                    tail
+                }
+                Piece::Added(text) => match floor_boundary(text, k) {
+                    0 => return i,
+                    k => Piece::Added(text.split_off(k)),
+                },
+                Piece::Gone(_) => unreachable!("deleted pieces take no room"),
+            };
+            pieces.insert(i + 1, tail);
+            return i + 1;
+        }
+        offset += len;
+    }
+    if offset < at {
+        pieces.push(Piece::Kept(at - offset));
+    }
+    pieces.len()
+}
+
+/// `at` moved back out of a surrogate pair of `text`.
+fn floor_boundary(text: &[u16], at: usize) -> usize {
+    let splits_pair = |i: usize| (0xD800..0xDC00).contains(&text[i - 1]) && (0xDC00..0xE000).contains(&text[i]);
+    if 0 < at && at < text.len() && splits_pair(at) {
+        at - 1
+    } else {
+        at
+    }
+}
+
+/// `ops`, checked against `base`, with offsets into a surrogate pair
+/// moved back before it as they are when applied.
+fn floor_ops(base: &[u16], ops: &[EditOp]) -> Vec<EditOp> {
+    let mut text = base.to_vec();
+    let mut out = Vec::with_capacity(ops.len());
+    for op in ops {
+        match op {
+            EditOp::Ins { at, text: s } => {
+                let at = floor_boundary(&text, *at);
+                text.splice(at..at, s.encode_utf16());
+                out.push(EditOp::Ins { at, text: s.clone() });
+            }
+            EditOp::Del { at, len } => {
+                let (start, end) = (floor_boundary(&text, *at), floor_boundary(&text, at + len));
+                text.drain(start..end);
+                out.push(EditOp::Del { at: start, len: end - start });
+            }
+        }
+    }
+    out
+}
+
+/// Append a run, merging it into the last one of the same kind.
+fn push(runs: &mut Vec<Run>, run: Run) {
+    if run_len(&run) == 0 {
+        return;
+    }
+    match (runs.last_mut(), run) {
+        (Some(Run::Retain(n)), Run::Retain(m)) | (Some(Run::Delete(n)), Run::Delete(m)) => *n += m,
+        (Some(Run::Insert(text)), Run::Insert(more)) => text.extend(more),
+        (_, run) => runs.push(run),
+    }
+}
+
+fn run_len(run: &Run) -> usize {
+    match run {
+        Run::Retain(n) | Run::Delete(n) => *n,
+        Run::Insert(text) => text.len(),
+    }
+}
+
+/// What is left of `run` after its first `n` units.
+fn rest_of(run: Run, n: usize) -> Option<Run> {
+    let rest = match run {
+        Run::Retain(len) => Run::Retain(len - n),
+        Run::Delete(len) => Run::Delete(len - n),
+        Run::Insert(text) => Run::Insert(text[n..].to_vec()),
+    };
+    (run_len(&rest) > 0).then_some(rest)
+}
+
+fn base_len(runs: &[Run]) -> usize {
+    runs.iter().map(|run| match run {
+        Run::Retain(n) | Run::Delete(n) => *n,
+        Run::Insert(_) => 0,
+    }).sum()
+}
+
+/// Retain up to a base length of `len` (runs end with an implicit retain).
+fn pad(runs: &mut Vec<Run>, len: usize) {
+    let have = base_len(runs);
+    if have < len {
+        push(runs, Run::Retain(len - have));
+    }
+}
+
+/// Runs back to `EditOp`s, each addressing the text as left by the ones
+/// before it.
+fn to_ops(runs: Vec<Run>) -> Vec<EditOp> {
+    let mut at = 0;
+    let mut out = Vec::new();
+    for run in runs {
+        match run {
+            Run::Retain(n) => at += n,
+            Run::Insert(text) => {
+                let len = text.len();
+                // Whole pairs only (see `cut`), so nothing is lost here.
+                out.push(EditOp::Ins { at, text: String::from_utf16_lossy(&text) });
+                at += len;
+            }
+            Run::Delete(len) => out.push(EditOp::Del { at, len }),
+        }
+    }
+    out
+}
@@ -0,0 +1,162 @@
+//! Find-and-replace on top of `search.rs`.
+//!
+//! In regex mode the replacement is a template: `$1` / `${1}` for numbered
+//! groups, `$name` / `${name}` for named ones, `$$` for a literal `$` (the
+//! `regex` crate's `Captures::expand` syntax; groups always expand to
+//! document text, also under `normalize`). Literal queries insert the
+//! replacement as is. With `preserveCase`, each replacement takes the case
+//! of the text it replaces: `FOO` → `BAR`, `foo` → `bar`, `Foo` → `Bar`.
+//!
+//! Both calls run as one transaction, so a replace-all is one undo unit
+//! and one change record however many matches it hits.
+
+use std::ops::Range;
+
+use regex::Captures;
+use wasm_bindgen::prelude::*;
+
+use crate::ops::{utf16_len, TextRange};
+use crate::search::{search_options, to_bytes, to_utf16, Haystack, Matcher, SearchOptions};
+use crate::{CoreError, CoreText, Unit};
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Replace the first match at or after `from`. Returns the `{ start,
+    /// end }` of the inserted text, or `undefined` if nothing matched.
+    #[wasm_bindgen(js_name = replace)]
+    pub fn replace_js(&mut self, query: &str, replacement: &str, options: JsValue, from: usize) -> Result<JsValue, JsValue> {
+        let opts = search_options(options)?;
+        Ok(serde_wasm_bindgen::to_value(&self.replace(query, replacement, &opts, from)?)?)
+    }
+
+    /// Replace every match; returns how many were replaced.
+    #[wasm_bindgen(js_name = replaceAll)]
+    pub fn replace_all_js(&mut self, query: &str, replacement: &str, options: JsValue) -> Result<usize, JsValue> {
+        let opts = search_options(options)?;
+        Ok(self.replace_all(query, replacement, &opts)?)
+    }
+}
+
+impl CoreText {
+    pub fn replace(&mut self, query: &str, replacement: &str, opts: &SearchOptions, from: usize) -> Result<Option<TextRange>, CoreError> {
+        let from = self.check_index(Unit::Utf16, from, self.rope.len_utf16())?;
+        let matcher = Matcher::new(query, opts)?;
+        let (text, hay) = (self.rope.text(), Haystack::new(&self.rope, &matcher));
+        let Some(m) = matcher.next(&hay, hay.of_doc(to_bytes(&self.rope, from).end)) else {
+            return Ok(None);
+        };
+        let with = expand(&matcher, &text, &hay, m.clone(), replacement, opts);
+        let m = hay.to_doc(m);
+        let start = to_utf16(&self.rope, m.start);
+        let end = to_utf16(&self.rope, m.end);
+        drop((text, hay));
+        self.apply_replacements(vec![(start, end, with.clone())]);
+        Ok(Some(TextRange { start, end: start + utf16_len(&with) }))
+    }
+
+    pub fn replace_all(&mut self, query: &str, replacement: &str, opts: &SearchOptions) -> Result<usize, CoreError> {
+        let matcher = Matcher::new(query, opts)?;
+        let (text, hay) = (self.rope.text(), Haystack::new(&self.rope, &matcher));
+        let edits: Vec<(usize, usize, String)> = matcher
+            .iter(&hay, 0)
+            .map(|m| {
+                let with = expand(&matcher, &text, &hay, m.clone(), replacement, opts);
+                let m = hay.to_doc(m);
+                (to_utf16(&self.rope, m.start), to_utf16(&self.rope, m.end), with)
+            })
+            .collect();
+        let count = edits.len();
+        drop((text, hay));
+        self.apply_replacements(edits);
+        Ok(count)
+    }
+
+    /// Apply non-overlapping replacements, given in document order, as one
+    /// transaction, last to first so none shifts another. Callers drop
+    /// their handle on `Buffer::text` first, or the String backend would
+    /// copy the document on the first splice.
+    fn apply_replacements(&mut self, edits: Vec<(usize, usize, String)>) {
+        if edits.is_empty() {
+            return;
+        }
+        self.begin();
+        for (start, end, text) in edits.iter().rev() {
+            self.splice_utf16(*start, *end, text);
+        }
+        let _ = self.commit();
+    }
+}
+
+/// The text to put in place of haystack match `m`; `text` is the document.
+fn expand(matcher: &Matcher, text: &str, hay: &Haystack, m: Range<usize>, template: &str, opts: &SearchOptions) -> String {
+    let mut with = String::new();
+    let caps = if opts.regex { matcher.regex().captures_at(hay.text(), m.start) } else { None };
+    match caps {
+        Some(caps) => expand_groups(template, &caps, |g| &text[hay.to_doc(g)], &mut with),
+        None => with.push_str(template),
+    }
+    if opts.preserve_case {
+        with = match_case(&text[hay.to_doc(m)], &with);
+    }
+    with
+}
+
+/// `Captures::expand`, with each group's text looked up by `group`.
+fn expand_groups<'t>(template: &str, caps: &Captures, group: impl Fn(Range<usize>) -> &'t str, out: &mut String) {
+    let mut rest = template;
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", rest),
+            },
+            None => {
+                let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        if name.is_empty() {
+            out.push('$');
+            continue;
+        }
+        let m = match name.parse::<usize>() {
+            Ok(i) => caps.get(i),
+            Err(_) => caps.name(name),
+        };
+        if let Some(m) = m {
+            out.push_str(group(m.range()));
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+}
+
+/// `replacement` in the case of `matched`: all caps, all lowercase or a
+/// leading capital carry over; mixed case leaves it as typed.
+fn match_case(matched: &str, replacement: &str) -> String {
+    let Some(first) = matched.chars().find(|c| c.is_alphabetic()) else {
+        return replacement.to_string();
+    };
+    let (upper, lower) = (matched.chars().any(char::is_uppercase), matched.chars().any(char::is_lowercase));
+    if upper && !lower {
+        return replacement.to_uppercase();
+    }
+    if lower && !upper {
+        return replacement.to_lowercase();
+    }
+    if first.is_uppercase() && !matched.chars().skip_while(|c| !c.is_alphabetic()).skip(1).any(char::is_uppercase) {
+        let mut chars = replacement.chars();
+        return match chars.next() {
+            Some(c) => c.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+    }
+    replacement.to_string()
+}
@@ -0,0 +1,349 @@
+//! Find in document — replaces `getText()` + `String.indexOf` in the
+//! editor's find bar.
+//!
+//! Queries are literal text or regular expressions (Rust `regex` syntax,
+//! `^`/`$` matching at line breaks), optionally case-insensitive and/or
+//! whole-word. They run over the buffer's text in one piece (see
+//! `Buffer::text`), so a match can straddle rope chunks, and lines too if
+//! the pattern allows it. Offsets are UTF-16.
+//!
+//! `find` steps to the nearest match forward or backward from an offset,
+//! `findAll` returns one page of matches plus where the next page starts,
+//! and `searchCursor` hands out a lazy `SearchCursor`. A cursor searches the
+//! text as it was when it was made, like a `Snapshot`.
+//!
+//! Matches never overlap. An empty match (possible with patterns like
+//! `x*`) is reported once, and the scan resumes one char past it.
+//!
+//! With `normalize`, queries run over the folded text from `fold.rs`
+//! instead (no case, no accents: `resume` finds `Résumé`, `STRASSE` finds
+//! `straße`) and matches are mapped back to document offsets. A literal
+//! query is folded the same way; a regex is used as written, so it should
+//! be written in lowercase and without accents.
+
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use std::rc::Rc;
+
+use crate::buffer::Buffer;
+use crate::fold::{fold_str, Folded};
+use crate::ops::TextRange;
+use crate::{CoreError, CoreText, Unit};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchOptions {
+    /// Treat the query as a regular expression rather than literal text.
+    pub regex: bool,
+    pub case_sensitive: bool,
+    /// Skip matches with a letter, digit or `_` right before or after them.
+    pub whole_word: bool,
+    /// Replace only: match the case of each replaced text (see `replace.rs`).
+    pub preserve_case: bool,
+    /// Ignore case and accents (see the module docs); implies case-insensitive.
+    pub normalize: bool,
+}
+
+/// One page of `findAll` results.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SearchPage {
+    pub matches: Vec<TextRange>,
+    /// Where to resume for the next page, or `None` after the last match.
+    pub next: Option<usize>,
+}
+
+/// A compiled query.
+#[derive(Clone, Debug)]
+pub(crate) struct Matcher {
+    re: Regex,
+    whole_word: bool,
+    normalize: bool,
+    /// An empty literal, which matches nothing.
+    never: bool,
+}
+
+impl Matcher {
+    pub(crate) fn new(query: &str, opts: &SearchOptions) -> Result<Matcher, CoreError> {
+        let pattern = match (opts.regex, opts.normalize) {
+            (true, _) => query.to_string(),
+            (false, false) => regex::escape(query),
+            (false, true) => regex::escape(&fold_str(query)),
+        };
+        let re = RegexBuilder::new(&pattern)
+            .case_insensitive(!opts.case_sensitive || opts.normalize)
+            .multi_line(true)
+            .build()
+            .map_err(|e| CoreError::InvalidPattern { message: e.to_string() })?;
+        Ok(Matcher {
+            re,
+            whole_word: opts.whole_word,
+            normalize: opts.normalize,
+            never: pattern.is_empty() && !opts.regex,
+        })
+    }
+
+    pub(crate) fn regex(&self) -> &Regex {
+        &self.re
+    }
+
+    /// First match starting at or after byte `from`.
+    pub(crate) fn next(&self, hay: &Haystack, from: usize) -> Option<Range<usize>> {
+        let text = hay.text();
+        let mut at = from;
+        while !self.never && at <= text.len() {
+            let m = self.re.find_at(text, at)?;
+            if !self.whole_word || is_whole_word(text, m.range()) {
+                return Some(m.range());
+            }
+            at = char_after(text, m.start());
+        }
+        None
+    }
+
+    /// Matches from byte `from` on, in order.
+    pub(crate) fn iter<'a>(&'a self, hay: &'a Haystack, from: usize) -> impl Iterator<Item = Range<usize>> + 'a {
+        let mut at = from;
+        std::iter::from_fn(move || {
+            let m = self.next(hay, at)?;
+            at = hay.resume_after(&m);
+            Some(m)
+        })
+    }
+
+    /// Last match ending at or before byte `before`, leaving out an empty
+    /// match at `before` itself when `strict`.
+    ///
+    /// Regexes only scan forward, and where a scan starts decides which
+    /// matches it finds (`aa` in `aaa` is at 0 from the start but at 1 from
+    /// byte 1), so this walks the same sequence as `iter` from the start of
+    /// the text: backward steps land on exactly the matches forward ones
+    /// do, at the cost of a scan up to `before`.
+    pub(crate) fn prev(&self, hay: &Haystack, before: usize, strict: bool) -> Option<Range<usize>> {
+        self.iter(hay, 0)
+            .take_while(|m| m.end <= before)
+            .filter(|m| !(strict && m.start == before))
+            .last()
+    }
+}
+
+/// The text a query runs over, and how its bytes map to the document's.
+pub(crate) enum Haystack {
+    Plain(Rc<String>),
+    Folded(Rc<Folded>),
+}
+
+impl Haystack {
+    pub(crate) fn new(rope: &Buffer, matcher: &Matcher) -> Haystack {
+        match matcher.normalize {
+            false => Haystack::Plain(rope.text()),
+            true => Haystack::Folded(rope.folded()),
+        }
+    }
+
+    pub(crate) fn text(&self) -> &str {
+        match self {
+            Haystack::Plain(text) => text,
+            Haystack::Folded(folded) => &folded.text,
+        }
+    }
+
+    /// Haystack byte for a document char boundary.
+    pub(crate) fn of_doc(&self, byte: usize) -> usize {
+        match self {
+            Haystack::Plain(_) => byte,
+            Haystack::Folded(folded) => folded.to_folded(byte),
+        }
+    }
+
+    /// Where the scan resumes after match `m`: one char past an empty
+    /// match, and never inside the folding of a single char, so `s` finds
+    /// `ß` once, not twice.
+    pub(crate) fn resume_after(&self, m: &Range<usize>) -> usize {
+        let end = if m.is_empty() { char_after(self.text(), m.end) } else { m.end };
+        match self {
+            Haystack::Folded(folded) if end <= folded.text.len() => folded.to_folded(folded.to_orig(end, true)),
+            _ => end,
+        }
+    }
+
+    /// Document bytes a match covers: every char that folded into it.
+    pub(crate) fn to_doc(&self, m: Range<usize>) -> Range<usize> {
+        match self {
+            Haystack::Plain(_) => m,
+            Haystack::Folded(folded) if m.is_empty() => {
+                let at = folded.to_orig(m.start, false);
+                at..at
+            }
+            Haystack::Folded(folded) => folded.to_orig(m.start, false)..folded.to_orig(m.end, true),
+        }
+    }
+}
+
+/// Byte offset of the char after the one at `at`, or past the end.
+fn char_after(text: &str, at: usize) -> usize {
+    at + text[at..].chars().next().map_or(1, char::len_utf8)
+}
+
+fn is_whole_word(text: &str, m: Range<usize>) -> bool {
+    let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    !word(text[..m.start].chars().next_back()) && !word(text[m.end..].chars().next())
+}
+
+pub(crate) fn to_utf16(rope: &Buffer, byte: usize) -> usize {
+    rope.char_to_utf16(rope.byte_to_char(byte))
+}
+
+/// Byte offsets of the char boundaries at or before and at or after a
+/// UTF-16 offset; they differ inside a surrogate pair.
+pub(crate) fn to_bytes(rope: &Buffer, utf16: usize) -> Range<usize> {
+    let ch = rope.utf16_to_char(utf16);
+    let ceil = if rope.char_to_utf16(ch) < utf16 { ch + 1 } else { ch };
+    rope.char_to_byte(ch)..rope.char_to_byte(ceil)
+}
+
+/// UTF-16 range of a haystack match.
+fn to_range(rope: &Buffer, hay: &Haystack, m: Range<usize>) -> TextRange {
+    let m = hay.to_doc(m);
+    TextRange { start: to_utf16(rope, m.start), end: to_utf16(rope, m.end) }
+}
+
+/// `find` over any buffer; `from` must be in range.
+pub(crate) fn find_in(rope: &Buffer, matcher: &Matcher, from: usize, backward: bool) -> Option<TextRange> {
+    let hay = Haystack::new(rope, matcher);
+    let from = to_bytes(rope, from);
+    let m = match backward {
+        true => matcher.prev(&hay, hay.of_doc(from.start), false),
+        false => matcher.next(&hay, hay.of_doc(from.end)),
+    };
+    m.map(|m| to_range(rope, &hay, m))
+}
+
+/// `findAll` over any buffer; `from` must be in range.
+pub(crate) fn find_all_in(rope: &Buffer, matcher: &Matcher, from: usize, limit: usize) -> SearchPage {
+    let hay = Haystack::new(rope, matcher);
+    let mut iter = matcher.iter(&hay, hay.of_doc(to_bytes(rope, from).end));
+    let found: Vec<Range<usize>> = iter.by_ref().take(limit).collect();
+    // Resume where the iterator would: one char past a trailing empty match.
+    let next = match found.last() {
+        Some(last) if iter.next().is_some() => {
+            let end = hay.to_doc(last.clone()).end;
+            Some(to_utf16(rope, if last.is_empty() { char_after(&rope.text(), end) } else { end }))
+        }
+        _ => None,
+    };
+    SearchPage { matches: found.into_iter().map(|m| to_range(rope, &hay, m)).collect(), next }
+}
+
+pub(crate) fn search_options(options: JsValue) -> Result<SearchOptions, JsValue> {
+    if options.is_undefined() || options.is_null() {
+        return Ok(SearchOptions::default());
+    }
+    Ok(serde_wasm_bindgen::from_value(options)?)
+}
+
+/// Lazy match iterator from `CoreText.searchCursor` / `Snapshot.searchCursor`.
+#[wasm_bindgen]
+pub struct SearchCursor {
+    rope: Buffer,
+    matcher: Matcher,
+    hay: Haystack,
+    /// Haystack bytes of the last match, or around the starting offset
+    /// before the first one.
+    current: Range<usize>,
+    matched: bool,
+}
+
+impl SearchCursor {
+    pub(crate) fn new(rope: Buffer, matcher: Matcher, from: usize) -> SearchCursor {
+        let hay = Haystack::new(&rope, &matcher);
+        let from = to_bytes(&rope, from);
+        let current = hay.of_doc(from.start)..hay.of_doc(from.end);
+        SearchCursor { rope, matcher, hay, current, matched: false }
+    }
+}
+
+#[wasm_bindgen]
+impl SearchCursor {
+    /// `{ start, end }` of the next match, or `undefined` past the last.
+    #[wasm_bindgen(js_name = next)]
+    pub fn next_js(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.next_match())?)
+    }
+
+    /// `{ start, end }` of the match before the current one (or before the
+    /// starting offset), or `undefined`.
+    #[wasm_bindgen(js_name = prev)]
+    pub fn prev_js(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.prev_match())?)
+    }
+}
+
+impl SearchCursor {
+    pub fn next_match(&mut self) -> Option<TextRange> {
+        let from = match self.matched {
+            true => self.hay.resume_after(&self.current),
+            false => self.current.end,
+        };
+        let m = self.matcher.next(&self.hay, from)?;
+        (self.current, self.matched) = (m.clone(), true);
+        Some(to_range(&self.rope, &self.hay, m))
+    }
+
+    pub fn prev_match(&mut self) -> Option<TextRange> {
+        let m = self.matcher.prev(&self.hay, self.current.start, self.matched)?;
+        (self.current, self.matched) = (m.clone(), true);
+        Some(to_range(&self.rope, &self.hay, m))
+    }
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Nearest match for `query`: the first starting at or after `from`,
+    /// or with `backward` the last ending at or before it. `{ start, end }`
+    /// or `undefined`. `options`: `{ regex, caseSensitive, wholeWord,
+    /// normalize }`, all off by default (`preserveCase` only affects
+    /// replacing).
+    #[wasm_bindgen(js_name = find)]
+    pub fn find_js(&self, query: &str, options: JsValue, from: usize, backward: Option<bool>) -> Result<JsValue, JsValue> {
+        let opts = search_options(options)?;
+        Ok(serde_wasm_bindgen::to_value(&self.find(query, &opts, from, backward.unwrap_or(false))?)?)
+    }
+
+    /// Up to `limit` matches (default: all) starting at or after `from`
+    /// (default 0), as `{ matches, next }`; pass `next` back as `from` for
+    /// the following page.
+    #[wasm_bindgen(js_name = findAll)]
+    pub fn find_all_js(&self, query: &str, options: JsValue, from: Option<usize>, limit: Option<usize>) -> Result<JsValue, JsValue> {
+        let opts = search_options(options)?;
+        let page = self.find_all(query, &opts, from.unwrap_or(0), limit.unwrap_or(usize::MAX))?;
+        Ok(serde_wasm_bindgen::to_value(&page)?)
+    }
+
+    /// A `SearchCursor` positioned at `from` (default 0) over the current text.
+    #[wasm_bindgen(js_name = searchCursor)]
+    pub fn search_cursor_js(&self, query: &str, options: JsValue, from: Option<usize>) -> Result<SearchCursor, JsValue> {
+        let opts = search_options(options)?;
+        Ok(self.search_cursor(query, &opts, from.unwrap_or(0))?)
+    }
+}
+
+impl CoreText {
+    pub fn find(&self, query: &str, opts: &SearchOptions, from: usize, backward: bool) -> Result<Option<TextRange>, CoreError> {
+        let from = self.check_index(Unit::Utf16, from, self.rope.len_utf16())?;
+        Ok(find_in(&self.rope, &Matcher::new(query, opts)?, from, backward))
+    }
+
+    pub fn find_all(&self, query: &str, opts: &SearchOptions, from: usize, limit: usize) -> Result<SearchPage, CoreError> {
+        let from = self.check_index(Unit::Utf16, from, self.rope.len_utf16())?;
+        Ok(find_all_in(&self.rope, &Matcher::new(query, opts)?, from, limit))
+    }
+
+    pub fn search_cursor(&self, query: &str, opts: &SearchOptions, from: usize) -> Result<SearchCursor, CoreError> {
+        let from = self.check_index(Unit::Utf16, from, self.rope.len_utf16())?;
+        Ok(SearchCursor::new(self.rope.clone(), Matcher::new(query, opts)?, from))
+    }
+}
@@ -0,0 +1,229 @@
+//! Multi-cursor selections.
+//!
+//! A `CoreText` carries a selection set: ranges with an `anchor` (where the
+//! selection started) and a `head` (where the caret is), in UTF-16 offsets,
+//! kept sorted and non-overlapping. Ranges that overlap, or a caret that
+//! touches another range, merge into one. Every edit remaps the set the
+//! same way decorations are remapped: text typed at either edge of a range
+//! lands outside it, and a caret moves past text inserted at it.
+//!
+//! `insertText`, `paste`, `deleteBackward` and `deleteForward` act at every
+//! selection at once (one undo unit, one change record), leaving a caret
+//! after each edit. With a single caret they are plain edits to the undo
+//! history, so typing through `insertText` coalesces into runs just like
+//! `insertUtf16`.
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::ops::Splice;
+use crate::{CoreError, CoreText, Unit};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn caret(offset: usize) -> Selection {
+        Selection { anchor: offset, head: offset }
+    }
+
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// Same direction as `self`, spanning `start..end`.
+    fn with_range(&self, start: usize, end: usize) -> Selection {
+        if self.head < self.anchor {
+            Selection { anchor: end, head: start }
+        } else {
+            Selection { anchor: start, head: end }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Selections {
+    list: Vec<Selection>,
+}
+
+impl Default for Selections {
+    fn default() -> Self {
+        Selections { list: vec![Selection::caret(0)] }
+    }
+}
+
+impl Selections {
+    fn set(&mut self, mut list: Vec<Selection>) {
+        list.sort_by_key(|s| (s.start(), s.end()));
+        let mut merged: Vec<Selection> = Vec::with_capacity(list.len());
+        for sel in list {
+            if let Some(last) = merged.last_mut() {
+                let touching = last.end() == sel.start() && (last.is_empty() || sel.is_empty());
+                if last.end() > sel.start() || touching {
+                    // A caret swallowed by a range takes on the range's direction.
+                    let base = if last.is_empty() { sel } else { *last };
+                    *last = base.with_range(last.start(), last.end().max(sel.end()));
+                    continue;
+                }
+            }
+            merged.push(sel);
+        }
+        if merged.is_empty() {
+            merged.push(Selection::caret(0));
+        }
+        self.list = merged;
+    }
+
+    pub(crate) fn apply(&mut self, edit: &Splice) {
+        let (start, old_end) = (edit.start, edit.start + edit.removed);
+        let map = |p: usize, after: bool| {
+            if p < start || (p == start && !after) {
+                p
+            } else if p > old_end || (p == old_end && after) {
+                p - edit.removed + edit.inserted
+            } else if after {
+                start + edit.inserted
+            } else {
+                start
+            }
+        };
+        let list = self
+            .list
+            .iter()
+            .map(|sel| {
+                let from = map(sel.start(), true);
+                sel.with_range(from, map(sel.end(), false).max(from))
+            })
+            .collect();
+        self.set(list);
+    }
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Current selections as `{ anchor, head }[]`, sorted by position.
+    #[wasm_bindgen(js_name = selections)]
+    pub fn selections_js(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.selections())?)
+    }
+
+    /// Replace the selection set; ranges are sorted and merged.
+    #[wasm_bindgen(js_name = setSelections)]
+    pub fn set_selections_js(&mut self, selections: JsValue) -> Result<(), JsValue> {
+        let list: Vec<Selection> = serde_wasm_bindgen::from_value(selections)?;
+        Ok(self.set_selections(&list)?)
+    }
+
+    #[wasm_bindgen(js_name = addSelection)]
+    pub fn add_selection(&mut self, anchor: usize, head: usize) -> Result<(), CoreError> {
+        let mut list = self.selections.list.clone();
+        list.push(self.check_selection(Selection { anchor, head })?);
+        self.selections.set(list);
+        Ok(())
+    }
+
+    /// Collapse to a single caret.
+    #[wasm_bindgen(js_name = setCursor)]
+    pub fn set_cursor(&mut self, offset: usize) -> Result<(), CoreError> {
+        self.set_selections(&[Selection::caret(offset)])
+    }
+
+    /// Type `text` at every selection, replacing what each one covers.
+    #[wasm_bindgen(js_name = insertText)]
+    pub fn insert_text(&mut self, text: &str) {
+        self.edit_selections(|_, sel, _| Some((sel.start(), sel.end(), text.to_string())));
+    }
+
+    /// Paste `text` at every selection. When it has exactly one line per
+    /// selection, each selection gets its own line instead.
+    #[wasm_bindgen(js_name = paste)]
+    pub fn paste(&mut self, text: &str) {
+        let lines: Vec<&str> = text.split('\n').collect();
+        let spread = self.selections.list.len() > 1 && lines.len() == self.selections.list.len();
+        self.edit_selections(|_, sel, i| {
+            let text = if spread { lines[i] } else { text };
+            Some((sel.start(), sel.end(), text.to_string()))
+        });
+    }
+
+    /// Backspace at every selection: delete it, or the grapheme cluster
+    /// before a caret.
+    #[wasm_bindgen(js_name = deleteBackward)]
+    pub fn delete_backward(&mut self) {
+        self.edit_selections(|text, sel, _| match sel.is_empty() {
+            false => Some((sel.start(), sel.end(), String::new())),
+            true if sel.head == 0 => None,
+            true => Some((text.prev_grapheme_utf16(sel.head), sel.head, String::new())),
+        });
+    }
+
+    /// Forward delete at every selection: delete it, or the grapheme cluster
+    /// after a caret.
+    #[wasm_bindgen(js_name = deleteForward)]
+    pub fn delete_forward(&mut self) {
+        self.edit_selections(|text, sel, _| match sel.is_empty() {
+            false => Some((sel.start(), sel.end(), String::new())),
+            true if sel.head == text.rope.len_utf16() => None,
+            true => Some((sel.head, text.next_grapheme_utf16(sel.head), String::new())),
+        });
+    }
+}
+
+impl CoreText {
+    pub fn selections(&self) -> Vec<Selection> {
+        self.selections.list.clone()
+    }
+
+    pub fn set_selections(&mut self, list: &[Selection]) -> Result<(), CoreError> {
+        let list = list.iter().map(|sel| self.check_selection(*sel)).collect::<Result<_, _>>()?;
+        self.selections.set(list);
+        Ok(())
+    }
+
+    fn check_selection(&self, sel: Selection) -> Result<Selection, CoreError> {
+        let len = self.rope.len_utf16();
+        Ok(Selection {
+            anchor: self.check_index(Unit::Utf16, sel.anchor, len)?,
+            head: self.check_index(Unit::Utf16, sel.head, len)?,
+        })
+    }
+
+    /// Apply one replacement per selection (`f` gets the selection and its
+    /// index) as a single change. Replacements are worked out against the
+    /// text as it is now and applied last to first, so none of them shifts
+    /// another; the splices themselves move the carets. Several of them are
+    /// grouped into one undo unit; a lone one is left to the typing-run
+    /// merge.
+    fn edit_selections(&mut self, mut f: impl FnMut(&CoreText, Selection, usize) -> Option<(usize, usize, String)>) {
+        let mut edits: Vec<(usize, usize, String)> = Vec::new();
+        for (i, sel) in self.selections.list.iter().enumerate() {
+            if let Some((start, end, text)) = f(self, *sel, i) {
+                let floor = edits.last().map_or(0, |e| e.1);
+                edits.push((start.max(floor), end.max(floor), text));
+            }
+        }
+        let grouped = edits.len() > 1;
+        if grouped {
+            self.history.begin_group();
+        }
+        self.changes.open();
+        for (start, end, text) in edits.iter().rev() {
+            self.splice_utf16(*start, *end, text);
+        }
+        self.changes.close();
+        if grouped {
+            self.history.end_group();
+        }
+    }
+}
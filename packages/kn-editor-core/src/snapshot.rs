@@ -0,0 +1,149 @@
+//! Immutable document snapshots.
+//!
+//! `CoreText::snapshot` replaces the full-string `EditorSnapshot` that
+//! `EditorModel` rebuilds on every change. A `Snapshot` shares storage with
+//! the live buffer (see `buffer.rs`), so taking one is O(1) and it stays
+//! readable however the document moves on. Its memory goes when JS calls
+//! `free()` on the handle (or it is garbage collected).
+
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::Buffer;
+use crate::diff::{diff, diff_options, DiffOptions};
+use crate::ops::{EditOp, TextRange};
+use crate::search::{find_all_in, find_in, search_options, Matcher, SearchCursor, SearchOptions, SearchPage};
+use crate::{CoreError, CoreText, Unit};
+
+#[wasm_bindgen]
+pub struct Snapshot {
+    rope: Buffer,
+    version: u64,
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Freeze the current text at the current `version`.
+    #[wasm_bindgen(js_name = snapshot)]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot { rope: self.rope.clone(), version: self.changes.version() }
+    }
+}
+
+#[wasm_bindgen]
+impl Snapshot {
+    /// Document version this snapshot was taken at.
+    #[wasm_bindgen(js_name = version)]
+    pub fn version(&self) -> f64 {
+        self.version as f64
+    }
+
+    #[wasm_bindgen(js_name = lenChars)]
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    #[wasm_bindgen(js_name = lenUtf16)]
+    pub fn len_utf16(&self) -> usize {
+        self.rope.len_utf16()
+    }
+
+    #[wasm_bindgen(js_name = lenLines)]
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    #[wasm_bindgen(js_name = slice)]
+    pub fn slice(&self, start_char: usize, end_char: usize) -> Result<String, CoreError> {
+        let (start, end) = check_range(Unit::Char, start_char, end_char, self.rope.len_chars())?;
+        Ok(self.rope.slice(start, end))
+    }
+
+    #[wasm_bindgen(js_name = sliceUtf16)]
+    pub fn slice_utf16(&self, start_utf16: usize, end_utf16: usize) -> Result<String, CoreError> {
+        let (start, end) = check_range(Unit::Utf16, start_utf16, end_utf16, self.rope.len_utf16())?;
+        Ok(self.rope.slice_utf16(start, end))
+    }
+
+    /// The whole text.
+    #[wasm_bindgen(js_name = toString)]
+    pub fn text(&self) -> String {
+        self.rope.slice(0, self.rope.len_chars())
+    }
+
+    /// UTF-16 offset of the first occurrence of `needle` at or after
+    /// `from_utf16`, or `undefined`.
+    #[wasm_bindgen(js_name = indexOf)]
+    pub fn index_of(&self, needle: &str, from_utf16: usize) -> Result<Option<usize>, CoreError> {
+        let from = check_range(Unit::Utf16, from_utf16, from_utf16, self.rope.len_utf16())?.0;
+        let from = self.rope.utf16_to_char(from);
+        let rest = self.rope.slice(from, self.rope.len_chars());
+        Ok(rest.find(needle).map(|byte| {
+            let ch = from + rest[..byte].chars().count();
+            self.rope.char_to_utf16(ch)
+        }))
+    }
+
+    /// As `CoreText.find`, over this snapshot.
+    #[wasm_bindgen(js_name = find)]
+    pub fn find_js(&self, query: &str, options: JsValue, from: usize, backward: Option<bool>) -> Result<JsValue, JsValue> {
+        let opts = search_options(options)?;
+        Ok(serde_wasm_bindgen::to_value(&self.find(query, &opts, from, backward.unwrap_or(false))?)?)
+    }
+
+    /// As `CoreText.findAll`, over this snapshot.
+    #[wasm_bindgen(js_name = findAll)]
+    pub fn find_all_js(&self, query: &str, options: JsValue, from: Option<usize>, limit: Option<usize>) -> Result<JsValue, JsValue> {
+        let opts = search_options(options)?;
+        let page = self.find_all(query, &opts, from.unwrap_or(0), limit.unwrap_or(usize::MAX))?;
+        Ok(serde_wasm_bindgen::to_value(&page)?)
+    }
+
+    #[wasm_bindgen(js_name = searchCursor)]
+    pub fn search_cursor_js(&self, query: &str, options: JsValue, from: Option<usize>) -> Result<SearchCursor, JsValue> {
+        let opts = search_options(options)?;
+        Ok(self.search_cursor(query, &opts, from.unwrap_or(0))?)
+    }
+
+    /// Ops that turn this snapshot into `next`; options as for `diffText`.
+    #[wasm_bindgen(js_name = diffTo)]
+    pub fn diff_to_js(&self, next: &Snapshot, options: JsValue) -> Result<JsValue, JsValue> {
+        let opts = diff_options(options)?;
+        Ok(serde_wasm_bindgen::to_value(&self.diff_to(next, &opts))?)
+    }
+}
+
+impl Snapshot {
+    pub(crate) fn new(rope: Buffer, version: u64) -> Snapshot {
+        Snapshot { rope, version }
+    }
+
+    pub fn find(&self, query: &str, opts: &SearchOptions, from: usize, backward: bool) -> Result<Option<TextRange>, CoreError> {
+        let from = check_range(Unit::Utf16, from, from, self.rope.len_utf16())?.0;
+        Ok(find_in(&self.rope, &Matcher::new(query, opts)?, from, backward))
+    }
+
+    pub fn find_all(&self, query: &str, opts: &SearchOptions, from: usize, limit: usize) -> Result<SearchPage, CoreError> {
+        let from = check_range(Unit::Utf16, from, from, self.rope.len_utf16())?.0;
+        Ok(find_all_in(&self.rope, &Matcher::new(query, opts)?, from, limit))
+    }
+
+    pub fn search_cursor(&self, query: &str, opts: &SearchOptions, from: usize) -> Result<SearchCursor, CoreError> {
+        let from = check_range(Unit::Utf16, from, from, self.rope.len_utf16())?.0;
+        Ok(SearchCursor::new(self.rope.clone(), Matcher::new(query, opts)?, from))
+    }
+
+    pub fn diff_to(&self, next: &Snapshot, opts: &DiffOptions) -> Vec<EditOp> {
+        diff(&self.text(), &next.text(), opts)
+    }
+}
+
+fn check_range(unit: Unit, start: usize, end: usize, len: usize) -> Result<(usize, usize), CoreError> {
+    if start > end {
+        return Err(CoreError::InvertedRange { start, end });
+    }
+    if end > len {
+        let index = if start > len { start } else { end };
+        return Err(CoreError::OutOfBounds { unit, index, len });
+    }
+    Ok((start, end))
+}
@@ -0,0 +1,189 @@
+//! Client side of the relay protocol: optimistic local edits rebased over
+//! what the server accepted meanwhile, on top of `ot.rs`.
+//!
+//! The server keeps a linear history; `revision` counts the batches of it
+//! this client has seen (its own, once acknowledged, and everyone else's).
+//! Local edits go to `pending` as they are made. `send` moves them in
+//! flight, one batch at a time, based on `revision`; the server answers
+//! with `ack`, or has the batch rebased over whatever it got first.
+//! Meanwhile remote batches are transformed past the in-flight and pending
+//! ops (which are transformed past them in turn) and applied to the
+//! `CoreText`. Since they land on the text as it is now, selections and
+//! anchors are remapped by the usual edit mapping: an offline session that
+//! reconnects keeps its carets where the user left them, with the server's
+//! edits moving them as needed.
+//!
+//! Ties between inserts at the same spot go to the client, as
+//! `transform(client, server)` does on the relay. Remote ops are checked
+//! strictly whatever the clamp mode and produce a change record, but no
+//! undo entry: they end the user's typing run and the undo history is
+//! rebased past them. Change records from `applyRemote` are not local
+//! edits and must not be fed back to `local`.
+//!
+//! Reconnecting: catch up with the server's batches after `revision`
+//! (`ack` for this client's in-flight one, if it made it, `applyRemote`
+//! for the rest), then `resend` what is still in flight.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::ops::EditOp;
+use crate::ot::{compose, transform};
+use crate::{CoreError, CoreText};
+
+/// A batch to send: `ops` apply to server revision `revision`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Outgoing {
+    pub revision: u64,
+    pub ops: Vec<EditOp>,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct SyncClient {
+    revision: u64,
+    /// Sent, not acknowledged yet; applies to server revision `revision`.
+    in_flight: Option<Vec<EditOp>>,
+    /// Not sent yet; applies after `in_flight`.
+    pending: Vec<EditOp>,
+}
+
+#[wasm_bindgen]
+impl SyncClient {
+    /// A client in sync with server revision `revision`.
+    #[wasm_bindgen(constructor)]
+    pub fn new_js(revision: f64) -> SyncClient {
+        SyncClient::new(revision as u64)
+    }
+
+    #[wasm_bindgen(js_name = revision)]
+    pub fn revision_js(&self) -> f64 {
+        self.revision as f64
+    }
+
+    /// Nothing in flight or pending.
+    #[wasm_bindgen(js_name = isSynced)]
+    pub fn is_synced(&self) -> bool {
+        self.in_flight.is_none() && self.pending.is_empty()
+    }
+
+    /// The ops awaiting `ack`, or `undefined`.
+    #[wasm_bindgen(js_name = inFlight)]
+    pub fn in_flight_js(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.in_flight)?)
+    }
+
+    #[wasm_bindgen(js_name = pending)]
+    pub fn pending_js(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.pending)?)
+    }
+
+    /// Record ops already applied to the document locally, e.g. the `ops`
+    /// of a change record.
+    #[wasm_bindgen(js_name = local)]
+    pub fn local_js(&mut self, ops: JsValue) -> Result<(), JsValue> {
+        let ops: Vec<EditOp> = serde_wasm_bindgen::from_value(ops)?;
+        self.local(&ops);
+        Ok(())
+    }
+
+    /// Apply local ops to `doc` and record them.
+    #[wasm_bindgen(js_name = applyLocal)]
+    pub fn apply_local_js(&mut self, doc: &mut CoreText, ops: JsValue) -> Result<(), JsValue> {
+        let ops: Vec<EditOp> = serde_wasm_bindgen::from_value(ops)?;
+        Ok(self.apply_local(doc, &ops)?)
+    }
+
+    /// Put the pending ops in flight. Returns `{ revision, ops }` to send,
+    /// or `undefined` while a batch is in flight or nothing is pending.
+    #[wasm_bindgen(js_name = send)]
+    pub fn send_js(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.send())?)
+    }
+
+    /// The in-flight batch again, for a new connection; `undefined` if none.
+    #[wasm_bindgen(js_name = resend)]
+    pub fn resend_js(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.resend())?)
+    }
+
+    /// The server accepted the in-flight batch. Returns false if nothing
+    /// was in flight.
+    #[wasm_bindgen(js_name = ack)]
+    pub fn ack(&mut self) -> bool {
+        match self.in_flight.take() {
+            Some(_) => {
+                self.revision += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply the server's next batch to `doc`. Returns the ops as applied,
+    /// i.e. rebased past the local ones.
+    #[wasm_bindgen(js_name = applyRemote)]
+    pub fn apply_remote_js(&mut self, doc: &mut CoreText, ops: JsValue) -> Result<JsValue, JsValue> {
+        let ops: Vec<EditOp> = serde_wasm_bindgen::from_value(ops)?;
+        Ok(serde_wasm_bindgen::to_value(&self.apply_remote(doc, &ops)?)?)
+    }
+}
+
+impl SyncClient {
+    pub fn new(revision: u64) -> SyncClient {
+        SyncClient { revision, ..SyncClient::default() }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn in_flight(&self) -> Option<&[EditOp]> {
+        self.in_flight.as_deref()
+    }
+
+    pub fn pending(&self) -> &[EditOp] {
+        &self.pending
+    }
+
+    pub fn local(&mut self, ops: &[EditOp]) {
+        self.pending = compose(&self.pending, ops);
+    }
+
+    pub fn apply_local(&mut self, doc: &mut CoreText, ops: &[EditOp]) -> Result<(), CoreError> {
+        doc.apply_ops(ops)?;
+        self.local(ops);
+        Ok(())
+    }
+
+    pub fn send(&mut self) -> Option<Outgoing> {
+        if self.in_flight.is_some() || self.pending.is_empty() {
+            return None;
+        }
+        self.in_flight = Some(std::mem::take(&mut self.pending));
+        self.resend()
+    }
+
+    pub fn resend(&self) -> Option<Outgoing> {
+        let ops = self.in_flight.clone()?;
+        Some(Outgoing { revision: self.revision, ops })
+    }
+
+    /// Nothing changes, on the document or here, if the rebased ops do not
+    /// apply to `doc`.
+    pub fn apply_remote(&mut self, doc: &mut CoreText, ops: &[EditOp]) -> Result<Vec<EditOp>, CoreError> {
+        let (in_flight, ops) = match &self.in_flight {
+            Some(in_flight) => {
+                let (in_flight, ops) = transform(in_flight, ops);
+                (Some(in_flight), ops)
+            }
+            None => (None, ops.to_vec()),
+        };
+        let (pending, ops) = transform(&self.pending, &ops);
+        doc.apply_remote_ops(&ops)?;
+        self.in_flight = in_flight;
+        self.pending = pending;
+        self.revision += 1;
+        Ok(ops)
+    }
+}
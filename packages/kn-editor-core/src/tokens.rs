@@ -0,0 +1,186 @@
+//! Incremental inverted index — replaces the `TokenIndexer` round trip
+//! through `tokenizer.worker.ts`.
+//!
+//! Tokens follow the worker's rule exactly: `[\p{L}\p{N}][\p{L}\p{N}_-]*`,
+//! lowercased, with positions in UTF-16 units from the start of the block.
+//! Because positions are block-relative, a block that only moved keeps its
+//! postings; the index remembers each block's hash and retokenizes only
+//! blocks whose hash changed, dropping blocks that are gone. That check
+//! runs lazily, before a query or `takeTokenDelta`, so a burst of edits
+//! costs one pass, and it needs no extra bookkeeping to follow undo,
+//! rollback or `importBlockIds`.
+//!
+//! `takeTokenDelta` reports every `(token, block)` posting that changed
+//! since the previous call; an empty `positions` means the posting is gone.
+//!
+//! `setTokenNormalization(true)` switches to the folding used by `find`
+//! with `normalize` (see `fold.rs`), so the index and find agree: tokens
+//! are folded instead of lowercased, and may carry combining marks, so a
+//! decomposed `résumé` stays one token, `resume`. Switching rebuilds the
+//! index, and the next delta says so posting by posting.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::fold::fold_str;
+use crate::ops::utf16_len;
+use crate::CoreText;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenEntry {
+    pub block_id: String,
+    pub positions: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenPosting {
+    pub token: String,
+    pub block_id: String,
+    pub positions: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TokenIndex {
+    /// block id → (hash when tokenized, tokens it holds)
+    blocks: HashMap<String, (u32, Vec<String>)>,
+    /// token → block id → positions
+    postings: HashMap<String, HashMap<String, Vec<usize>>>,
+    /// `(token, block id)` → positions, not yet taken
+    pending: HashMap<(String, String), Vec<usize>>,
+    normalize: bool,
+}
+
+impl TokenIndex {
+    fn remove_block(&mut self, id: &str) {
+        let Some((_, tokens)) = self.blocks.remove(id) else {
+            return;
+        };
+        for token in tokens {
+            if let Some(by_block) = self.postings.get_mut(&token) {
+                by_block.remove(id);
+                if by_block.is_empty() {
+                    self.postings.remove(&token);
+                }
+            }
+            self.pending.insert((token, id.to_string()), Vec::new());
+        }
+    }
+
+    fn add_block(&mut self, id: &str, hash: u32, text: &str) {
+        let tokens = tokenize(text, self.normalize);
+        let mut names = Vec::with_capacity(tokens.len());
+        for (token, positions) in tokens {
+            self.pending.insert((token.clone(), id.to_string()), positions.clone());
+            self.postings.entry(token.clone()).or_default().insert(id.to_string(), positions);
+            names.push(token);
+        }
+        self.blocks.insert(id.to_string(), (hash, names));
+    }
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// `{ blockId, positions }[]` for a token (case-insensitive), in
+    /// document order; same shape as `TokenIndex` entries in TS.
+    #[wasm_bindgen(js_name = tokenPositions)]
+    pub fn token_positions_js(&mut self, token: &str) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.token_positions(token))?)
+    }
+
+    /// Postings changed since the last call, as `{ token, blockId,
+    /// positions }[]`. The first call returns the whole index.
+    #[wasm_bindgen(js_name = takeTokenDelta)]
+    pub fn take_token_delta_js(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.take_token_delta())?)
+    }
+
+    /// Fold tokens like `find` with `normalize` (see the module docs).
+    #[wasm_bindgen(js_name = setTokenNormalization)]
+    pub fn set_token_normalization(&mut self, normalize: bool) {
+        if self.tokens.normalize == normalize {
+            return;
+        }
+        let ids: Vec<String> = self.tokens.blocks.keys().cloned().collect();
+        for id in ids {
+            self.tokens.remove_block(&id);
+        }
+        self.tokens.normalize = normalize;
+    }
+
+    #[wasm_bindgen(js_name = tokenNormalization)]
+    pub fn token_normalization(&self) -> bool {
+        self.tokens.normalize
+    }
+}
+
+impl CoreText {
+    pub fn token_positions(&mut self, token: &str) -> Vec<TokenEntry> {
+        self.sync_tokens();
+        let key = if self.tokens.normalize { fold_str(token) } else { token.to_lowercase() };
+        let Some(by_block) = self.tokens.postings.get(&key) else {
+            return Vec::new();
+        };
+        self.blocks
+            .spans()
+            .filter_map(|(id, ..)| {
+                let positions = by_block.get(id)?;
+                Some(TokenEntry { block_id: id.to_string(), positions: positions.clone() })
+            })
+            .collect()
+    }
+
+    pub fn take_token_delta(&mut self) -> Vec<TokenPosting> {
+        self.sync_tokens();
+        let mut delta: Vec<TokenPosting> = self
+            .tokens
+            .pending
+            .drain()
+            .map(|((token, block_id), positions)| TokenPosting { token, block_id, positions })
+            .collect();
+        delta.sort_by(|a, b| (&a.token, &a.block_id).cmp(&(&b.token, &b.block_id)));
+        delta
+    }
+
+    /// Bring the index in line with the current blocks.
+    fn sync_tokens(&mut self) {
+        let mut live = HashSet::new();
+        for (id, hash, start, end) in self.blocks.spans() {
+            live.insert(id);
+            if self.tokens.blocks.get(id).is_some_and(|(h, _)| *h == hash) {
+                continue;
+            }
+            self.tokens.remove_block(id);
+            let text = self.rope.slice_utf16(start, end);
+            self.tokens.add_block(id, hash, &text);
+        }
+        let gone: Vec<String> = self.tokens.blocks.keys().filter(|id| !live.contains(id.as_str())).cloned().collect();
+        for id in gone {
+            self.tokens.remove_block(&id);
+        }
+    }
+}
+
+/// Tokens of `text` with their UTF-16 positions, like `tokenizeBlock`.
+fn tokenize(text: &str, normalize: bool) -> HashMap<String, Vec<usize>> {
+    static WORD: OnceLock<Regex> = OnceLock::new();
+    static WORD_MARKS: OnceLock<Regex> = OnceLock::new();
+    let word = match normalize {
+        false => WORD.get_or_init(|| Regex::new(r"[\p{L}\p{N}][\p{L}\p{N}_-]*").unwrap()),
+        true => WORD_MARKS.get_or_init(|| Regex::new(r"[\p{L}\p{N}][\p{L}\p{M}\p{N}_-]*").unwrap()),
+    };
+    let mut tokens: HashMap<String, Vec<usize>> = HashMap::new();
+    let (mut byte, mut utf16) = (0, 0);
+    for m in word.find_iter(text) {
+        utf16 += utf16_len(&text[byte..m.start()]);
+        byte = m.start();
+        let token = if normalize { fold_str(m.as_str()) } else { m.as_str().to_lowercase() };
+        tokens.entry(token).or_default().push(utf16);
+    }
+    tokens
+}
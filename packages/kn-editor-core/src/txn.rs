@@ -0,0 +1,251 @@
+//! Transactions and change records.
+//!
+//! `begin` / `commit` / `rollback` group edits into one logical change.
+//! While a transaction is open the edits apply as usual (reads see them),
+//! the undo history collects them into one unit, and a checkpoint of the
+//! document is kept so `rollback` can put everything back. Transactions
+//! nest: an inner `commit` folds into the outer one, an inner `rollback`
+//! only undoes its own part.
+//!
+//! Every change that reaches the outside world bumps `version` once and
+//! produces a `ChangeRecord`: the ops that were applied (replayable with
+//! `applyOps` against the previous version) and the ranges they touched in
+//! the new text. Edits made outside a transaction are their own change;
+//! `applyOps`, `undo` and `redo` each count as one.
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::anchors::Anchors;
+use crate::blocks::Blocks;
+use crate::buffer::Buffer;
+use crate::decorations::Decorations;
+use crate::ops::{EditOp, Splice, TextRange};
+use crate::selections::Selections;
+use crate::{CoreError, CoreText};
+
+/// Records kept for `takeChanges`; older ones are dropped, which shows up
+/// as a gap in `version`.
+const RECORD_LIMIT: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ChangeRecord {
+    /// Document version after the change.
+    pub version: u64,
+    /// Disjoint, sorted ranges in the new text that were inserted into or
+    /// had text removed at them (a removal shows up as an empty range).
+    pub ranges: Vec<TextRange>,
+    /// Ops that turn the previous version into this one, in order.
+    pub ops: Vec<EditOp>,
+}
+
+/// State restored by `rollback`.
+pub(crate) struct Checkpoint {
+    rope: Buffer,
+    blocks: Blocks,
+    anchors: Anchors,
+    decorations: Decorations,
+    selections: Selections,
+    ops: usize,
+    ranges: Vec<TextRange>,
+    history: usize,
+}
+
+pub(crate) struct Changes {
+    version: u64,
+    /// Open batches (transactions, plus internal ones like `applyOps`); the
+    /// record goes out when the last one closes.
+    depth: usize,
+    ops: Vec<EditOp>,
+    ranges: Vec<TextRange>,
+    records: VecDeque<ChangeRecord>,
+    listeners: Vec<(u32, js_sys::Function)>,
+    next_listener: u32,
+}
+
+impl Default for Changes {
+    fn default() -> Self {
+        Changes {
+            version: 0,
+            depth: 0,
+            ops: Vec::new(),
+            ranges: Vec::new(),
+            records: VecDeque::new(),
+            listeners: Vec::new(),
+            next_listener: 1,
+        }
+    }
+}
+
+impl Changes {
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub(crate) fn record(&mut self, edit: &Splice, text: &str) {
+        if edit.removed > 0 {
+            self.ops.push(EditOp::Del { at: edit.start, len: edit.removed });
+        }
+        if edit.inserted > 0 {
+            self.ops.push(EditOp::Ins { at: edit.start, text: text.to_string() });
+        }
+        let (start, old_end, new_end) = (edit.start, edit.start + edit.removed, edit.start + edit.inserted);
+        let shift = |p: usize| if p >= old_end { p + new_end - old_end } else { p.min(new_end) };
+        let mut touched = TextRange { start, end: new_end };
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        for r in self.ranges.drain(..) {
+            let r = TextRange { start: if r.start > start { shift(r.start) } else { r.start }, end: shift(r.end) };
+            if r.end < touched.start {
+                ranges.push(r);
+            } else if r.start > touched.end {
+                if touched.end >= touched.start {
+                    ranges.push(touched);
+                    touched = TextRange { start: usize::MAX, end: 0 };
+                }
+                ranges.push(r);
+            } else {
+                touched = TextRange { start: touched.start.min(r.start), end: touched.end.max(r.end) };
+            }
+        }
+        if touched.end >= touched.start {
+            ranges.push(touched);
+        }
+        self.ranges = ranges;
+        if self.depth == 0 {
+            self.flush();
+        }
+    }
+
+    pub(crate) fn open(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Close a batch; the outermost one returns the record, if anything changed.
+    pub(crate) fn close(&mut self) -> Option<ChangeRecord> {
+        self.depth -= 1;
+        if self.depth == 0 {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    fn flush(&mut self) -> Option<ChangeRecord> {
+        if self.ops.is_empty() {
+            return None;
+        }
+        self.version += 1;
+        let record = ChangeRecord {
+            version: self.version,
+            ranges: std::mem::take(&mut self.ranges),
+            ops: std::mem::take(&mut self.ops),
+        };
+        if self.records.len() == RECORD_LIMIT {
+            self.records.pop_front();
+        }
+        self.records.push_back(record.clone());
+        if !self.listeners.is_empty() {
+            if let Ok(value) = serde_wasm_bindgen::to_value(&record) {
+                for (_, listener) in &self.listeners {
+                    let _ = listener.call1(&JsValue::NULL, &value);
+                }
+            }
+        }
+        Some(record)
+    }
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    /// Start a transaction; see the module docs.
+    #[wasm_bindgen(js_name = begin)]
+    pub fn begin(&mut self) {
+        let history = self.history.begin_txn();
+        self.txns.push(Checkpoint {
+            rope: self.rope.clone(),
+            blocks: self.blocks.clone(),
+            anchors: self.anchors.clone(),
+            decorations: self.decorations.clone(),
+            selections: self.selections.clone(),
+            ops: self.changes.ops.len(),
+            ranges: self.changes.ranges.clone(),
+            history,
+        });
+        self.changes.open();
+    }
+
+    /// Commit the innermost transaction. Returns the `ChangeRecord` when
+    /// this closes the outermost one and something changed, else `undefined`.
+    #[wasm_bindgen(js_name = commit)]
+    pub fn commit_js(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.commit()?)?)
+    }
+
+    /// Undo everything since the matching `begin`.
+    #[wasm_bindgen(js_name = rollback)]
+    pub fn rollback(&mut self) -> Result<(), CoreError> {
+        let checkpoint = self.txns.pop().ok_or(CoreError::NoTransaction)?;
+        self.rope = checkpoint.rope;
+        self.blocks = checkpoint.blocks;
+        // Anchors made inside the transaction go too, but their ids are
+        // never handed out again.
+        let next_anchor = self.anchors.next_id;
+        self.anchors = checkpoint.anchors;
+        self.anchors.next_id = next_anchor;
+        self.decorations = checkpoint.decorations;
+        self.selections = checkpoint.selections;
+        self.changes.ops.truncate(checkpoint.ops);
+        self.changes.ranges = checkpoint.ranges;
+        self.history.rollback_txn(checkpoint.history);
+        self.changes.close();
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = inTransaction)]
+    pub fn in_transaction(&self) -> bool {
+        !self.txns.is_empty()
+    }
+
+    /// Monotonic document version, bumped once per change record.
+    #[wasm_bindgen(js_name = version)]
+    pub fn version(&self) -> f64 {
+        self.changes.version as f64
+    }
+
+    /// Drain the change records produced since the last call.
+    #[wasm_bindgen(js_name = takeChanges)]
+    pub fn take_changes_js(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.take_changes())?)
+    }
+
+    /// Call `listener(record)` after every change. It runs while this
+    /// `CoreText` is still borrowed, so it must not call back into it
+    /// synchronously; everything it needs is in the record. Returns an id
+    /// for `unsubscribe`.
+    #[wasm_bindgen(js_name = subscribe)]
+    pub fn subscribe(&mut self, listener: js_sys::Function) -> u32 {
+        let id = self.changes.next_listener;
+        self.changes.next_listener += 1;
+        self.changes.listeners.push((id, listener));
+        id
+    }
+
+    #[wasm_bindgen(js_name = unsubscribe)]
+    pub fn unsubscribe(&mut self, id: u32) {
+        self.changes.listeners.retain(|(l, _)| *l != id);
+    }
+}
+
+impl CoreText {
+    pub fn commit(&mut self) -> Result<Option<ChangeRecord>, CoreError> {
+        self.txns.pop().ok_or(CoreError::NoTransaction)?;
+        self.history.end_txn();
+        Ok(self.changes.close())
+    }
+
+    pub fn take_changes(&mut self) -> Vec<ChangeRecord> {
+        self.changes.records.drain(..).collect()
+    }
+}
@@ -0,0 +1,168 @@
+//! Word and sentence boundaries (UAX #29).
+//!
+//! Both kinds of boundary always fall on either side of a `\n`, so each
+//! lookup segments just the line (or lines) around the offset, including
+//! its `\n`, instead of the whole document. Offsets are UTF-16.
+//!
+//! `next/prevWordBoundary` step over every UAX #29 segment, spaces and
+//! punctuation included. `nextWordEnd` / `prevWordStart` are the
+//! Ctrl+Arrow moves: they skip to the far edge of the next segment that
+//! holds a letter or digit, crossing lines if they have to.
+
+use unicode_segmentation::UnicodeSegmentation;
+use wasm_bindgen::prelude::*;
+
+use crate::ops::{utf16_len, TextRange};
+use crate::{CoreError, CoreText, Unit};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Word,
+    Sentence,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    start: usize,
+    end: usize,
+    /// Holds a letter or digit (always true for sentences).
+    word: bool,
+}
+
+#[wasm_bindgen]
+impl CoreText {
+    #[wasm_bindgen(js_name = nextWordBoundary)]
+    pub fn next_word_boundary(&self, offset: usize) -> Result<usize, CoreError> {
+        let offset = self.check_offset(offset)?;
+        Ok(self.next_boundary(offset, Kind::Word))
+    }
+
+    #[wasm_bindgen(js_name = prevWordBoundary)]
+    pub fn prev_word_boundary(&self, offset: usize) -> Result<usize, CoreError> {
+        let offset = self.check_offset(offset)?;
+        Ok(self.prev_boundary(offset, Kind::Word))
+    }
+
+    /// Ctrl+Right: the end of the next word after `offset`.
+    #[wasm_bindgen(js_name = nextWordEnd)]
+    pub fn next_word_end(&self, offset: usize) -> Result<usize, CoreError> {
+        let offset = self.check_offset(offset)?;
+        for line in self.rope.utf16_to_line(offset)..self.rope.len_lines() {
+            if let Some(seg) = self.line_segments(line, Kind::Word).into_iter().find(|s| s.word && s.end > offset) {
+                return Ok(seg.end);
+            }
+        }
+        Ok(self.rope.len_utf16())
+    }
+
+    /// Ctrl+Left: the start of the word before `offset`.
+    #[wasm_bindgen(js_name = prevWordStart)]
+    pub fn prev_word_start(&self, offset: usize) -> Result<usize, CoreError> {
+        let offset = self.check_offset(offset)?;
+        for line in (0..=self.rope.utf16_to_line(offset)).rev() {
+            if let Some(seg) = self.line_segments(line, Kind::Word).into_iter().rev().find(|s| s.word && s.start < offset) {
+                return Ok(seg.start);
+            }
+        }
+        Ok(0)
+    }
+
+    /// `{ start, end }` of the segment to select on double-click at
+    /// `offset`. On a boundary the word to the right wins, then the word to
+    /// the left; a line break selects nothing.
+    #[wasm_bindgen(js_name = wordAt)]
+    pub fn word_at_js(&self, offset: usize) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.word_at(offset)?)?)
+    }
+
+    #[wasm_bindgen(js_name = nextSentenceBoundary)]
+    pub fn next_sentence_boundary(&self, offset: usize) -> Result<usize, CoreError> {
+        let offset = self.check_offset(offset)?;
+        Ok(self.next_boundary(offset, Kind::Sentence))
+    }
+
+    #[wasm_bindgen(js_name = prevSentenceBoundary)]
+    pub fn prev_sentence_boundary(&self, offset: usize) -> Result<usize, CoreError> {
+        let offset = self.check_offset(offset)?;
+        Ok(self.prev_boundary(offset, Kind::Sentence))
+    }
+
+    /// `{ start, end }` of the sentence holding `offset`, without its
+    /// trailing line break.
+    #[wasm_bindgen(js_name = sentenceAt)]
+    pub fn sentence_at_js(&self, offset: usize) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.sentence_at(offset)?)?)
+    }
+}
+
+impl CoreText {
+    pub fn word_at(&self, offset: usize) -> Result<TextRange, CoreError> {
+        let offset = self.check_offset(offset)?;
+        let segs = self.line_segments(self.rope.utf16_to_line(offset), Kind::Word);
+        let right = segs.iter().find(|s| s.start <= offset && offset < s.end);
+        let left = segs.iter().find(|s| s.start < offset && offset <= s.end);
+        let pick = match (right, left) {
+            (Some(r), _) if r.word => Some(r),
+            (_, Some(l)) if l.word => Some(l),
+            (r, l) => r.or(l),
+        };
+        Ok(match pick {
+            Some(s) if self.rope.slice_utf16(s.start, s.end) != "\n" => TextRange { start: s.start, end: s.end },
+            _ => TextRange { start: offset, end: offset },
+        })
+    }
+
+    pub fn sentence_at(&self, offset: usize) -> Result<TextRange, CoreError> {
+        let offset = self.check_offset(offset)?;
+        let line = self.rope.utf16_to_line(offset);
+        let segs = self.line_segments(line, Kind::Sentence);
+        let seg = segs.iter().find(|s| offset < s.end).or(segs.last());
+        Ok(match seg {
+            Some(s) => {
+                let line_end = self.line_end(line)?;
+                TextRange { start: s.start.min(line_end), end: s.end.min(line_end) }
+            }
+            None => TextRange { start: offset, end: offset },
+        })
+    }
+
+    fn check_offset(&self, offset: usize) -> Result<usize, CoreError> {
+        self.check_index(Unit::Utf16, offset, self.rope.len_utf16())
+    }
+
+    fn next_boundary(&self, offset: usize, kind: Kind) -> usize {
+        let segs = self.line_segments(self.rope.utf16_to_line(offset), kind);
+        segs.iter().find(|s| s.end > offset).map_or(self.rope.len_utf16(), |s| s.end)
+    }
+
+    fn prev_boundary(&self, offset: usize, kind: Kind) -> usize {
+        let line = self.rope.utf16_to_line(offset);
+        let line = if line > 0 && self.rope.line_to_utf16(line) == offset { line - 1 } else { line };
+        let segs = self.line_segments(line, kind);
+        segs.iter().rev().find(|s| s.start < offset).map_or(0, |s| s.start)
+    }
+
+    /// Segments of one line, `\n` included, in document offsets.
+    fn line_segments(&self, line: usize, kind: Kind) -> Vec<Segment> {
+        let start = self.rope.line_to_char(line);
+        let end = if line + 1 < self.rope.len_lines() {
+            self.rope.line_to_char(line + 1)
+        } else {
+            self.rope.len_chars()
+        };
+        let text = self.rope.slice(start, end);
+        let mut at = self.rope.char_to_utf16(start);
+        let mut push = |s: &str, word: bool| {
+            let seg = Segment { start: at, end: at + utf16_len(s), word };
+            at = seg.end;
+            seg
+        };
+        match kind {
+            Kind::Word => text
+                .split_word_bounds()
+                .map(|s| push(s, s.chars().any(char::is_alphanumeric)))
+                .collect(),
+            Kind::Sentence => text.split_sentence_bounds().map(|s| push(s, true)).collect(),
+        }
+    }
+}
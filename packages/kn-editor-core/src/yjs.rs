@@ -0,0 +1,470 @@
+//! Yjs interop: one `Y.Text` field of a Yjs document, in Yjs's own update
+//! format (v1, what `Y.encodeStateAsUpdate` and `Y.applyUpdate` use).
+//!
+//! `YjsText` runs the same merge as `CrdtText` (`crdt.rs`), which is Yjs's
+//! algorithm with Yjs's ids, so updates flow both ways unchanged:
+//! `applyUpdate` takes a Yjs update (persisted or from a provider) and
+//! returns the `EditOp[]` for the mirrored `CoreText`, and local edits fed
+//! in with `applyOps` come out of `takeUpdate` as a Yjs update.
+//! `YjsEditorAdapter` feeds it the `EditOp`s the model reports through
+//! `subscribeOps`, so nothing is diffed on either side.
+//!
+//! Only the named root text is materialized. Structs of other types in the
+//! document (maps, other fields, attributes) are kept as received, so
+//! clocks, origins and state vectors stay right and `encodeStateAsUpdate`
+//! can pass them on (as Skip structs, for the rare one that arrived partly
+//! known). Embeds show up as U+FFFC, one unit each like in Yjs;
+//! formatting marks take no room and are kept as they came.
+//!
+//! `clientId` must not be shared with a `Y.Doc` that also writes to the
+//! document, or the two would hand out the same clocks.
+//!
+//! ```text
+//! update  = count client_structs* delete_set
+//! client_structs = count client clock struct*
+//! struct  = info ...            info & 0x1f: content ref (0 = GC,
+//!           1 = deleted, 4 = string, 5 = embed, 6 = format, 10 = skip...);
+//!           0x80 has origin, 0x40 has right origin, 0x20 has parentSub
+//! delete_set = count (client count (clock len)*)*
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use wasm_bindgen::prelude::*;
+
+use crate::crdt::{decode_id, decode_state_vector, push_delete, Content, Delete, Id, Incoming, Item, Other, Replica, Stored};
+use crate::encoding::{Decoder, Encoder};
+use crate::ops::{utf16_len, EditOp, TextRange};
+use crate::CoreError;
+
+const HAS_ORIGIN: u8 = 0x80;
+const HAS_RIGHT_ORIGIN: u8 = 0x40;
+const HAS_PARENT_SUB: u8 = 0x20;
+const CONTENT: u8 = 0x1f;
+
+const GC: u8 = 0;
+const DELETED: u8 = 1;
+const JSON: u8 = 2;
+const BINARY: u8 = 3;
+const STRING: u8 = 4;
+const EMBED: u8 = 5;
+const FORMAT: u8 = 6;
+const TYPE: u8 = 7;
+const ANY: u8 = 8;
+const DOC: u8 = 9;
+const SKIP: u8 = 10;
+
+/// `YXmlElement` and `YXmlHook` carry a name after their type ref.
+const NAMED_TYPES: [u64; 2] = [3, 5];
+
+/// Deepest nesting accepted in `Any` content.
+const ANY_DEPTH: usize = 64;
+
+#[wasm_bindgen]
+pub struct YjsText {
+    replica: Replica,
+    field: String,
+}
+
+#[wasm_bindgen]
+impl YjsText {
+    /// An empty replica of root text `field` (the `name` in
+    /// `doc.getText(name)`), writing as `client_id`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(client_id: u32, field: &str) -> YjsText {
+        YjsText { replica: Replica::new(u64::from(client_id)), field: field.to_string() }
+    }
+
+    #[wasm_bindgen(js_name = clientId)]
+    pub fn client_id(&self) -> u32 {
+        self.replica.agent() as u32
+    }
+
+    #[wasm_bindgen(js_name = field)]
+    pub fn field(&self) -> String {
+        self.field.clone()
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn text(&self) -> String {
+        self.replica.text()
+    }
+
+    #[wasm_bindgen(js_name = lenUtf16)]
+    pub fn len_utf16(&self) -> usize {
+        self.replica.len_utf16()
+    }
+
+    /// As `CrdtText.insert`.
+    #[wasm_bindgen(js_name = insert)]
+    pub fn insert_js(&mut self, at: usize, text: &str) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.insert(at, text)?)?)
+    }
+
+    /// As `CrdtText.delete`.
+    #[wasm_bindgen(js_name = delete)]
+    pub fn delete_js(&mut self, at: usize, len: usize) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.delete(at, len)?)?)
+    }
+
+    /// Apply local `EditOp[]`, e.g. `ChangeRecord.ops` of the mirrored
+    /// `CoreText` (skipping the records its `applyUpdate` ops produced).
+    #[wasm_bindgen(js_name = applyOps)]
+    pub fn apply_ops_js(&mut self, ops: JsValue) -> Result<(), JsValue> {
+        let ops: Vec<EditOp> = serde_wasm_bindgen::from_value(ops)?;
+        Ok(self.apply_ops(&ops)?)
+    }
+
+    /// Local edits since the last call as a Yjs update, or an empty array
+    /// when there are none.
+    #[wasm_bindgen(js_name = takeUpdate)]
+    pub fn take_update(&mut self) -> Vec<u8> {
+        let (items, deletes) = self.replica.take_local();
+        if items.is_empty() && deletes.is_empty() {
+            return Vec::new();
+        }
+        let structs = items.into_iter().map(Struct::Item).collect();
+        encode_update(&self.field, structs, &deletes)
+    }
+
+    /// Merge a Yjs update. Returns the `EditOp[]` the text went through, to
+    /// apply to the mirrored `CoreText`.
+    #[wasm_bindgen(js_name = applyUpdate)]
+    pub fn apply_update_js(&mut self, update: &[u8]) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.apply_update(update)?)?)
+    }
+
+    /// The Yjs state vector of everything seen, for a peer's
+    /// `Y.encodeStateAsUpdate(doc, stateVector)`.
+    #[wasm_bindgen(js_name = stateVector)]
+    pub fn state_vector(&self) -> Vec<u8> {
+        self.replica.state_vector()
+    }
+
+    /// A Yjs update with what a peer at `state_vector` (a Yjs state vector;
+    /// everything, if omitted) is missing of this text, plus all deletes.
+    #[wasm_bindgen(js_name = encodeStateAsUpdate)]
+    pub fn encode_state_as_update(&self, state_vector: Option<Vec<u8>>) -> Result<Vec<u8>, CoreError> {
+        let known = match state_vector {
+            Some(bytes) => decode_state_vector(&bytes)?,
+            None => HashMap::new(),
+        };
+        let mut structs = Vec::new();
+        for stored in self.replica.structs() {
+            let have = known.get(&stored.id().agent).copied().unwrap_or(0);
+            if stored.end() <= have {
+                continue;
+            }
+            let skip = have.saturating_sub(stored.id().seq);
+            structs.push(match stored {
+                Stored::Item(item) if skip > 0 => Struct::Item(item.clone().skip(skip)),
+                Stored::Item(item) => Struct::Item(item.clone()),
+                // Passed on whole; Yjs drops the part a peer already has.
+                Stored::Other(other) if other.raw.is_some() => Struct::Units(other.clone()),
+                Stored::Other(other) => {
+                    Struct::Units(Other { id: other.id.plus(skip), len: other.len - skip, gc: other.gc, raw: None })
+                }
+            });
+        }
+        Ok(encode_update(&self.field, structs, &self.replica.delete_set()))
+    }
+
+    #[wasm_bindgen(js_name = pendingCount)]
+    pub fn pending_count(&self) -> usize {
+        self.replica.pending_count()
+    }
+}
+
+impl YjsText {
+    pub fn insert(&mut self, at: usize, text: &str) -> Result<TextRange, CoreError> {
+        self.replica.insert(at, text)
+    }
+
+    pub fn delete(&mut self, at: usize, len: usize) -> Result<TextRange, CoreError> {
+        self.replica.delete(at, len)
+    }
+
+    pub fn apply_ops(&mut self, ops: &[EditOp]) -> Result<(), CoreError> {
+        self.replica.apply_ops(ops)
+    }
+
+    /// An empty update changes nothing, as in `CrdtText`.
+    pub fn apply_update(&mut self, update: &[u8]) -> Result<Vec<EditOp>, CoreError> {
+        if update.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (structs, deletes) = decode_update(update, &self.field)?;
+        Ok(self.replica.receive(structs, deletes))
+    }
+}
+
+/// A struct to write: an item of this text, or other units: GC, another
+/// type's struct as received, or (if it did not come whole) a Skip.
+enum Struct {
+    Item(Item),
+    Units(Other),
+}
+
+impl Struct {
+    fn id(&self) -> Id {
+        match self {
+            Struct::Item(item) => item.id,
+            Struct::Units(other) => other.id,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        match self {
+            Struct::Item(item) => item.len,
+            Struct::Units(other) => other.len,
+        }
+    }
+}
+
+fn decode_update(update: &[u8], field: &str) -> Result<(Vec<Incoming>, Vec<Delete>), CoreError> {
+    let mut dec = Decoder::new(update);
+    let mut structs = Vec::new();
+    for _ in 0..dec.var_uint()? {
+        let count = dec.var_uint()?;
+        let agent = dec.var_uint()?;
+        let mut seq = dec.var_uint()?;
+        for _ in 0..count {
+            let start = dec.offset();
+            let id = Id { agent, seq };
+            let info = dec.u8()?;
+            let len = match info & CONTENT {
+                GC => {
+                    let len = dec.var_uint()?;
+                    structs.push(Incoming::Other(Other { id, len, gc: true, raw: None }));
+                    len
+                }
+                // Units the update leaves out; they'll come with another.
+                SKIP => dec.var_uint()?,
+                kind => {
+                    let origin = if info & HAS_ORIGIN != 0 { Some(decode_id(&mut dec)?) } else { None };
+                    let right_origin = if info & HAS_RIGHT_ORIGIN != 0 { Some(decode_id(&mut dec)?) } else { None };
+                    let mut in_text = false;
+                    if origin.is_none() && right_origin.is_none() {
+                        in_text = match dec.var_uint()? {
+                            1 => dec.string()? == field,
+                            _ => decode_id(&mut dec).map(|_| false)?,
+                        };
+                        if info & HAS_PARENT_SUB != 0 {
+                            dec.string()?;
+                            in_text = false;
+                        }
+                    }
+                    let (len, content) = decode_content(&mut dec, kind)?;
+                    let raw = Some(dec.since(start).to_vec());
+                    structs.push(match content {
+                        Some(content) => Incoming::Item { item: Item { id, origin, right_origin, len, content }, in_text, raw },
+                        None => Incoming::Other(Other { id, len, gc: false, raw }),
+                    });
+                    len
+                }
+            };
+            seq = seq.checked_add(len).filter(|_| len > 0).ok_or(CoreError::InvalidUpdate { offset: start })?;
+        }
+    }
+    let mut deletes = Vec::new();
+    for _ in 0..dec.var_uint()? {
+        let agent = dec.var_uint()?;
+        for _ in 0..dec.var_uint()? {
+            let start = dec.offset();
+            let delete = Delete { id: Id { agent, seq: dec.var_uint()? }, len: dec.var_uint()? };
+            if delete.id.seq.checked_add(delete.len).is_none() {
+                return Err(CoreError::InvalidUpdate { offset: start });
+            }
+            deletes.push(delete);
+        }
+    }
+    if !dec.is_done() {
+        return Err(CoreError::InvalidUpdate { offset: dec.offset() });
+    }
+    Ok((structs, deletes))
+}
+
+/// Length and content of an item. Content a `Y.Text` can't hold (arrays
+/// of JSON or `Any` values) comes back as `None`; it belongs to some other
+/// type and only its length matters.
+fn decode_content(dec: &mut Decoder, kind: u8) -> Result<(u64, Option<Content>), CoreError> {
+    let start = dec.offset();
+    match kind {
+        DELETED => Ok((dec.var_uint()?, Some(Content::Deleted))),
+        STRING => {
+            let text = dec.string()?;
+            Ok((utf16_len(text) as u64, Some(Content::Text(text.to_string()))))
+        }
+        JSON => {
+            let len = dec.var_uint()?;
+            for _ in 0..len {
+                dec.string()?;
+            }
+            Ok((len, None))
+        }
+        ANY => {
+            let len = dec.var_uint()?;
+            for _ in 0..len {
+                skip_any(dec, 0)?;
+            }
+            Ok((len, None))
+        }
+        FORMAT => {
+            dec.string()?;
+            dec.string()?;
+            Ok((1, Some(Content::Format(dec.since(start).to_vec()))))
+        }
+        BINARY | EMBED | TYPE | DOC => {
+            match kind {
+                BINARY => {
+                    let len = dec.len()?;
+                    dec.bytes(len)?;
+                }
+                EMBED => {
+                    dec.string()?;
+                }
+                TYPE => {
+                    if NAMED_TYPES.contains(&dec.var_uint()?) {
+                        dec.string()?;
+                    }
+                }
+                _ => {
+                    dec.string()?;
+                    skip_any(dec, 0)?;
+                }
+            }
+            Ok((1, Some(Content::Embed { kind, raw: dec.since(start).to_vec() })))
+        }
+        _ => Err(CoreError::InvalidUpdate { offset: start }),
+    }
+}
+
+/// Step over one lib0 `Any` value.
+fn skip_any(dec: &mut Decoder, depth: usize) -> Result<(), CoreError> {
+    let start = dec.offset();
+    if depth > ANY_DEPTH {
+        return Err(CoreError::InvalidUpdate { offset: start });
+    }
+    match dec.u8()? {
+        // undefined, null, false, true
+        127 | 126 | 121 | 120 => {}
+        // integer: a varInt continues on the same high bit as a varUint
+        125 => {
+            dec.var_uint()?;
+        }
+        124 => {
+            dec.bytes(4)?;
+        }
+        123 | 122 => {
+            dec.bytes(8)?;
+        }
+        119 => {
+            dec.string()?;
+        }
+        118 => {
+            for _ in 0..dec.var_uint()? {
+                dec.string()?;
+                skip_any(dec, depth + 1)?;
+            }
+        }
+        117 => {
+            for _ in 0..dec.var_uint()? {
+                skip_any(dec, depth + 1)?;
+            }
+        }
+        116 => {
+            let len = dec.len()?;
+            dec.bytes(len)?;
+        }
+        _ => return Err(CoreError::InvalidUpdate { offset: start }),
+    }
+    Ok(())
+}
+
+/// Write `structs` (any order) and `deletes` as a v1 update. Gaps between
+/// one client's structs become Skip structs. Clients go highest first in
+/// both halves, as Yjs writes them, so the bytes match its own updates.
+fn encode_update(field: &str, structs: Vec<Struct>, deletes: &[Delete]) -> Vec<u8> {
+    let mut by_client: BTreeMap<u64, Vec<Struct>> = BTreeMap::new();
+    for s in structs {
+        by_client.entry(s.id().agent).or_default().push(s);
+    }
+    let mut enc = Encoder::new();
+    enc.var_uint(by_client.len() as u64);
+    for (agent, mut structs) in by_client.into_iter().rev() {
+        structs.sort_by_key(|s| s.id().seq);
+        let first = structs[0].id().seq;
+        let mut out = Encoder::new();
+        let mut count = 0;
+        let mut seq = first;
+        for s in &structs {
+            if s.id().seq > seq {
+                out.u8(SKIP);
+                out.var_uint(s.id().seq - seq);
+                count += 1;
+            }
+            write_struct(&mut out, field, s);
+            count += 1;
+            seq = s.id().seq + s.len();
+        }
+        enc.var_uint(count);
+        enc.var_uint(agent);
+        enc.var_uint(first);
+        enc.bytes(&out.finish());
+    }
+
+    let mut by_client: BTreeMap<u64, Vec<Delete>> = BTreeMap::new();
+    for delete in deletes {
+        push_delete(by_client.entry(delete.id.agent).or_default(), *delete);
+    }
+    enc.var_uint(by_client.len() as u64);
+    for (agent, deletes) in by_client.into_iter().rev() {
+        enc.var_uint(agent);
+        enc.var_uint(deletes.len() as u64);
+        for delete in deletes {
+            enc.var_uint(delete.id.seq);
+            enc.var_uint(delete.len);
+        }
+    }
+    enc.finish()
+}
+
+fn write_struct(enc: &mut Encoder, field: &str, s: &Struct) {
+    let item = match s {
+        Struct::Item(item) => item,
+        Struct::Units(Other { raw: Some(raw), .. }) => return enc.bytes(raw),
+        Struct::Units(other) => {
+            enc.u8(if other.gc { GC } else { SKIP });
+            enc.var_uint(other.len);
+            return;
+        }
+    };
+    let kind = match &item.content {
+        Content::Text(_) => STRING,
+        Content::Embed { kind, .. } => *kind,
+        Content::Format(_) => FORMAT,
+        Content::Deleted => DELETED,
+    };
+    let mut info = kind;
+    if item.origin.is_some() {
+        info |= HAS_ORIGIN;
+    }
+    if item.right_origin.is_some() {
+        info |= HAS_RIGHT_ORIGIN;
+    }
+    enc.u8(info);
+    for id in [item.origin, item.right_origin].into_iter().flatten() {
+        enc.var_uint(id.agent);
+        enc.var_uint(id.seq);
+    }
+    if item.origin.is_none() && item.right_origin.is_none() {
+        // Parent: the root type named `field`.
+        enc.var_uint(1);
+        enc.string(field);
+    }
+    match &item.content {
+        Content::Text(text) => enc.string(text),
+        Content::Embed { raw, .. } | Content::Format(raw) => enc.bytes(raw),
+        Content::Deleted => enc.var_uint(item.len),
+    }
+}
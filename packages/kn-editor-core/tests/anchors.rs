@@ -0,0 +1,118 @@
+//! Anchors against a model that tags every code unit: an anchor stays
+//! next to the unit it is stuck to until that unit is deleted.
+
+mod common;
+
+use common::seeded;
+use kn_editor_core::{Bias, CoreText, EditOp};
+
+/// Single code units only, so every offset is a char boundary.
+const ALPHABET: &[char] = &['a', 'é', '中', '\n', ' '];
+
+/// What an anchor is stuck to: a unit's tag, or an end of the text.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stuck {
+    Unit(u64),
+    Start,
+    End,
+}
+
+fn stuck(units: &[u64], offset: usize, bias: Bias) -> Stuck {
+    match bias {
+        Bias::Left if offset == 0 => Stuck::Start,
+        Bias::Left => Stuck::Unit(units[offset - 1]),
+        Bias::Right if offset == units.len() => Stuck::End,
+        Bias::Right => Stuck::Unit(units[offset]),
+    }
+}
+
+fn offset_of(units: &[u64], to: Stuck, bias: Bias) -> Option<usize> {
+    match to {
+        Stuck::Start => Some(0),
+        Stuck::End => Some(units.len()),
+        Stuck::Unit(tag) => {
+            let i = units.iter().position(|&u| u == tag)?;
+            Some(if bias == Bias::Left { i + 1 } else { i })
+        }
+    }
+}
+
+#[test]
+fn anchors_follow_the_units_they_stick_to() {
+    seeded(200, |seed, rng| {
+        let mut doc = CoreText::from_string("hello world");
+        let mut units: Vec<u64> = (0..11).collect();
+        let mut next_tag = 11;
+        let mut ids = Vec::new();
+        for _ in 0..12 {
+            let bias = if rng.below(2) == 0 { Bias::Left } else { Bias::Right };
+            ids.push(doc.create_anchor(rng.below(units.len() + 1), bias).unwrap());
+        }
+
+        for step in 0..40 {
+            let before: Vec<_> = ids.iter().map(|&id| doc.anchor(id).unwrap()).collect();
+            let targets: Vec<Stuck> = before.iter().map(|a| stuck(&units, a.offset, a.bias)).collect();
+
+            let len = units.len();
+            let (at, removed, inserted) = if len > 0 && rng.below(2) == 0 {
+                let at = rng.below(len);
+                (at, 1 + rng.below((len - at).min(4)), 0)
+            } else {
+                (rng.below(len + 1), 0, 1 + rng.below(3))
+            };
+            let text: String = (0..inserted).map(|_| ALPHABET[rng.below(ALPHABET.len())]).collect();
+            let op = if removed == 0 { EditOp::Ins { at, text } } else { EditOp::Del { at, len: removed } };
+            doc.apply_ops(&[op]).unwrap();
+            let fresh: Vec<u64> = (next_tag..next_tag + inserted as u64).collect();
+            next_tag += inserted as u64;
+            units.splice(at..at + removed, fresh);
+
+            let mut newly_deleted = Vec::new();
+            for ((id, was), target) in ids.iter().zip(&before).zip(&targets) {
+                let now = doc.anchor(*id).unwrap();
+                match offset_of(&units, *target, was.bias) {
+                    Some(offset) => {
+                        assert_eq!(now.offset, offset, "seed {seed} step {step}");
+                        assert_eq!(now.deleted, was.deleted, "seed {seed} step {step}");
+                    }
+                    None => {
+                        let collapsed = if was.bias == Bias::Left { at } else { at + inserted };
+                        assert_eq!(now.offset, collapsed, "seed {seed} step {step}");
+                        assert!(now.deleted, "seed {seed} step {step}");
+                        if !was.deleted {
+                            newly_deleted.push(*id);
+                        }
+                    }
+                }
+            }
+            let mut reported = doc.take_deleted_anchors();
+            reported.sort();
+            assert_eq!(reported, newly_deleted, "seed {seed} step {step}");
+        }
+    });
+}
+
+#[test]
+fn inserts_at_an_anchor_land_on_its_free_side() {
+    let mut doc = CoreText::from_string("ab");
+    let left = doc.create_anchor(1, Bias::Left).unwrap();
+    let right = doc.create_anchor(1, Bias::Right).unwrap();
+    doc.insert_utf16(1, "XY").unwrap();
+    assert_eq!((doc.anchor(left).unwrap().offset, doc.anchor(right).unwrap().offset), (1, 3));
+}
+
+#[test]
+fn set_anchor_revives_a_deleted_anchor() {
+    let mut doc = CoreText::from_string("abc");
+    let id = doc.create_anchor(2, Bias::Left).unwrap();
+    doc.delete_utf16(0, 3).unwrap();
+    assert!(doc.anchor(id).unwrap().deleted);
+    doc.insert_utf16(0, "xyz").unwrap();
+    assert!(doc.set_anchor(id, 2).unwrap());
+    assert!(!doc.anchor(id).unwrap().deleted);
+    assert!(doc.set_anchor(id, 9).is_err());
+
+    assert!(doc.remove_anchor(id));
+    assert_eq!(doc.anchors(&[id]), [None]);
+    assert!(!doc.set_anchor(id, 0).unwrap());
+}
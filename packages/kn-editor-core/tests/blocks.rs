@@ -0,0 +1,116 @@
+//! Block segmentation: incremental resegmentation against a full parse,
+//! persistent ids and the block delta.
+
+mod common;
+
+use common::{seeded, text};
+use kn_editor_core::{CoreText, EditOp, EditorBlock};
+
+const PIECES: &[&str] = &["a", "bc", "\n", "\n\n", "\n\n\n", "é", "😀", "x\n\ny"];
+
+/// Blocks without their ids, which only the incremental path assigns.
+fn shapes(blocks: &[EditorBlock]) -> Vec<(usize, usize, &str, &str, &str)> {
+    blocks.iter().map(|b| (b.start, b.end, b.text.as_str(), b.gap.as_str(), b.hash.as_str())).collect()
+}
+
+fn ids(doc: &CoreText) -> Vec<String> {
+    doc.editor_blocks().into_iter().map(|b| b.id).collect()
+}
+
+#[test]
+fn incremental_segmentation_matches_a_full_parse() {
+    seeded(300, |seed, rng| {
+        let mut doc = CoreText::from_string("one\n\ntwo\nthree\n\n\nfour");
+        for step in 0..40 {
+            let len = doc.len_utf16();
+            let op = if len > 0 && rng.below(3) == 0 {
+                let at = rng.below(len);
+                EditOp::Del { at, len: 1 + rng.below((len - at).min(6)) }
+            } else {
+                EditOp::Ins { at: rng.below(len + 1), text: PIECES[rng.below(PIECES.len())].into() }
+            };
+            doc.apply_ops(&[op]).unwrap();
+            let full = CoreText::from_string(&text(&doc)).editor_blocks();
+            let blocks = doc.editor_blocks();
+            assert_eq!(shapes(&blocks), shapes(&full), "seed {seed} step {step}");
+
+            let joined: String = blocks.iter().map(|b| format!("{}{}", b.text, b.gap)).collect();
+            assert_eq!(joined, text(&doc), "seed {seed} step {step}");
+            let mut unique = ids(&doc);
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), blocks.len(), "seed {seed} step {step}");
+        }
+    });
+}
+
+#[test]
+fn blocks_split_at_runs_of_newlines() {
+    let doc = CoreText::from_string("a\nb\n\nc\n\n\n");
+    let blocks = doc.editor_blocks();
+    let spans: Vec<_> = blocks.iter().map(|b| (b.start, b.end, b.text.as_str(), b.gap.as_str())).collect();
+    assert_eq!(spans, [(0, 3, "a\nb", "\n\n"), (5, 6, "c", "\n\n\n"), (9, 9, "", "")]);
+    assert_eq!(ids(&doc), ["block-0", "block-1", "block-2"]);
+}
+
+#[test]
+fn edits_keep_ids_outside_and_carry_them_inside() {
+    let mut doc = CoreText::from_string("one\n\ntwo\n\nthree");
+    assert_eq!(ids(&doc), ["block-0", "block-1", "block-2"]);
+
+    // Editing inside a block keeps every id.
+    doc.insert_utf16(6, "X").unwrap();
+    assert_eq!(ids(&doc), ["block-0", "block-1", "block-2"]);
+
+    // A split leaves the id on the left fragment.
+    doc.insert_utf16(7, "\n\n").unwrap();
+    assert_eq!(text(&doc), "one\n\ntX\n\nwo\n\nthree");
+    assert_eq!(ids(&doc), ["block-0", "block-1", "block-3", "block-2"]);
+
+    // A merge keeps the leftmost id.
+    doc.delete_utf16(3, 2).unwrap();
+    assert_eq!(ids(&doc), ["block-0", "block-3", "block-2"]);
+}
+
+#[test]
+fn the_delta_reports_what_changed_since_it_was_last_taken() {
+    let mut doc = CoreText::from_string("one\n\ntwo");
+    assert!(doc.take_block_delta().added.is_empty());
+
+    doc.insert_utf16(0, "1").unwrap();
+    doc.insert_utf16(9, "\n\nthree").unwrap();
+    let delta = doc.take_block_delta();
+    // "two" changes too: like `hashText`, the hash covers the gap it gained.
+    assert_eq!(delta.changed.iter().map(|b| b.text.as_str()).collect::<Vec<_>>(), ["1one", "two"]);
+    assert_eq!(delta.added.iter().map(|b| b.text.as_str()).collect::<Vec<_>>(), ["three"]);
+    assert!(delta.removed.is_empty());
+
+    // Changes that undo themselves report nothing.
+    doc.insert_utf16(0, "2").unwrap();
+    doc.delete_utf16(0, 1).unwrap();
+    assert_eq!(doc.take_block_delta(), Default::default());
+
+    doc.delete_utf16(4, 12).unwrap();
+    let delta = doc.take_block_delta();
+    assert_eq!(delta.removed.len(), 2);
+    assert_eq!(text(&doc), "1one");
+}
+
+#[test]
+fn ids_survive_a_reload() {
+    let mut doc = CoreText::from_string("a\n\nb\n\nc");
+    doc.insert_utf16(4, "\n\nnew").unwrap();
+    let saved = doc.export_block_ids();
+
+    let mut reloaded = CoreText::from_string(&text(&doc));
+    assert_eq!(reloaded.import_block_ids(&saved), saved.len());
+    assert_eq!(ids(&reloaded), ids(&doc));
+
+    // Edited offline: blocks still found by hash keep their ids, fresh ids
+    // never collide with saved ones.
+    let mut edited = CoreText::from_string("a\n\nother\n\nc");
+    assert_eq!(edited.import_block_ids(&saved), 2);
+    let now = ids(&edited);
+    assert_eq!((now[0].as_str(), now[2].as_str()), (saved[0].id.as_str(), saved[3].id.as_str()));
+    assert!(!saved.iter().any(|r| r.id == now[1]));
+}
@@ -0,0 +1,68 @@
+//! Helpers shared by the integration tests. Each test crate uses its own
+//! subset, hence the `dead_code` allowance.
+
+#![allow(dead_code)]
+
+use kn_editor_core::{CoreText, EditOp};
+
+/// xorshift64, so runs are reproducible from the seed.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Runs `check` for seeds `1..=runs`, each with its own `Rng`; `seed` is
+/// for failure messages.
+pub fn seeded(runs: u64, mut check: impl FnMut(u64, &mut Rng)) {
+    for seed in 1..=runs {
+        check(seed, &mut Rng(seed * 7919));
+    }
+}
+
+pub fn text(doc: &CoreText) -> String {
+    doc.slice(0, doc.len_chars()).unwrap()
+}
+
+/// One random edit through `applyOps`: a short delete, or an insert of one
+/// of `pieces`.
+pub fn edit(rng: &mut Rng, doc: &mut CoreText, pieces: &[&str]) {
+    let len = doc.len_utf16();
+    let op = if len > 0 && rng.below(3) == 0 {
+        let at = rng.below(len);
+        EditOp::Del { at, len: 1 + rng.below((len - at).min(3)) }
+    } else {
+        EditOp::Ins { at: rng.below(len + 1), text: (*rng.pick(pieces)).into() }
+    };
+    doc.apply_ops(&[op]).unwrap();
+}
+
+/// Budget for a timing check: `strict` with `PERF_STRICT=1` (the numbers
+/// in `performance-architecture.md`), `soft` otherwise so CI and debug
+/// builds don't flake.
+pub fn perf_budget_ms(strict: f64, soft: f64) -> f64 {
+    if std::env::var("PERF_STRICT").as_deref() == Ok("1") {
+        strict
+    } else {
+        soft
+    }
+}
+
+/// 95th percentile of `samples`, in milliseconds.
+pub fn p95_ms(mut samples: Vec<std::time::Duration>) -> f64 {
+    samples.sort();
+    samples[(samples.len() * 95 / 100).min(samples.len() - 1)].as_secs_f64() * 1000.0
+}
@@ -0,0 +1,160 @@
+//! `CrdtText` replicas: convergence whatever order updates arrive in, and
+//! robustness against updates that are not well formed.
+
+mod common;
+
+use common::{seeded, text, Rng};
+use kn_editor_core::{CoreText, CrdtText, EditOp, TextRange};
+
+const PIECES: &[&str] = &["a", "bc", "😀", "xyz\n", "é", "Q"];
+
+/// Replicas edit concurrently and exchange updates late, shuffled and
+/// duplicated; each keeps a `CoreText` in step from the ops `applyUpdate`
+/// returns. All end with the same text.
+#[test]
+fn replicas_converge_in_any_delivery_order() {
+    seeded(199, |seed, rng| {
+        let n = 2 + rng.below(3);
+        let mut replicas: Vec<CrdtText> = (0..n).map(|i| CrdtText::new(i as u32 * 3 + 1)).collect();
+        let mut mirrors: Vec<CoreText> = (0..n).map(|_| CoreText::new()).collect();
+        let mut updates: Vec<(usize, Vec<u8>)> = Vec::new();
+        let mut seen: Vec<Vec<bool>> = vec![Vec::new(); n];
+
+        for _ in 0..60 {
+            let r = rng.below(n);
+            let len = replicas[r].len_utf16();
+            match rng.below(10) {
+                0..=4 => {
+                    let ops = [EditOp::Ins { at: rng.below(len + 1), text: PIECES[rng.below(PIECES.len())].to_string() }];
+                    replicas[r].apply_ops(&ops).unwrap();
+                    mirrors[r].apply_ops(&ops).unwrap();
+                }
+                5 | 6 if len > 0 => {
+                    let at = rng.below(len);
+                    let range = replicas[r].delete(at, 1 + rng.below((len - at).min(4))).unwrap();
+                    mirrors[r].delete_utf16(range.start, range.end - range.start).unwrap();
+                }
+                7 => {
+                    let update = replicas[r].take_update();
+                    if !update.is_empty() {
+                        updates.push((r, update));
+                    }
+                }
+                _ => {
+                    let unseen: Vec<usize> = (0..updates.len())
+                        .filter(|&i| updates[i].0 != r && !seen[r].get(i).copied().unwrap_or(false))
+                        .collect();
+                    if unseen.is_empty() {
+                        continue;
+                    }
+                    let i = unseen[rng.below(unseen.len())];
+                    let ops = replicas[r].apply_update(&updates[i].1).unwrap();
+                    mirrors[r].apply_ops(&ops).unwrap();
+                    seen[r].resize(updates.len(), false);
+                    seen[r][i] = true;
+                }
+            }
+            assert_eq!(replicas[r].text(), text(&mirrors[r]), "seed {seed}");
+        }
+
+        for (r, replica) in replicas.iter_mut().enumerate() {
+            let update = replica.take_update();
+            if !update.is_empty() {
+                updates.push((r, update));
+            }
+        }
+        for r in 0..n {
+            let mut order: Vec<usize> = (0..updates.len()).filter(|&i| updates[i].0 != r).collect();
+            for i in (1..order.len()).rev() {
+                order.swap(i, rng.below(i + 1));
+            }
+            let again: Vec<usize> = order.iter().copied().filter(|_| rng.below(3) == 0).collect();
+            order.extend(again);
+            for i in order {
+                let ops = replicas[r].apply_update(&updates[i].1).unwrap();
+                mirrors[r].apply_ops(&ops).unwrap();
+                assert_eq!(replicas[r].text(), text(&mirrors[r]), "seed {seed}");
+            }
+            assert_eq!(replicas[r].pending_count(), 0, "seed {seed}");
+        }
+        for r in 1..n {
+            assert_eq!(replicas[r].text(), replicas[0].text(), "seed {seed}");
+        }
+
+        // A new replica catches up from the full state, a half-synced one
+        // from what it is missing.
+        let mut fresh = CrdtText::new(999);
+        fresh.apply_update(&replicas[0].encode_state_as_update(None).unwrap()).unwrap();
+        assert_eq!(fresh.text(), replicas[0].text(), "seed {seed}");
+        let mut half = CrdtText::new(998);
+        for (_, update) in &updates[..updates.len() / 2] {
+            half.apply_update(update).unwrap();
+        }
+        let missing = replicas[1].encode_state_as_update(Some(half.state_vector())).unwrap();
+        half.apply_update(&missing).unwrap();
+        assert_eq!(half.text(), replicas[0].text(), "seed {seed}");
+        assert_eq!(half.pending_count(), 0, "seed {seed}");
+    });
+}
+
+/// Truncated, bit-flipped and random updates fail or apply; they never
+/// panic, and the ops they return keep a mirror in step.
+#[test]
+fn malformed_updates_never_panic() {
+    let mut source = CrdtText::new(1);
+    source.insert(0, "hello 😀 world").unwrap();
+    source.delete(2, 3).unwrap();
+    let mut other = CrdtText::new(2);
+    other.apply_update(&source.encode_state_as_update(None).unwrap()).unwrap();
+    other.insert(4, "xy").unwrap();
+    let good = [source.encode_state_as_update(None).unwrap(), other.take_update()];
+
+    let mut rng = Rng(42);
+    for round in 0..3000 {
+        let mut update = good[round % 2].clone();
+        match rng.below(3) {
+            0 => update.truncate(rng.below(update.len())),
+            1 => {
+                for _ in 0..1 + rng.below(3) {
+                    let i = rng.below(update.len());
+                    update[i] ^= 1 << rng.below(8);
+                }
+            }
+            _ => update = (0..rng.below(24)).map(|_| rng.next() as u8).collect(),
+        }
+        let mut replica = CrdtText::new(7);
+        let mut mirror = CoreText::new();
+        if let Ok(ops) = replica.apply_update(&update) {
+            mirror.apply_ops(&ops).unwrap();
+            assert_eq!(replica.text(), text(&mirror), "round {round}");
+        }
+    }
+}
+
+#[test]
+fn empty_update_is_a_no_op() {
+    let mut replica = CrdtText::new(1);
+    replica.insert(0, "abc").unwrap();
+    replica.take_update();
+    assert_eq!(replica.apply_update(&[]).unwrap(), Vec::<EditOp>::new());
+    assert_eq!(replica.text(), "abc");
+    // Nothing to send is an empty update too.
+    assert!(replica.take_update().is_empty());
+}
+
+#[test]
+fn edits_inside_a_surrogate_pair_report_the_range_touched() {
+    let mut replica = CrdtText::new(1);
+    replica.insert(0, "a😀b").unwrap();
+    // Ends inside the pair move back before it: the pair goes whole...
+    assert_eq!(replica.delete(1, 1).unwrap(), TextRange { start: 1, end: 1 });
+    assert_eq!(replica.text(), "a😀b");
+    // ...or not at all, and the range says which.
+    assert_eq!(replica.delete(2, 1).unwrap(), TextRange { start: 1, end: 3 });
+    assert_eq!(replica.text(), "ab");
+
+    let mut replica = CrdtText::new(2);
+    replica.insert(0, "😀").unwrap();
+    assert_eq!(replica.insert(1, "x").unwrap(), TextRange { start: 0, end: 1 });
+    assert_eq!(replica.text(), "x😀");
+}
@@ -0,0 +1,168 @@
+//! Decorations: interval-tree queries against a linear scan, through
+//! edits that move, shrink and drop them.
+
+mod common;
+
+use std::time::Instant;
+
+use common::{p95_ms, perf_budget_ms, seeded, Rng};
+use kn_editor_core::{CoreText, Decoration, EditOp};
+
+fn decoration(id: usize, start: usize, end: usize) -> Decoration {
+    Decoration { id: format!("d{id}"), kind: ["spell", "link"][id % 2].into(), start, end, payload: None }
+}
+
+/// Where an edit replacing `[at, at + removed)` with `inserted` units moves
+/// an edge; `after` edges are pushed past text inserted at them.
+fn map(p: usize, after: bool, at: usize, removed: usize, inserted: usize) -> usize {
+    if p < at || (p == at && !after) {
+        p
+    } else if p > at + removed || (p == at + removed && after) {
+        p - removed + inserted
+    } else if after {
+        at + inserted
+    } else {
+        at
+    }
+}
+
+fn remap(model: &mut Vec<Decoration>, at: usize, removed: usize, inserted: usize) {
+    model.retain_mut(|d| {
+        let point = d.start == d.end;
+        d.start = map(d.start, true, at, removed, inserted);
+        d.end = if point { d.start } else { map(d.end, false, at, removed, inserted).max(d.start) };
+        point || d.start < d.end
+    });
+}
+
+/// The query rule spelled out: ranges overlap, a point decoration counts
+/// inside a range or at a point query, a point query hits the unit after it.
+fn hits(d: &Decoration, start: usize, end: usize) -> bool {
+    match (d.start == d.end, start == end) {
+        (true, true) => d.start == start,
+        (true, false) => start <= d.start && d.start < end,
+        (false, true) => d.start <= start && start < d.end,
+        (false, false) => d.start < end && start < d.end,
+    }
+}
+
+fn ids(ds: &[Decoration]) -> Vec<String> {
+    let mut ids: Vec<String> = ds.iter().map(|d| d.id.clone()).collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn queries_match_a_linear_scan_through_edits() {
+    seeded(200, |seed, rng| {
+        let mut doc = CoreText::from_string(&"word ".repeat(20));
+        let mut model: Vec<Decoration> = Vec::new();
+        for step in 0..60 {
+            let len = doc.len_utf16();
+            match rng.below(4) {
+                0 => {
+                    let start = rng.below(len + 1);
+                    let end = start + rng.below((len - start).min(12) + 1);
+                    let d = decoration(rng.below(30), start, end);
+                    doc.add_decoration(d.clone()).unwrap();
+                    model.retain(|m| m.id != d.id);
+                    model.push(d);
+                }
+                1 if len > 0 => {
+                    let at = rng.below(len);
+                    let removed = 1 + rng.below((len - at).min(10));
+                    doc.apply_ops(&[EditOp::Del { at, len: removed }]).unwrap();
+                    remap(&mut model, at, removed, 0);
+                }
+                _ => {
+                    let at = rng.below(len + 1);
+                    let inserted = 1 + rng.below(4);
+                    doc.apply_ops(&[EditOp::Ins { at, text: "x".repeat(inserted) }]).unwrap();
+                    remap(&mut model, at, 0, inserted);
+                }
+            }
+
+            let len = doc.len_utf16();
+            for _ in 0..4 {
+                let start = rng.below(len + 1);
+                let end = start + rng.below(len - start + 1);
+                let expected: Vec<Decoration> = model.iter().filter(|d| hits(d, start, end)).cloned().collect();
+                let found = doc.decorations_in_range(start, end).unwrap();
+                assert_eq!(ids(&found), ids(&expected), "seed {seed} step {step} [{start}, {end})");
+                for d in &found {
+                    assert_eq!(Some(d), model.iter().find(|m| m.id == d.id), "seed {seed} step {step}");
+                }
+                assert!(found.windows(2).all(|w| w[0].start <= w[1].start), "seed {seed} step {step}");
+            }
+            let at = rng.below(len + 1);
+            let expected: Vec<Decoration> = model.iter().filter(|d| hits(d, at, at)).cloned().collect();
+            assert_eq!(ids(&doc.decorations_at(at).unwrap()), ids(&expected), "seed {seed} step {step} at {at}");
+        }
+    });
+}
+
+#[test]
+fn typing_at_an_edge_lands_outside() {
+    let mut doc = CoreText::from_string("abc");
+    doc.add_decoration(decoration(0, 1, 2)).unwrap();
+    doc.insert_utf16(2, "X").unwrap();
+    doc.insert_utf16(1, "Y").unwrap();
+    let d = &doc.decorations_in_range(0, 5).unwrap()[0];
+    assert_eq!((d.start, d.end), (2, 3));
+}
+
+#[test]
+fn decorations_whose_text_goes_are_dropped() {
+    let mut doc = CoreText::from_string("abcdef");
+    doc.add_decoration(decoration(0, 1, 3)).unwrap();
+    doc.add_decoration(decoration(1, 2, 2)).unwrap();
+    doc.delete_utf16(0, 4).unwrap();
+    // The point decoration survives at the edit point.
+    assert_eq!(ids(&doc.decorations_in_range(0, 2).unwrap()), ["d1"]);
+    assert_eq!(doc.clear_decorations_by_type("link"), 1);
+    assert!(doc.decorations_at(0).unwrap().is_empty());
+}
+
+#[test]
+fn lines_include_points_at_the_end_of_the_last_one() {
+    let mut doc = CoreText::from_string("one\ntwo\nthree");
+    doc.add_decoration(decoration(0, 0, 2)).unwrap();
+    doc.add_decoration(decoration(1, 7, 7)).unwrap();
+    doc.add_decoration(decoration(2, 8, 10)).unwrap();
+    assert_eq!(ids(&doc.decorations_in_lines(1, 1).unwrap()), ["d1"]);
+    assert_eq!(ids(&doc.decorations_in_lines(0, 2).unwrap()), ["d0", "d1", "d2"]);
+    assert!(doc.decorations_in_lines(2, 1).is_err());
+}
+
+/// `performance-architecture.md` budgets typing at p95 < 5ms a keystroke.
+/// Adding, removing and editing are O(n) in the decoration count (see
+/// `decorations.rs`); this keeps that in check at 10k+ decorations, with a
+/// viewport query after every keystroke to pay for the rebuild.
+#[test]
+fn typing_with_10k_decorations_meets_the_budget() {
+    let paragraph = "Some words here get underlined by the checker.\n".repeat(9) + "\n";
+    let mut doc = CoreText::from_string(&paragraph.repeat(300));
+    let len = doc.len_utf16();
+    let started = Instant::now();
+    for i in 0..12_000 {
+        let start = i * len / 12_000;
+        doc.add_decoration(decoration(i, start, start + 4)).unwrap();
+    }
+    let adding = started.elapsed();
+    let mut rng = Rng(7919);
+    let mut keystrokes = Vec::new();
+    for i in 0..500 {
+        let started = Instant::now();
+        doc.insert_utf16(rng.below(doc.len_utf16() + 1), "x").unwrap();
+        let line = doc.line_of_offset(rng.below(doc.len_utf16() + 1)).unwrap();
+        std::hint::black_box(doc.decorations_in_lines(line, (line + 40).min(doc.len_lines() - 1)).unwrap());
+        if i % 10 == 0 {
+            doc.add_decoration(decoration(20_000 + i, 0, 3)).unwrap();
+        }
+        keystrokes.push(started.elapsed());
+    }
+    assert_eq!(doc.decorations_in_range(0, doc.len_utf16()).unwrap().len(), 12_050);
+    let p95 = p95_ms(keystrokes);
+    let budget = perf_budget_ms(5.0, 25.0);
+    assert!(p95 < budget, "p95 {p95:.3}ms over {budget}ms (adding took {adding:?})");
+}
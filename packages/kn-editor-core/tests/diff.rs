@@ -0,0 +1,99 @@
+//! `diff`: minimal scripts that apply back to the new text, and the
+//! wholesale fallback past the time or size budget.
+
+mod common;
+
+use common::{seeded, text, Rng};
+use kn_editor_core::{diff, CoreText, DiffOptions, EditOp, Granularity, MAX_EDIT_DISTANCE};
+
+const ALPHABET: &[char] = &['a', 'b', 'é', ' ', '\n', '中', '😀'];
+
+fn random_text(rng: &mut Rng, len: usize) -> String {
+    (0..len).map(|_| ALPHABET[rng.below(ALPHABET.len())]).collect()
+}
+
+fn apply(old: &str, ops: &[EditOp]) -> String {
+    let mut doc = CoreText::from_string(old);
+    doc.apply_ops(ops).unwrap();
+    text(&doc)
+}
+
+fn unbounded(granularity: Granularity) -> DiffOptions {
+    DiffOptions { granularity, timeout_ms: f64::INFINITY }
+}
+
+/// Edit distance of a script, in tokens.
+fn cost(ops: &[EditOp]) -> usize {
+    ops.iter()
+        .map(|op| match op {
+            EditOp::Del { len, .. } => *len,
+            EditOp::Ins { text, .. } => text.chars().count(),
+        })
+        .sum()
+}
+
+#[test]
+fn scripts_apply_back_to_the_new_text() {
+    seeded(300, |seed, rng| {
+        let (n, m) = (rng.below(40), rng.below(40));
+        let (old, new) = (random_text(rng, n), random_text(rng, m));
+        for granularity in [Granularity::Char, Granularity::Line] {
+            let ops = diff(&old, &new, &unbounded(granularity));
+            assert_eq!(apply(&old, &ops), new, "seed {seed} {granularity:?}");
+        }
+    });
+}
+
+#[test]
+fn scripts_are_minimal() {
+    let ops = diff("kitten", "sitting", &unbounded(Granularity::Char));
+    assert_eq!(
+        ops,
+        vec![
+            EditOp::Del { at: 0, len: 1 },
+            EditOp::Ins { at: 0, text: "s".into() },
+            EditOp::Del { at: 4, len: 1 },
+            EditOp::Ins { at: 4, text: "i".into() },
+            EditOp::Ins { at: 6, text: "g".into() },
+        ]
+    );
+    // Surrogate pairs are one token and offsets are UTF-16.
+    let ops = diff("😀a😀", "😀b😀", &unbounded(Granularity::Char));
+    assert_eq!(ops, vec![EditOp::Del { at: 2, len: 1 }, EditOp::Ins { at: 2, text: "b".into() }]);
+    assert_eq!(diff("same", "same", &DiffOptions::default()), Vec::<EditOp>::new());
+}
+
+#[test]
+fn lines_diff_whole_lines() {
+    let ops = diff("one\ntwo\nthree\n", "one\n2\nthree\n", &unbounded(Granularity::Line));
+    assert_eq!(ops, vec![EditOp::Del { at: 4, len: 4 }, EditOp::Ins { at: 4, text: "2\n".into() }]);
+}
+
+#[test]
+fn past_the_time_budget_the_middle_is_replaced_wholesale() {
+    let mut rng = Rng(3);
+    let old = format!("<{}>", random_text(&mut rng, 3000));
+    let new = format!("<{}>", random_text(&mut rng, 3000));
+    let ops = diff(&old, &new, &DiffOptions { granularity: Granularity::Char, timeout_ms: 0.0 });
+    assert_eq!(ops.len(), 2, "{ops:?}");
+    assert!(matches!(ops[0], EditOp::Del { at: 1, .. }));
+    assert_eq!(apply(&old, &ops), new);
+}
+
+#[test]
+fn past_the_edit_distance_cap_the_middle_is_replaced_wholesale() {
+    // Two long texts over a small alphabet share a long subsequence, so a
+    // minimal script is much shorter than replacing everything.
+    let mut rng = Rng(5);
+    let small = |rng: &mut Rng, len: usize| -> String { (0..len).map(|_| ['a', 'b'][rng.below(2)]).collect() };
+    let (old, new) = (small(&mut rng, MAX_EDIT_DISTANCE * 4), small(&mut rng, MAX_EDIT_DISTANCE * 4));
+    let ops = diff(&old, &new, &unbounded(Granularity::Char));
+    assert_eq!(ops.len(), 2, "{} ops", ops.len());
+    assert_eq!(apply(&old, &ops), new);
+
+    // Under the cap the search still finds the short script.
+    let (old, new) = (small(&mut rng, MAX_EDIT_DISTANCE / 2), small(&mut rng, MAX_EDIT_DISTANCE / 2));
+    let ops = diff(&old, &new, &unbounded(Granularity::Char));
+    assert!(ops.len() > 2 && cost(&ops) < MAX_EDIT_DISTANCE, "{} ops", ops.len());
+    assert_eq!(apply(&old, &ops), new);
+}
@@ -0,0 +1,112 @@
+//! Out-of-range input: the same `CoreError` from both backends (run with
+//! and without `--no-default-features`), the fields JS sees on the thrown
+//! error, and clamp mode pulling indices back instead.
+
+mod common;
+
+use common::text;
+use kn_editor_core::{CoreError, CoreText, EditOp, JsProp, Unit};
+
+type Edit = Box<dyn Fn(&mut CoreText) -> Result<(), CoreError>>;
+
+fn out_of_bounds(unit: Unit, index: usize, len: usize) -> CoreError {
+    CoreError::OutOfBounds { unit, index, len }
+}
+
+/// "a😀b\nc": 5 chars, 6 UTF-16 units, 8 bytes, 2 lines.
+fn doc() -> CoreText {
+    CoreText::from_string("a😀b\nc")
+}
+
+#[test]
+fn out_of_range_edits_fail_and_change_nothing() {
+    let cases: Vec<(&str, Edit, CoreError)> = vec![
+        ("insert", Box::new(|d| d.insert(6, "x")), out_of_bounds(Unit::Char, 6, 5)),
+        ("delete end", Box::new(|d| d.delete(3, 5)), out_of_bounds(Unit::Char, 8, 5)),
+        ("delete start", Box::new(|d| d.delete(usize::MAX, 1)), out_of_bounds(Unit::Char, usize::MAX, 5)),
+        ("insertUtf16", Box::new(|d| d.insert_utf16(7, "x")), out_of_bounds(Unit::Utf16, 7, 6)),
+        ("deleteUtf16", Box::new(|d| d.delete_utf16(5, 2)), out_of_bounds(Unit::Utf16, 7, 6)),
+        (
+            "applyOps",
+            Box::new(|d| d.apply_ops(&[EditOp::Ins { at: 0, text: "x".into() }, EditOp::Del { at: 6, len: 2 }]).map(drop)),
+            CoreError::InvalidOp { op: 1, error: Box::new(out_of_bounds(Unit::Utf16, 8, 7)) },
+        ),
+    ];
+    for (name, edit, expected) in cases {
+        let mut doc = doc();
+        assert_eq!(edit(&mut doc), Err(expected), "{name}");
+        assert_eq!(text(&doc), "a😀b\nc", "{name}");
+    }
+}
+
+#[test]
+fn out_of_range_queries_fail() {
+    let doc = doc();
+    assert_eq!(doc.slice(0, 6), Err(out_of_bounds(Unit::Char, 6, 5)));
+    assert_eq!(doc.slice(6, 9), Err(out_of_bounds(Unit::Char, 6, 5)));
+    assert_eq!(doc.slice(3, 1), Err(CoreError::InvertedRange { start: 3, end: 1 }));
+    assert_eq!(doc.slice_utf16(2, 7), Err(out_of_bounds(Unit::Utf16, 7, 6)));
+    assert_eq!(doc.char_to_byte(6), Err(out_of_bounds(Unit::Char, 6, 5)));
+    assert_eq!(doc.byte_to_char(10), Err(out_of_bounds(Unit::Byte, 10, 8)));
+    assert_eq!(doc.utf16_to_char(7), Err(out_of_bounds(Unit::Utf16, 7, 6)));
+    assert_eq!(doc.line_of_offset(7), Err(out_of_bounds(Unit::Utf16, 7, 6)));
+    // Lines: `lineToChar` takes one past the last line, the rest do not.
+    assert_eq!(doc.line_to_char(3), Err(out_of_bounds(Unit::Line, 3, 2)));
+    assert_eq!(doc.line_start(2), Err(out_of_bounds(Unit::Line, 2, 1)));
+    assert_eq!(doc.line_slice(2), Err(out_of_bounds(Unit::Line, 2, 1)));
+}
+
+#[test]
+fn errors_carry_a_code_and_indices_for_js() {
+    use JsProp::{Num, Str};
+    let err = doc().delete(3, 5).unwrap_err();
+    assert_eq!(err.to_string(), "char index 8 out of bounds (len 5)");
+    assert_eq!(err.js_props(), [("code", Str("OUT_OF_BOUNDS")), ("unit", Str("char")), ("index", Num(8.0)), ("len", Num(5.0))]);
+
+    let err = doc().slice(3, 1).unwrap_err();
+    assert_eq!(err.js_props(), [("code", Str("INVERTED_RANGE")), ("start", Num(3.0)), ("end", Num(1.0))]);
+
+    // A failed batch names the op and why it failed.
+    let err = doc().apply_ops(&[EditOp::Del { at: 6, len: 2 }]).unwrap_err();
+    assert_eq!(err.to_string(), "op 0: utf16 index 8 out of bounds (len 6)");
+    assert_eq!(
+        err.js_props(),
+        [
+            ("code", Str("INVALID_OP")),
+            ("op", Num(0.0)),
+            ("reason", Str("OUT_OF_BOUNDS")),
+            ("unit", Str("utf16")),
+            ("index", Num(8.0)),
+            ("len", Num(6.0)),
+        ]
+    );
+
+    let err = CoreText::new().commit().unwrap_err();
+    assert_eq!(err.js_props(), [("code", Str("NO_TRANSACTION"))]);
+}
+
+#[test]
+fn clamp_mode_clamps_instead() {
+    let mut doc = doc();
+    doc.set_clamp_mode(true);
+    assert_eq!(doc.slice(3, 99).unwrap(), "\nc");
+    assert_eq!(doc.slice(99, 1).unwrap(), "😀b\nc");
+    assert_eq!(doc.slice_utf16(4, 1).unwrap(), "😀b");
+    assert_eq!(doc.utf16_to_char(99).unwrap(), 5);
+    assert_eq!(doc.byte_to_char(99).unwrap(), 5);
+    assert_eq!(doc.line_start(9).unwrap(), 5);
+    assert_eq!(doc.line_slice(9).unwrap(), "c");
+
+    doc.insert(99, "!").unwrap();
+    assert_eq!(text(&doc), "a😀b\nc!");
+    doc.insert_utf16(99, "?").unwrap();
+    assert_eq!(text(&doc), "a😀b\nc!?");
+    doc.delete(4, 99).unwrap();
+    assert_eq!(text(&doc), "a😀b\n");
+    doc.delete_utf16(usize::MAX, 1).unwrap();
+    assert_eq!(text(&doc), "a😀b\n");
+
+    // Off again, the same calls fail.
+    doc.set_clamp_mode(false);
+    assert!(matches!(doc.insert(99, "!"), Err(CoreError::OutOfBounds { unit: Unit::Char, index: 99, len: 4 })));
+}
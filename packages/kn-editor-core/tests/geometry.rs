@@ -0,0 +1,119 @@
+//! Selection geometry against `selectionLineSegmentsMonospace` from
+//! `geometry.ts`, ported below line for line: equal on plain text, and
+//! equal with UTF-16 units swapped for columns where tabs and wide
+//! clusters make the two differ.
+
+use kn_editor_core::{CoreText, Segment};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// `selectionLineSegmentsMonospace` with its width arithmetic passed in:
+/// `measure(line, from, to)` is the width of UTF-16 offsets `from..to` of
+/// `line`, both relative to the line start. `geometry.ts` uses `to - from`.
+fn ts_segments(text: &str, start: usize, end: usize, measure: &dyn Fn(&str, usize, usize) -> usize) -> Vec<Segment> {
+    let units: Vec<u16> = text.encode_utf16().collect();
+    let nl = |i: usize| units[i] == u16::from(b'\n');
+    let a = start.min(end);
+    let b = a.max(start.max(end));
+    let count_lines = |index: usize| (0..index.min(units.len())).filter(|&i| nl(i)).count();
+    let last_line_start = |index: usize| (0..index).rev().find(|&i| nl(i)).map_or(0, |p| p + 1);
+    let line_end = |index: usize| (index..units.len()).find(|&i| nl(i)).unwrap_or(units.len());
+    let line = |from: usize| String::from_utf16(&units[from..line_end(from)]).unwrap();
+    let (row_a, row_b) = (count_lines(a), count_lines(b));
+    let (line_start_a, line_start_b) = (last_line_start(a), last_line_start(b));
+
+    let x = measure(&line(line_start_a), 0, a - line_start_a);
+    if row_a == row_b {
+        let width = measure(&line(line_start_a), a - line_start_a, b - line_start_a);
+        return vec![Segment { row: row_a, x, width: width.max(1) }];
+    }
+    let mut segs = Vec::new();
+    let end_of_line_a = line_end(a);
+    let width = measure(&line(line_start_a), a - line_start_a, end_of_line_a - line_start_a);
+    segs.push(Segment { row: row_a, x, width: width.max(1) });
+    let mut s = end_of_line_a + 1;
+    for r in row_a + 1..row_b {
+        let e = line_end(s);
+        segs.push(Segment { row: r, x: 0, width: measure(&line(s), 0, e - s).max(1) });
+        s = e + 1;
+    }
+    segs.push(Segment { row: row_b, x: 0, width: measure(&line(line_start_b), 0, b - line_start_b).max(1) });
+    segs
+}
+
+fn units(_: &str, from: usize, to: usize) -> usize {
+    to - from
+}
+
+/// Columns from the line start to UTF-16 offset `to`, cluster by cluster;
+/// a cluster `to` falls inside does not count.
+fn column(line: &str, to: usize, tab: usize) -> usize {
+    let mut col = 0;
+    let mut at = 0;
+    for cluster in line.graphemes(true) {
+        at += cluster.encode_utf16().count();
+        if at > to {
+            break;
+        }
+        col = if cluster == "\t" { col + tab - col % tab } else { col + cluster.width() };
+    }
+    col
+}
+
+fn check_all_ranges(text: &str, tab: usize, measure: &dyn Fn(&str, usize, usize) -> usize) {
+    let doc = CoreText::from_string(text);
+    let len = doc.len_utf16();
+    for start in 0..=len {
+        for end in 0..=len {
+            assert_eq!(
+                doc.selection_segments(start, end, tab).unwrap(),
+                ts_segments(text, start, end, measure),
+                "{text:?} {start}..{end}"
+            );
+        }
+    }
+}
+
+#[test]
+fn plain_text_matches_geometry_ts() {
+    for text in ["", "x", "hello", "ab\ncd", "ab\n\ncd\nefg\n", "\n\n\n", "one\n\n\ntwo\nthree"] {
+        check_all_ranges(text, 4, &units);
+    }
+}
+
+#[test]
+fn tabs_and_wide_clusters_count_columns() {
+    for text in [
+        "\tx\n  \tab\n\t\t",
+        "中文\n\nx中",
+        "👨‍👩‍👧 family\n🇯🇵 flag\n❤️ vs16\ne\u{301}\u{301} marks",
+        "a\t中\t👍🏽\n\n\t",
+    ] {
+        for tab in [2, 4, 8] {
+            check_all_ranges(text, tab, &|line, from, to| column(line, to, tab) - column(line, from, tab));
+        }
+    }
+}
+
+#[test]
+fn cluster_widths() {
+    let seg = |text: &str, start, end| CoreText::from_string(text).selection_segments(start, end, 4).unwrap();
+    let one = |row, x, width| vec![Segment { row, x, width }];
+    // One two-column cell each, however many code units.
+    assert_eq!(seg("👨‍👩‍👧x", 0, 8), one(0, 0, 2));
+    assert_eq!(seg("🇯🇵x", 0, 4), one(0, 0, 2));
+    assert_eq!(seg("❤️x", 0, 2), one(0, 0, 2));
+    assert_eq!(seg("❤️x", 2, 3), one(0, 2, 1));
+    // Combining marks ride on their base.
+    assert_eq!(seg("e\u{301}x", 2, 3), one(0, 1, 1));
+    // Tab stops depend on the column the tab starts at.
+    assert_eq!(seg("ab\tc", 3, 4), one(0, 4, 1));
+    assert_eq!(seg("中\tc", 2, 3), one(0, 4, 1));
+    // Ends inside a cluster count from its start.
+    assert_eq!(seg("👨‍👩‍👧x", 3, 9), one(0, 0, 3));
+    // Multi-line, through an empty line.
+    assert_eq!(
+        seg("\t中\n\n👍🏽x", 1, 9),
+        [Segment { row: 0, x: 4, width: 2 }, Segment { row: 1, x: 0, width: 1 }, Segment { row: 2, x: 0, width: 3 }]
+    );
+}
@@ -0,0 +1,88 @@
+//! Grapheme cluster boundaries against `unicode-segmentation` over the
+//! whole string, across buffer chunks and from inside surrogate pairs.
+
+mod common;
+
+use common::{seeded, text};
+use kn_editor_core::{CoreText, Selection};
+use unicode_segmentation::UnicodeSegmentation;
+
+const CLUSTERS: &[&str] = &[
+    "a",
+    "e\u{301}",
+    "\r\n",
+    "\n",
+    "😀",
+    "🇫🇷",
+    "👩\u{200d}👩\u{200d}👧",
+    "👍🏽",
+    "क्षि",
+    "中",
+    "\u{301}",
+];
+
+/// Cluster boundaries of `s` in UTF-16 offsets, both ends included.
+fn boundaries(s: &str) -> Vec<usize> {
+    let mut out = vec![0];
+    let mut at = 0;
+    for g in s.graphemes(true) {
+        at += g.encode_utf16().count();
+        out.push(at);
+    }
+    out
+}
+
+#[test]
+fn boundaries_match_a_whole_string_segmentation() {
+    seeded(8, |seed, rng| {
+        // Long enough to span several buffer chunks.
+        let s: String = (0..1200).map(|_| CLUSTERS[rng.below(CLUSTERS.len())]).collect();
+        let doc = CoreText::from_string(&s);
+        let expected = boundaries(&s);
+        let len = doc.len_utf16();
+        assert_eq!(*expected.last().unwrap(), len);
+
+        for offset in 0..=len {
+            let i = expected.partition_point(|&b| b <= offset);
+            let next = expected.get(i).copied().unwrap_or(len);
+            let prev = expected[..expected.partition_point(|&b| b < offset)].last().copied().unwrap_or(0);
+            assert_eq!(doc.next_grapheme_boundary(offset).unwrap(), next, "seed {seed} offset {offset}");
+            assert_eq!(doc.prev_grapheme_boundary(offset).unwrap(), prev, "seed {seed} offset {offset}");
+            assert_eq!(
+                doc.is_grapheme_boundary(offset).unwrap(),
+                expected.binary_search(&offset).is_ok(),
+                "seed {seed} offset {offset}"
+            );
+        }
+        assert!(doc.next_grapheme_boundary(len + 1).is_err());
+    });
+}
+
+#[test]
+fn boundaries_follow_edits() {
+    let mut doc = CoreText::from_string("e");
+    assert_eq!(doc.next_grapheme_boundary(0).unwrap(), 1);
+    doc.insert_utf16(1, "\u{301}").unwrap();
+    assert_eq!(doc.next_grapheme_boundary(0).unwrap(), 2);
+    assert!(!doc.is_grapheme_boundary(1).unwrap());
+
+    // Two regional indicators pair up into one flag.
+    let mut doc = CoreText::from_string("🇫x");
+    doc.delete_utf16(2, 1).unwrap();
+    doc.insert_utf16(2, "🇷").unwrap();
+    assert_eq!(doc.next_grapheme_boundary(0).unwrap(), 4);
+}
+
+#[test]
+fn deletion_takes_whole_clusters_at_every_caret() {
+    let mut doc = CoreText::from_string("a🇫🇷|e\u{301}|\r\nb");
+    doc.set_selections(&[Selection { anchor: 5, head: 5 }, Selection { anchor: 8, head: 8 }, Selection { anchor: 11, head: 11 }])
+        .unwrap();
+    doc.delete_backward();
+    assert_eq!(text(&doc), "a||b");
+    doc.undo();
+    doc.set_selections(&[Selection { anchor: 1, head: 1 }, Selection { anchor: 6, head: 6 }, Selection { anchor: 9, head: 9 }])
+        .unwrap();
+    doc.delete_forward();
+    assert_eq!(text(&doc), "a||b");
+}
@@ -0,0 +1,120 @@
+//! Undo/redo: units walk the text back and forth exactly, and typing runs
+//! and explicit groups decide what a unit holds.
+
+mod common;
+
+use common::{edit, seeded, text};
+use kn_editor_core::{CoreText, TextRange, UndoResult};
+
+const PIECES: &[&str] = &["a", "b", "é", "😀", " ", "\n", "xy"];
+
+/// Units of random edits, some grouped, some sealed, some left to merge:
+/// undoing walks back through exactly the texts seen between units, and
+/// redoing walks forward again.
+#[test]
+fn undo_and_redo_walk_through_every_unit() {
+    seeded(200, |seed, rng| {
+        let mut doc = CoreText::from_string("start");
+        let mut texts = vec![text(&doc)];
+        for _ in 0..30 {
+            match rng.below(3) {
+                0 => {
+                    doc.begin_undo_group();
+                    for _ in 0..1 + rng.below(4) {
+                        edit(rng, &mut doc, PIECES);
+                    }
+                    doc.end_undo_group();
+                }
+                _ => {
+                    edit(rng, &mut doc, PIECES);
+                    doc.seal_undo();
+                }
+            }
+            if texts.last() != Some(&text(&doc)) {
+                texts.push(text(&doc));
+            }
+        }
+
+        let mut back = vec![text(&doc)];
+        while doc.undo().is_some() {
+            back.push(text(&doc));
+        }
+        back.dedup();
+        back.reverse();
+        assert_eq!(back, texts, "seed {seed}");
+        while doc.redo().is_some() {}
+        assert_eq!(&text(&doc), texts.last().unwrap(), "seed {seed}");
+    });
+}
+
+#[test]
+fn typing_runs_merge_until_a_newline_or_seal() {
+    let mut doc = CoreText::from_string("");
+    for (i, c) in "ab\ncd".chars().enumerate() {
+        doc.insert_utf16(i, &c.to_string()).unwrap();
+    }
+    doc.seal_undo();
+    doc.insert_utf16(5, "e").unwrap();
+    assert_eq!(text(&doc), "ab\ncde");
+
+    doc.undo();
+    assert_eq!(text(&doc), "ab\ncd");
+    doc.undo();
+    assert_eq!(text(&doc), "ab");
+    doc.undo();
+    assert_eq!(text(&doc), "");
+    assert!(!doc.can_undo());
+}
+
+#[test]
+fn backspace_and_forward_delete_runs_merge() {
+    let mut doc = CoreText::from_string("abcdef");
+    doc.delete_utf16(3, 1).unwrap();
+    doc.delete_utf16(2, 1).unwrap();
+    doc.delete_utf16(2, 1).unwrap();
+    assert_eq!(text(&doc), "abf");
+    doc.undo();
+    assert_eq!(text(&doc), "abcdef");
+    assert!(!doc.can_undo());
+}
+
+#[test]
+fn groups_nest_and_empty_ones_leave_nothing() {
+    let mut doc = CoreText::from_string("x");
+    doc.begin_undo_group();
+    doc.insert_utf16(0, "a").unwrap();
+    doc.begin_undo_group();
+    doc.insert_utf16(2, "\nb").unwrap();
+    doc.end_undo_group();
+    // No undo while a group is open.
+    assert!(doc.undo().is_none());
+    doc.end_undo_group();
+    assert_eq!(text(&doc), "ax\nb");
+
+    doc.begin_undo_group();
+    doc.end_undo_group();
+    doc.undo();
+    assert_eq!(text(&doc), "x");
+    assert!(!doc.can_undo());
+}
+
+#[test]
+fn a_new_edit_drops_the_redo_stack() {
+    let mut doc = CoreText::from_string("");
+    doc.insert_utf16(0, "a").unwrap();
+    doc.undo();
+    assert!(doc.can_redo());
+    doc.insert_utf16(0, "b").unwrap();
+    assert!(!doc.can_redo());
+    assert!(doc.redo().is_none());
+}
+
+#[test]
+fn results_select_restored_text_or_place_a_caret() {
+    let mut doc = CoreText::from_string("hello world");
+    doc.delete_utf16(5, 6).unwrap();
+    let restored = TextRange { start: 5, end: 11 };
+    assert_eq!(doc.undo(), Some(UndoResult { range: restored, selection: restored }));
+    let caret = TextRange { start: 5, end: 5 };
+    assert_eq!(doc.redo(), Some(UndoResult { range: caret, selection: caret }));
+}
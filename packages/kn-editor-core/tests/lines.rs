@@ -0,0 +1,83 @@
+//! The line API against `split('\n')`, through edits that add, join and
+//! remove lines; on the String fallback (`--no-default-features`) that is
+//! the incremental line-start table. Plus the cursor-movement budget.
+
+mod common;
+
+use std::time::Instant;
+
+use common::{p95_ms, perf_budget_ms, seeded, text};
+use kn_editor_core::CoreText;
+
+const PIECES: &[&str] = &["a", "\n", "\n\n", "é\n", "😀", "ab\ncd", "中"];
+
+fn check_lines(doc: &CoreText, context: &str) {
+    let s = text(doc);
+    let lines: Vec<&str> = s.split('\n').collect();
+    assert_eq!(doc.len_lines(), lines.len(), "{context}");
+    let mut start = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let end = start + line.encode_utf16().count();
+        assert_eq!(doc.line_start(i).unwrap(), start, "{context}: line {i}");
+        assert_eq!(doc.line_end(i).unwrap(), end, "{context}: line {i}");
+        assert_eq!(doc.line_slice(i).unwrap(), *line, "{context}: line {i}");
+        // The `\n` belongs to the line it ends.
+        for offset in start..=end {
+            assert_eq!(doc.line_of_offset(offset).unwrap(), i, "{context}: offset {offset}");
+        }
+        start = end + 1;
+    }
+    assert!(doc.line_start(lines.len()).is_err(), "{context}");
+}
+
+#[test]
+fn lines_follow_random_edits() {
+    seeded(100, |seed, rng| {
+        let mut doc = CoreText::from_string("one\ntwo\n\nfour");
+        for step in 0..50 {
+            let len = doc.len_chars();
+            if len > 0 && rng.below(3) == 0 {
+                let at = rng.below(len);
+                doc.delete(at, 1 + rng.below((len - at).min(6))).unwrap();
+            } else {
+                let piece = *rng.pick(PIECES);
+                doc.insert(rng.below(len + 1), piece).unwrap();
+            }
+            check_lines(&doc, &format!("seed {seed} step {step}"));
+        }
+    });
+}
+
+#[test]
+fn trailing_and_lone_newlines() {
+    for s in ["", "\n", "\n\n", "a\n", "\na"] {
+        check_lines(&CoreText::from_string(s), &format!("{s:?}"));
+    }
+}
+
+/// `performance-architecture.md`: cursor movement across 10k lines, p95
+/// under 2ms per move, with typing in between so the line table is
+/// updated, not just read.
+#[test]
+fn cursor_moves_on_10k_lines_meet_the_budget() {
+    // 1,000 paragraphs of nine lines and a blank one.
+    let paragraph = "The quick brown fox jumps over the lazy dog.\n".repeat(9) + "\n";
+    let mut doc = CoreText::from_string(&paragraph.repeat(1_000));
+    let mut rng = common::Rng(7919);
+    let mut moves = Vec::new();
+    for i in 0..2_000 {
+        let started = Instant::now();
+        let row = doc.line_of_offset(rng.below(doc.len_utf16() + 1)).unwrap();
+        let target = (row + 1).min(doc.len_lines() - 1);
+        let col = (doc.line_end(target).unwrap() - doc.line_start(target).unwrap()).min(10);
+        std::hint::black_box(doc.line_slice(target).unwrap());
+        moves.push(started.elapsed());
+        if i % 4 == 0 {
+            doc.insert_utf16(doc.line_start(target).unwrap() + col, "x\n").unwrap();
+        }
+    }
+    assert_eq!(doc.len_lines(), 10_001 + 500);
+    let p95 = p95_ms(moves);
+    let budget = perf_budget_ms(2.0, 10.0);
+    assert!(p95 < budget, "p95 {p95:.3}ms over {budget}ms");
+}
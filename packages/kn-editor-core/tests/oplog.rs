@@ -0,0 +1,154 @@
+//! `OpLog`: replay from checkpoints, compaction, coalescing and stamps.
+
+mod common;
+
+use common::{seeded, text};
+use kn_editor_core::{CoreError, CoreText, EditOp, OpLog};
+
+fn ins(at: usize, text: &str) -> Vec<EditOp> {
+    vec![EditOp::Ins { at, text: text.into() }]
+}
+
+fn del(at: usize, len: usize) -> Vec<EditOp> {
+    vec![EditOp::Del { at, len }]
+}
+
+/// A log that coalesces whatever the test's pace.
+fn typing_log(text: &str) -> OpLog {
+    let mut log = OpLog::new(text);
+    log.set_coalesce_ms(60_000.0);
+    log
+}
+
+/// Every retained version replays to the text a `CoreText` had there,
+/// through checkpoints and compaction.
+#[test]
+fn snapshots_replay_every_retained_version() {
+    seeded(100, |seed, rng| {
+        let mut doc = CoreText::from_string("start");
+        let mut log = OpLog::new("start");
+        log.set_coalesce_ms(if seed % 2 == 0 { 0.0 } else { 60_000.0 });
+        let mut texts = vec![(0u64, text(&doc))];
+        for _ in 0..60 {
+            let len = doc.len_utf16();
+            let ops = match rng.below(4) {
+                0 if len > 0 => del(rng.below(len), 1),
+                1 => ins(rng.below(len + 1), ["a", "é", "\n", "中"][rng.below(4)]),
+                _ => ins(rng.below(len + 1), "t"),
+            };
+            doc.apply_ops(&ops).unwrap();
+            log.append(rng.below(2) as u32, &ops).unwrap();
+            texts.retain(|(v, _)| *v < log.version());
+            texts.push((log.version(), text(&doc)));
+
+            let span = (log.version() - log.base_version()) as usize + 1;
+            match rng.below(10) {
+                0 => log.checkpoint(log.base_version() + rng.below(span) as u64).unwrap(),
+                1 => log.compact(log.base_version() + rng.below(span) as u64).unwrap(),
+                _ => {}
+            }
+            for (v, t) in &texts {
+                match log.snapshot_at(*v) {
+                    Ok(snapshot) => assert_eq!(&snapshot.text(), t, "seed {seed} version {v}"),
+                    Err(_) => assert!(*v < log.base_version(), "seed {seed} version {v}"),
+                }
+            }
+        }
+
+        // The entries since the base replay it to the head.
+        let mut replay = CoreText::from_string(&log.snapshot_at(log.base_version()).unwrap().text());
+        let base = log.base_version();
+        for entry in log.entries_since(base).unwrap() {
+            replay.apply_ops(&entry.ops).unwrap();
+        }
+        assert_eq!(text(&replay), text(&doc), "seed {seed}");
+    });
+}
+
+#[test]
+fn compaction_drops_older_versions() {
+    let mut log = OpLog::new("");
+    log.set_coalesce_ms(0.0);
+    for (i, c) in ["a", "b", "c", "d"].iter().enumerate() {
+        log.append(1, &ins(i, c)).unwrap();
+    }
+    log.checkpoint(1).unwrap();
+    log.checkpoint(3).unwrap();
+    log.compact(2).unwrap();
+    assert_eq!(log.base_version(), 2);
+    assert_eq!(log.checkpoints(), [2, 3]);
+    assert_eq!(log.snapshot_at(2).unwrap().text(), "ab");
+    assert_eq!(log.entries_since(2).unwrap().len(), 2);
+    assert!(matches!(log.snapshot_at(1), Err(CoreError::UnknownVersion { version: 1, oldest: 2, latest: 4 })));
+    assert!(log.entries_since(5).is_err());
+}
+
+#[test]
+fn typing_coalesces_into_one_entry() {
+    let mut log = typing_log("hi ");
+    log.append(1, &ins(3, "w")).unwrap();
+    log.append(1, &ins(4, "o")).unwrap();
+    let entry = log.append(1, &del(4, 1)).unwrap().unwrap();
+    assert_eq!((entry.version, entry.ops), (1, ins(3, "w")));
+    // A newline, or another author, starts a new entry.
+    assert_eq!(log.append(1, &ins(4, "\n")).unwrap().unwrap().version, 2);
+    assert_eq!(log.append(2, &ins(5, "x")).unwrap().unwrap().version, 3);
+}
+
+#[test]
+fn entries_that_cancel_out_keep_their_version() {
+    let mut log = typing_log("hi ");
+    let sent = log.append(1, &ins(3, "w")).unwrap().unwrap();
+    log.append(1, &ins(4, "o")).unwrap();
+    log.append(1, &del(4, 1)).unwrap();
+    let cancelled = log.append(1, &del(3, 1)).unwrap().unwrap();
+    assert_eq!((cancelled.version, cancelled.ops), (sent.version, vec![]));
+    assert_eq!(log.version(), 1);
+    assert_eq!(log.entries_since(0).unwrap()[0].ops, vec![]);
+    assert_eq!(log.snapshot_at(1).unwrap().text(), "hi ");
+    // Sealed: the next keystroke is a new version, not version 1 again.
+    assert_eq!(log.append(1, &ins(3, "x")).unwrap().unwrap().version, 2);
+}
+
+#[test]
+fn entries_handed_out_never_change() {
+    let mut log = typing_log("");
+    log.append(1, &ins(0, "a")).unwrap();
+    let sent = log.entries_since(0).unwrap().to_vec();
+    let next = log.append(1, &ins(1, "b")).unwrap().unwrap();
+    assert_eq!(next.version, 2);
+    assert_eq!(log.entries_since(0).unwrap()[0], sent[0]);
+
+    // Same for a snapshot of the head.
+    let snapshot = log.snapshot_at(2).unwrap();
+    log.append(1, &ins(2, "c")).unwrap();
+    assert_eq!(log.version(), 3);
+    assert_eq!(log.snapshot_at(2).unwrap().text(), snapshot.text());
+}
+
+#[test]
+fn remote_stamps_are_kept_as_sent() {
+    let mut log = OpLog::new("");
+    log.set_coalesce_ms(0.0);
+    assert_eq!(log.append(1, &ins(0, "a")).unwrap().unwrap().lamport, 1);
+    assert_eq!(log.append_remote(2, &ins(0, "b"), 40).unwrap().unwrap().lamport, 40);
+    assert_eq!(log.append(1, &ins(0, "c")).unwrap().unwrap().lamport, 41);
+    // A stamp from behind is stored as sent and leaves the clock alone.
+    assert_eq!(log.append_remote(3, &ins(0, "d"), 7).unwrap().unwrap().lamport, 7);
+    assert_eq!(log.clock(), 41);
+    assert_eq!(log.append(1, &ins(0, "e")).unwrap().unwrap().lamport, 42);
+    // Empty batches tick nothing.
+    assert_eq!(log.append(1, &[]).unwrap(), None);
+    assert_eq!(log.clock(), 42);
+    let stamps: Vec<u64> = log.entries_since(0).unwrap().iter().map(|e| e.lamport).collect();
+    assert_eq!(stamps, [1, 40, 41, 7, 42]);
+}
+
+#[test]
+fn bad_batches_change_nothing() {
+    let mut log = OpLog::new("abc");
+    assert!(log.append(1, &del(2, 5)).is_err());
+    assert_eq!(log.version(), 0);
+    assert_eq!(log.clock(), 0);
+    assert_eq!(log.snapshot_at(0).unwrap().text(), "abc");
+}
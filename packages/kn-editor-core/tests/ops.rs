@@ -0,0 +1,40 @@
+//! `applyOps`: whole batches checked up front, or clamped op by op.
+
+mod common;
+
+use common::text;
+use kn_editor_core::{CoreError, CoreText, EditOp, TextRange};
+
+#[test]
+fn a_bad_op_leaves_the_text_untouched() {
+    let mut doc = CoreText::from_string("abc");
+    let ops = [EditOp::Ins { at: 3, text: "d".into() }, EditOp::Del { at: 2, len: 5 }];
+    assert!(matches!(doc.apply_ops(&ops), Err(CoreError::InvalidOp { op: 1, .. })));
+    assert_eq!(text(&doc), "abc");
+}
+
+#[test]
+fn the_range_covers_every_op() {
+    let mut doc = CoreText::from_string("hello world");
+    let ops = [
+        EditOp::Del { at: 0, len: 1 },
+        EditOp::Ins { at: 0, text: "J".into() },
+        EditOp::Ins { at: 11, text: "!".into() },
+    ];
+    assert_eq!(doc.apply_ops(&ops).unwrap(), Some(TextRange { start: 0, end: 12 }));
+    assert_eq!(text(&doc), "Jello world!");
+    assert_eq!(doc.apply_ops(&[]).unwrap(), None);
+}
+
+#[test]
+fn clamp_mode_reports_the_range_as_clamped() {
+    let mut doc = CoreText::from_string("abc");
+    doc.set_clamp_mode(true);
+    let range = doc.apply_ops(&[EditOp::Ins { at: 99, text: "d".into() }, EditOp::Del { at: 2, len: 50 }]).unwrap();
+    assert_eq!(text(&doc), "ab");
+    assert_eq!(range, Some(TextRange { start: 2, end: 4 }));
+
+    let range = doc.apply_ops(&[EditOp::Del { at: 40, len: 2 }]).unwrap();
+    assert_eq!(text(&doc), "ab");
+    assert_eq!(range, Some(TextRange { start: 2, end: 2 }));
+}
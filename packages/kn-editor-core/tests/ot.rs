@@ -0,0 +1,169 @@
+//! Randomized convergence checks for `transform`, `compose` and `invert`.
+
+mod common;
+
+use common::{seeded, Rng};
+use kn_editor_core::{compose, invert, transform, CoreText, EditOp};
+
+// Astral characters too, so offsets can land inside a surrogate pair.
+const ALPHABET: &[char] = &['a', 'b', 'c', 'é', ' ', '\n', '中', '😀', '𝄞'];
+
+fn text(rng: &mut Rng, max: usize) -> String {
+    (0..rng.below(max + 1)).map(|_| ALPHABET[rng.below(ALPHABET.len())]).collect()
+}
+
+/// Up to `max` sequential ops against `base`, at any UTF-16 offset.
+fn edits(rng: &mut Rng, base: &str, max: usize) -> Vec<EditOp> {
+    edits_at(rng, base, max, |_, at| at)
+}
+
+/// As `edits`, with every offset on a character boundary.
+fn whole_char_edits(rng: &mut Rng, base: &str, max: usize) -> Vec<EditOp> {
+    edits_at(rng, base, max, |doc, at| if at > 0 && at < doc.len() && is_low(doc[at]) { at - 1 } else { at })
+}
+
+fn is_low(unit: u16) -> bool {
+    (0xDC00..0xE000).contains(&unit)
+}
+
+fn edits_at(rng: &mut Rng, base: &str, max: usize, pick: impl Fn(&[u16], usize) -> usize) -> Vec<EditOp> {
+    let mut doc: Vec<u16> = base.encode_utf16().collect();
+    let mut ops = Vec::new();
+    for _ in 0..rng.below(max + 1) {
+        // What `CoreText` does with an offset: one inside a pair moves
+        // before it.
+        let floor = |doc: &[u16], i: usize| if i > 0 && i < doc.len() && is_low(doc[i]) { i - 1 } else { i };
+        if !doc.is_empty() && rng.below(2) == 0 {
+            let mut at = pick(&doc, rng.below(doc.len()));
+            let mut end = pick(&doc, (at + 1 + rng.below(4)).min(doc.len()));
+            // Both ends inside a pair or neither, so the batch deletes what
+            // it says and later offsets stay in bounds.
+            if (floor(&doc, at) == at) != (floor(&doc, end) == end) {
+                (at, end) = (floor(&doc, at), floor(&doc, end));
+            }
+            ops.push(EditOp::Del { at, len: end - at });
+            doc.drain(floor(&doc, at)..floor(&doc, end));
+        } else {
+            let at = pick(&doc, rng.below(doc.len() + 1));
+            let text = text(rng, 3);
+            let floored = floor(&doc, at);
+            doc.splice(floored..floored, text.encode_utf16());
+            ops.push(EditOp::Ins { at, text });
+        }
+    }
+    ops
+}
+
+fn apply(base: &str, ops: &[EditOp]) -> String {
+    let mut doc = CoreText::from_string(base);
+    doc.apply_ops(ops).unwrap();
+    doc.slice(0, doc.len_chars()).unwrap()
+}
+
+#[test]
+fn transform_converges() {
+    seeded(500, |seed, rng| {
+        let base = text(rng, 12);
+        let a = whole_char_edits(rng, &base, 5);
+        let b = whole_char_edits(rng, &base, 5);
+        let (a2, b2) = transform(&a, &b);
+        let left = apply(&apply(&base, &a), &b2);
+        let right = apply(&apply(&base, &b), &a2);
+        assert_eq!(left, right, "seed {seed}: {base:?} a={a:?} b={b:?}");
+    });
+}
+
+#[test]
+fn compose_matches_sequential() {
+    seeded(500, |seed, rng| {
+        let base = text(rng, 12);
+        let a = whole_char_edits(rng, &base, 5);
+        let mid = apply(&base, &a);
+        let b = whole_char_edits(rng, &mid, 5);
+        assert_eq!(apply(&base, &compose(&a, &b)), apply(&mid, &b), "seed {seed}: {base:?} a={a:?} b={b:?}");
+    });
+}
+
+#[test]
+fn invert_restores_base() {
+    seeded(500, |seed, rng| {
+        let base = text(rng, 12);
+        let a = edits(rng, &base, 5);
+        let undo = invert(&a, &base).unwrap();
+        assert_eq!(apply(&apply(&base, &a), &undo), base, "seed {seed}: {base:?} a={a:?}");
+    });
+}
+
+#[test]
+fn rebase_behind_client() {
+    // The relay rebases a client's batch over everything it missed.
+    seeded(200, |seed, rng| {
+        let base = text(rng, 12);
+        let missed1 = whole_char_edits(rng, &base, 3);
+        let mid = apply(&base, &missed1);
+        let missed2 = whole_char_edits(rng, &mid, 3);
+        let server = apply(&mid, &missed2);
+        let client = whole_char_edits(rng, &base, 4);
+        let (rebased, server2) = transform(&client, &compose(&missed1, &missed2));
+        assert_eq!(apply(&server, &rebased), apply(&apply(&base, &client), &server2), "seed {seed}");
+    });
+}
+
+#[test]
+fn concurrent_inserts_at_same_spot() {
+    let a = vec![EditOp::Ins { at: 1, text: "A".into() }];
+    let b = vec![EditOp::Ins { at: 1, text: "B".into() }];
+    let (a2, b2) = transform(&a, &b);
+    assert_eq!(apply(&apply("xy", &a), &b2), "xABy");
+    assert_eq!(apply(&apply("xy", &b), &a2), "xABy");
+}
+
+#[test]
+fn overlapping_deletes() {
+    let a = vec![EditOp::Del { at: 1, len: 3 }];
+    let b = vec![EditOp::Del { at: 2, len: 3 }];
+    let (a2, b2) = transform(&a, &b);
+    assert_eq!(a2, vec![EditOp::Del { at: 1, len: 1 }]);
+    assert_eq!(b2, vec![EditOp::Del { at: 1, len: 1 }]);
+    assert_eq!(apply(&apply("abcdefg", &a), &b2), "afg");
+}
+
+#[test]
+fn insert_inside_concurrent_delete() {
+    let a = vec![EditOp::Del { at: 1, len: 4 }];
+    let b = vec![EditOp::Ins { at: 3, text: "X".into() }];
+    let (a2, b2) = transform(&a, &b);
+    assert_eq!(apply(&apply("abcdef", &a), &b2), "aXf");
+    assert_eq!(apply(&apply("abcdef", &b), &a2), "aXf");
+}
+
+#[test]
+fn invert_checks_ops_against_base() {
+    assert!(invert(&[EditOp::Del { at: 2, len: 5 }], "abc").is_err());
+}
+
+#[test]
+fn compose_never_splits_an_inserted_pair() {
+    let a = vec![EditOp::Ins { at: 1, text: "😀".into() }];
+    // Inside the pair: lands before it, as `CoreText` puts it.
+    let b = vec![EditOp::Ins { at: 2, text: "x".into() }];
+    assert_eq!(compose(&a, &b), vec![EditOp::Ins { at: 1, text: "x😀".into() }]);
+    assert_eq!(apply("ab", &compose(&a, &b)), apply(&apply("ab", &a), &b));
+
+    // A delete ending inside the pair keeps it whole; one starting inside
+    // takes it whole.
+    let b = vec![EditOp::Del { at: 0, len: 2 }];
+    assert_eq!(apply("ab", &compose(&a, &b)), "😀b");
+    let b = vec![EditOp::Del { at: 2, len: 1 }];
+    assert_eq!(apply("ab", &compose(&a, &b)), "ab");
+}
+
+#[test]
+fn invert_keeps_pairs_whole() {
+    let base = "a😀b";
+    let a = vec![EditOp::Del { at: 2, len: 1 }];
+    assert_eq!(apply(base, &a), "ab");
+    let undo = invert(&a, base).unwrap();
+    assert_eq!(undo, vec![EditOp::Ins { at: 1, text: "😀".into() }]);
+    assert_eq!(apply("ab", &undo), base);
+}
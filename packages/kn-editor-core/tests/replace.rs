@@ -0,0 +1,73 @@
+//! Replace: capture templates, case preservation, and replace-all as one
+//! change.
+
+mod common;
+
+use common::{seeded, text};
+use kn_editor_core::{CoreText, SearchOptions, TextRange};
+
+const ALPHABET: &[&str] = &["a", "b", "é", "😀", " ", "\n"];
+
+fn regex() -> SearchOptions {
+    SearchOptions { regex: true, case_sensitive: true, ..Default::default() }
+}
+
+/// A literal replace-all does what `str::replace` does, as one change
+/// record and one undo unit.
+#[test]
+fn literal_replace_all_agrees_with_str_replace() {
+    let literal = SearchOptions { case_sensitive: true, ..Default::default() };
+    seeded(200, |seed, rng| {
+        let s: String = (0..rng.below(60)).map(|_| ALPHABET[rng.below(ALPHABET.len())]).collect();
+        let (query, with) = (["ab", "😀", "a\n", "é"][rng.below(4)], ["", "$1", "😀😀", "x"][rng.below(4)]);
+        let mut doc = CoreText::from_string(&s);
+        doc.take_changes();
+        let count = doc.replace_all(query, with, &literal).unwrap();
+        assert_eq!(text(&doc), s.replace(query, with), "seed {seed}");
+        assert_eq!(count, s.matches(query).count(), "seed {seed}");
+        assert_eq!(doc.take_changes().len(), usize::from(count > 0), "seed {seed}");
+        doc.undo();
+        assert_eq!(text(&doc), s, "seed {seed}");
+    });
+}
+
+#[test]
+fn templates_expand_groups() {
+    let mut doc = CoreText::from_string("2024-05-01 and 1999-12-31");
+    let count = doc.replace_all(r"(\d+)-(?<m>\d+)-(\d+)", "$3/${m}/${1}", &regex()).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(text(&doc), "01/05/2024 and 31/12/1999");
+
+    let mut doc = CoreText::from_string("price: 5");
+    doc.replace_all(r"(\d)", "$$$1.00 $9$name${unclosed", &regex()).unwrap();
+    // Unknown groups expand to nothing; a `$` that starts no group stays.
+    assert_eq!(text(&doc), "price: $5.00 ${unclosed");
+
+    // Literal queries insert the replacement as typed.
+    let mut doc = CoreText::from_string("a.b");
+    doc.replace_all(".", "$0", &SearchOptions::default()).unwrap();
+    assert_eq!(text(&doc), "a$0b");
+}
+
+#[test]
+fn replace_steps_to_the_next_match() {
+    let mut doc = CoreText::from_string("😀 cat cat");
+    let opts = SearchOptions::default();
+    assert_eq!(doc.replace("cat", "lion", &opts, 0).unwrap(), Some(TextRange { start: 3, end: 7 }));
+    assert_eq!(doc.replace("cat", "lion", &opts, 7).unwrap(), Some(TextRange { start: 8, end: 12 }));
+    assert_eq!(doc.replace("cat", "lion", &opts, 0).unwrap(), None);
+    assert_eq!(text(&doc), "😀 lion lion");
+}
+
+#[test]
+fn preserve_case_follows_each_match() {
+    let mut doc = CoreText::from_string("foo Foo FOO fOo");
+    let opts = SearchOptions { preserve_case: true, ..Default::default() };
+    assert_eq!(doc.replace_all("foo", "bar", &opts).unwrap(), 4);
+    assert_eq!(text(&doc), "bar Bar BAR bar");
+
+    let mut doc = CoreText::from_string("Hello hello");
+    let opts = SearchOptions { regex: true, preserve_case: true, ..Default::default() };
+    doc.replace_all(r"h(ello)", "j${1}y", &opts).unwrap();
+    assert_eq!(text(&doc), "Jelloy jelloy");
+}
@@ -0,0 +1,213 @@
+//! Find: literal matches against `str::match_indices`, paging, cursors,
+//! the regex and whole-word options, and normalized matches mapped back to
+//! document offsets.
+
+mod common;
+
+use common::seeded;
+use kn_editor_core::{CoreText, SearchOptions, TextRange};
+
+const ALPHABET: &[&str] = &["a", "b", "é", "😀", " ", "\n"];
+
+/// None of these overlaps itself, so every occurrence is a match.
+const QUERIES: &[&str] = &["ab", "b a", "😀", "é", "a\nb"];
+
+fn utf16(s: &str, byte: usize) -> usize {
+    s[..byte].encode_utf16().count()
+}
+
+/// Every occurrence of `query`, in UTF-16.
+fn occurrences(s: &str, query: &str) -> Vec<TextRange> {
+    s.match_indices(query)
+        .map(|(at, m)| TextRange { start: utf16(s, at), end: utf16(s, at + m.len()) })
+        .collect()
+}
+
+fn literal() -> SearchOptions {
+    SearchOptions { case_sensitive: true, ..Default::default() }
+}
+
+#[test]
+fn literal_matches_agree_with_match_indices() {
+    seeded(200, |seed, rng| {
+        let s: String = (0..rng.below(60)).map(|_| ALPHABET[rng.below(ALPHABET.len())]).collect();
+        let doc = CoreText::from_string(&s);
+        let query = QUERIES[rng.below(QUERIES.len())];
+        let all = occurrences(&s, query);
+        let page = doc.find_all(query, &literal(), 0, usize::MAX).unwrap();
+        assert_eq!((&page.matches, page.next), (&all, None), "seed {seed}");
+
+        // Char boundaries only: a mid-pair offset stands for its pair.
+        let bounds: Vec<usize> = s.char_indices().map(|(b, _)| utf16(&s, b)).chain([doc.len_utf16()]).collect();
+        let from = bounds[rng.below(bounds.len())];
+        let forward = all.iter().copied().find(|m| m.start >= from);
+        let backward = all.iter().copied().rev().find(|m| m.end <= from);
+        assert_eq!(doc.find(query, &literal(), from, false).unwrap(), forward, "seed {seed} from {from}");
+        assert_eq!(doc.find(query, &literal(), from, true).unwrap(), backward, "seed {seed} from {from}");
+        let rest: Vec<TextRange> = all.iter().copied().filter(|m| m.start >= from).collect();
+        assert_eq!(doc.find_all(query, &literal(), from, usize::MAX).unwrap().matches, rest, "seed {seed} from {from}");
+    });
+}
+
+#[test]
+fn pages_add_up_to_all_matches() {
+    let doc = CoreText::from_string(&"needle hay ".repeat(50));
+    let all = doc.find_all("needle", &literal(), 0, usize::MAX).unwrap().matches;
+    assert_eq!(all.len(), 50);
+    for limit in [1, 7, 49, 50] {
+        let (mut paged, mut from, mut pages) = (Vec::new(), Some(0), 0);
+        while let Some(at) = from {
+            let page = doc.find_all("needle", &literal(), at, limit).unwrap();
+            assert!(page.matches.len() <= limit);
+            paged.extend(page.matches);
+            from = page.next;
+            pages += 1;
+        }
+        assert_eq!(paged, all, "limit {limit}");
+        assert_eq!(pages, 50usize.div_ceil(limit), "limit {limit}");
+    }
+}
+
+#[test]
+fn cursors_walk_the_text_they_were_made_on() {
+    let mut doc = CoreText::from_string("ab ab ab");
+    let mut cursor = doc.search_cursor("ab", &literal(), 2).unwrap();
+    doc.delete_utf16(0, 8).unwrap();
+    assert_eq!(cursor.next_match(), Some(TextRange { start: 3, end: 5 }));
+    assert_eq!(cursor.next_match(), Some(TextRange { start: 6, end: 8 }));
+    assert_eq!(cursor.next_match(), None);
+    assert_eq!(cursor.prev_match(), Some(TextRange { start: 3, end: 5 }));
+    assert_eq!(cursor.prev_match(), Some(TextRange { start: 0, end: 2 }));
+    assert_eq!(cursor.prev_match(), None);
+}
+
+#[test]
+fn regex_case_and_whole_word_options() {
+    let doc = CoreText::from_string("Cat cat\ncatalog CAT");
+    let starts = |query: &str, opts: SearchOptions| -> Vec<usize> {
+        doc.find_all(query, &opts, 0, usize::MAX).unwrap().matches.iter().map(|m| m.start).collect()
+    };
+    assert_eq!(starts("cat", SearchOptions::default()), [0, 4, 8, 16]);
+    assert_eq!(starts("cat", literal()), [4, 8]);
+    assert_eq!(starts("cat", SearchOptions { whole_word: true, ..Default::default() }), [0, 4, 16]);
+    assert_eq!(starts("^cat", SearchOptions { regex: true, ..Default::default() }), [0, 8]);
+    assert_eq!(starts("t$", SearchOptions { regex: true, ..Default::default() }), [6, 18]);
+    assert!(doc.find_all("(", &SearchOptions { regex: true, ..Default::default() }, 0, 1).is_err());
+
+    // An empty match is reported once and the scan moves on a char.
+    let doc = CoreText::from_string("😀x");
+    assert_eq!(
+        doc.find_all("x*", &SearchOptions { regex: true, ..Default::default() }, 0, usize::MAX).unwrap().matches,
+        [TextRange { start: 0, end: 0 }, TextRange { start: 2, end: 3 }, TextRange { start: 3, end: 3 }]
+    );
+}
+
+/// Backward steps land on the forward matches, also for queries that
+/// overlap themselves, where scanning from elsewhere finds other ones.
+#[test]
+fn backward_matches_are_forward_matches() {
+    let regex = SearchOptions { regex: true, ..Default::default() };
+    let queries = [("aa", literal()), ("aba", literal()), ("a😀a", literal()), ("a+b?", regex), ("b*", regex), ("(ab)*a", regex)];
+    seeded(200, |seed, rng| {
+        let s: String = (0..rng.below(30)).map(|_| *rng.pick(&["a", "a", "b", "😀"])).collect();
+        let doc = CoreText::from_string(&s);
+        let (query, opts) = rng.pick(&queries);
+        let all = doc.find_all(query, opts, 0, usize::MAX).unwrap().matches;
+        let bounds: Vec<usize> = s.char_indices().map(|(b, _)| utf16(&s, b)).chain([doc.len_utf16()]).collect();
+        for &from in &bounds {
+            let before: Vec<TextRange> = all.iter().copied().filter(|m| m.end <= from).collect();
+            let context = format!("seed {seed} {query:?} in {s:?} from {from}");
+            assert_eq!(doc.find(query, opts, from, true).unwrap(), before.last().copied(), "{context}");
+            // A cursor walks back through the same matches, one by one.
+            let mut cursor = doc.search_cursor(query, opts, from).unwrap();
+            let walked: Vec<TextRange> = std::iter::from_fn(|| cursor.prev_match()).collect();
+            assert_eq!(walked, before.into_iter().rev().collect::<Vec<_>>(), "{context}");
+        }
+    });
+    let doc = CoreText::from_string("aaa");
+    assert_eq!(doc.find("aa", &literal(), 3, true).unwrap(), Some(TextRange { start: 0, end: 2 }));
+
+    // Far enough into the text that a scan from near `from` would see
+    // other matches than one from the start.
+    let doc = CoreText::from_string(&format!("b{}b", "a".repeat(3001)));
+    let all = doc.find_all("aa", &literal(), 0, usize::MAX).unwrap().matches;
+    for from in 0..=doc.len_utf16() {
+        let before = all.iter().copied().rev().find(|m| m.end <= from);
+        assert_eq!(doc.find("aa", &literal(), from, true).unwrap(), before, "from {from}");
+    }
+}
+
+const ACCENTED: &[&str] = &["e", "é", "e\u{301}", "E", "É", "E\u{301}", "r", "s", "😀", " "];
+
+/// The folded text as `(char, start, end)` of the original char it came
+/// from, for texts whose chars fold to at most one char each. Dropped marks
+/// widen the char before them.
+fn folded(s: &str) -> Vec<(char, usize, usize)> {
+    use unicode_normalization::UnicodeNormalization;
+    let mut out: Vec<(char, usize, usize)> = Vec::new();
+    let mut at = 0;
+    for c in s.chars() {
+        let end = at + c.len_utf16();
+        let kept: Vec<char> = c.to_lowercase().collect::<String>().nfd().filter(|&d| d != '\u{301}').collect();
+        match kept[..] {
+            [] => out.last_mut().unwrap().2 = end,
+            [k] => out.push((k, at, end)),
+            _ => unreachable!("{c:?} folds to more than one char"),
+        }
+        at = end;
+    }
+    out
+}
+
+#[test]
+fn normalized_matches_map_back_to_whole_chars() {
+    let normalize = SearchOptions { normalize: true, ..Default::default() };
+    seeded(200, |seed, rng| {
+        let s: String = (0..rng.below(40)).map(|_| ACCENTED[rng.below(ACCENTED.len())]).collect();
+        let doc = CoreText::from_string(&s);
+        let query = ["e", "RÉ", "e\u{301} s", "😀e", "es"][rng.below(5)];
+
+        let hay = folded(&s);
+        let needle: Vec<char> = folded(query).into_iter().map(|f| f.0).collect();
+        let mut expected = Vec::new();
+        let mut i = 0;
+        while i + needle.len() <= hay.len() {
+            if hay[i..i + needle.len()].iter().map(|f| f.0).eq(needle.iter().copied()) {
+                expected.push(TextRange { start: hay[i].1, end: hay[i + needle.len() - 1].2 });
+                i += needle.len();
+            } else {
+                i += 1;
+            }
+        }
+        let found = doc.find_all(query, &normalize, 0, usize::MAX).unwrap().matches;
+        assert_eq!(found, expected, "seed {seed} {query:?} in {s:?}");
+    });
+}
+
+#[test]
+fn normalized_matches_that_change_length() {
+    let normalize = SearchOptions { normalize: true, ..Default::default() };
+    let doc = CoreText::from_string("Straße ﬁne STRASSE");
+    let found = doc.find_all("strasse", &normalize, 0, usize::MAX).unwrap().matches;
+    assert_eq!(found, [TextRange { start: 0, end: 6 }, TextRange { start: 11, end: 18 }]);
+    // Half of a char's folding takes the whole char.
+    assert_eq!(doc.find("f", &normalize, 0, false).unwrap(), Some(TextRange { start: 7, end: 8 }));
+    assert_eq!(doc.find("ss", &normalize, 0, false).unwrap(), Some(TextRange { start: 4, end: 5 }));
+
+    // Backward from inside a folded char, and a replace that keeps the
+    // rest of the text where it was.
+    assert_eq!(doc.find("fine", &normalize, 11, true).unwrap(), Some(TextRange { start: 7, end: 10 }));
+    let mut doc = CoreText::from_string("Résumé: re\u{301}sume\u{301}.");
+    assert_eq!(doc.replace_all("resume", "CV", &normalize).unwrap(), 2);
+    assert_eq!(doc.slice(0, doc.len_chars()).unwrap(), "CV: CV.");
+}
+
+#[test]
+fn the_token_index_folds_like_find() {
+    let mut doc = CoreText::from_string("Résumé re\u{301}sume\u{301} RESUME");
+    doc.set_token_normalization(true);
+    let normalize = SearchOptions { normalize: true, whole_word: true, ..Default::default() };
+    let starts: Vec<usize> = doc.find_all("resume", &normalize, 0, usize::MAX).unwrap().matches.iter().map(|m| m.start).collect();
+    assert_eq!(doc.token_positions("résumé")[0].positions, starts);
+    assert_eq!(starts, [0, 7, 16]);
+}
@@ -0,0 +1,81 @@
+//! Editing at several selections at once, and how it lands in the undo
+//! history.
+
+mod common;
+
+use common::text;
+use kn_editor_core::{CoreText, Selection};
+
+fn undo_all(doc: &mut CoreText) -> usize {
+    let mut steps = 0;
+    while doc.undo().is_some() {
+        steps += 1;
+    }
+    steps
+}
+
+#[test]
+fn typing_at_one_caret_coalesces_like_insert_utf16() {
+    let mut doc = CoreText::from_string("");
+    doc.set_cursor(0).unwrap();
+    for c in ["h", "e", "l", "l", "o"] {
+        doc.insert_text(c);
+    }
+    assert_eq!(text(&doc), "hello");
+    assert_eq!(undo_all(&mut doc), 1);
+    assert_eq!(text(&doc), "");
+
+    let mut doc = CoreText::from_string("");
+    for (i, c) in ["h", "e", "l", "l", "o"].iter().enumerate() {
+        doc.insert_utf16(i, c).unwrap();
+    }
+    assert_eq!(undo_all(&mut doc), 1);
+}
+
+#[test]
+fn backspacing_at_one_caret_coalesces() {
+    let mut doc = CoreText::from_string("hello");
+    doc.set_cursor(5).unwrap();
+    for _ in 0..3 {
+        doc.delete_backward();
+    }
+    assert_eq!(text(&doc), "he");
+    assert_eq!(undo_all(&mut doc), 1);
+    assert_eq!(text(&doc), "hello");
+}
+
+#[test]
+fn an_edit_at_several_carets_is_one_change_and_one_undo_unit() {
+    let mut doc = CoreText::from_string("a\nb\nc");
+    doc.set_selections(&[Selection { anchor: 1, head: 1 }, Selection { anchor: 3, head: 3 }, Selection { anchor: 5, head: 5 }])
+        .unwrap();
+    doc.take_changes();
+    doc.insert_text(";");
+    assert_eq!(text(&doc), "a;\nb;\nc;");
+    assert_eq!(doc.selections(), [Selection { anchor: 2, head: 2 }, Selection { anchor: 5, head: 5 }, Selection { anchor: 8, head: 8 }]);
+    assert_eq!(doc.take_changes().len(), 1);
+
+    doc.delete_backward();
+    assert_eq!(text(&doc), "a\nb\nc");
+    assert_eq!(undo_all(&mut doc), 2);
+    assert_eq!(text(&doc), "a\nb\nc");
+}
+
+#[test]
+fn paste_spreads_lines_over_selections() {
+    let mut doc = CoreText::from_string("x y");
+    doc.set_selections(&[Selection { anchor: 0, head: 1 }, Selection { anchor: 2, head: 3 }]).unwrap();
+    doc.paste("1\n2");
+    assert_eq!(text(&doc), "1 2");
+    doc.set_cursor(0).unwrap();
+    doc.paste("1\n2");
+    assert_eq!(text(&doc), "1\n21 2");
+}
+
+#[test]
+fn delete_forward_takes_whole_grapheme_clusters() {
+    let mut doc = CoreText::from_string("e\u{301}x");
+    doc.set_cursor(0).unwrap();
+    doc.delete_forward();
+    assert_eq!(text(&doc), "x");
+}
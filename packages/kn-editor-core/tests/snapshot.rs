@@ -0,0 +1,53 @@
+//! Snapshots: frozen at their version however the document moves on.
+
+mod common;
+
+use common::{seeded, text};
+use kn_editor_core::{CoreError, CoreText, DiffOptions, EditOp, Snapshot};
+
+const PIECES: &[&str] = &["a", "bc", "é", "😀", "\n", "long piece of text "];
+
+#[test]
+fn snapshots_keep_their_text_and_version() {
+    seeded(100, |seed, rng| {
+        let mut doc = CoreText::from_string("start\n");
+        let mut taken: Vec<(Snapshot, String, f64)> = Vec::new();
+        for _ in 0..80 {
+            let len = doc.len_utf16();
+            let op = if len > 0 && rng.below(3) == 0 {
+                let at = rng.below(len);
+                EditOp::Del { at, len: 1 + rng.below((len - at).min(8)) }
+            } else {
+                EditOp::Ins { at: rng.below(len + 1), text: PIECES[rng.below(PIECES.len())].into() }
+            };
+            doc.apply_ops(&[op]).unwrap();
+            if rng.below(4) == 0 {
+                taken.push((doc.snapshot(), text(&doc), doc.version()));
+            }
+        }
+        for (snapshot, t, version) in &taken {
+            assert_eq!(&snapshot.text(), t, "seed {seed}");
+            assert_eq!(snapshot.version(), *version, "seed {seed}");
+            assert_eq!(snapshot.len_utf16(), t.encode_utf16().count(), "seed {seed}");
+            assert_eq!(snapshot.len_lines(), t.matches('\n').count() + 1, "seed {seed}");
+        }
+        // Any two snapshots diff into ops that replay one into the other.
+        for pair in taken.windows(2) {
+            let ops = pair[0].0.diff_to(&pair[1].0, &DiffOptions::default());
+            let mut replay = CoreText::from_string(&pair[0].1);
+            replay.apply_ops(&ops).unwrap();
+            assert_eq!(text(&replay), pair[1].1, "seed {seed}");
+        }
+    });
+}
+
+#[test]
+fn reads_are_bounds_checked_in_utf16() {
+    let snapshot = CoreText::from_string("a😀b😀").snapshot();
+    assert_eq!(snapshot.slice_utf16(1, 3).unwrap(), "😀");
+    assert_eq!(snapshot.slice(1, 3).unwrap(), "😀b");
+    assert_eq!(snapshot.index_of("😀", 3).unwrap(), Some(4));
+    assert_eq!(snapshot.index_of("c", 0).unwrap(), None);
+    assert!(matches!(snapshot.slice_utf16(2, 9), Err(CoreError::OutOfBounds { index: 9, len: 6, .. })));
+    assert!(matches!(snapshot.slice(2, 1), Err(CoreError::InvertedRange { start: 2, end: 1 })));
+}
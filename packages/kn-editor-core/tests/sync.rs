@@ -0,0 +1,196 @@
+//! `SyncClient` against a simulated relay, and how remote batches meet the
+//! local undo history.
+
+mod common;
+
+use std::collections::VecDeque;
+
+use common::{seeded, text, Rng};
+use kn_editor_core::{transform, Bias, CoreText, EditOp, SyncClient};
+
+const ALPHABET: &[char] = &['a', 'b', 'é', ' ', '\n', '中'];
+
+fn edits(rng: &mut Rng, mut len: usize) -> Vec<EditOp> {
+    let mut ops = Vec::new();
+    for _ in 0..1 + rng.below(3) {
+        if len > 0 && rng.below(2) == 0 {
+            let at = rng.below(len);
+            let n = 1 + rng.below((len - at).min(3));
+            ops.push(EditOp::Del { at, len: n });
+            len -= n;
+        } else {
+            let at = rng.below(len + 1);
+            let text: String = (0..1 + rng.below(3)).map(|_| ALPHABET[rng.below(ALPHABET.len())]).collect();
+            len += text.encode_utf16().count();
+            ops.push(EditOp::Ins { at, text });
+        }
+    }
+    ops
+}
+
+enum Message {
+    Ack,
+    Ops(Vec<EditOp>),
+}
+
+/// The relay: rebases a batch over everything accepted since its revision.
+struct Server {
+    doc: CoreText,
+    history: Vec<Vec<EditOp>>,
+}
+
+impl Server {
+    fn receive(&mut self, from: usize, revision: u64, mut ops: Vec<EditOp>, inboxes: &mut [VecDeque<Message>]) {
+        for accepted in &self.history[revision as usize..] {
+            ops = transform(&ops, accepted).0;
+        }
+        self.doc.apply_ops(&ops).unwrap();
+        self.history.push(ops.clone());
+        for (client, inbox) in inboxes.iter_mut().enumerate() {
+            inbox.push_back(if client == from { Message::Ack } else { Message::Ops(ops.clone()) });
+        }
+    }
+}
+
+fn deliver(client: &mut SyncClient, doc: &mut CoreText, message: Message) {
+    match message {
+        Message::Ack => assert!(client.ack()),
+        Message::Ops(ops) => {
+            client.apply_remote(doc, &ops).unwrap();
+        }
+    }
+}
+
+#[test]
+fn clients_converge_with_the_server() {
+    seeded(200, |seed, rng| {
+        let n = 3;
+        let base = "hello world";
+        let mut server = Server { doc: CoreText::from_string(base), history: Vec::new() };
+        let mut docs: Vec<CoreText> = (0..n).map(|_| CoreText::from_string(base)).collect();
+        let mut clients: Vec<SyncClient> = (0..n).map(|_| SyncClient::new(0)).collect();
+        let mut inboxes: Vec<VecDeque<Message>> = (0..n).map(|_| VecDeque::new()).collect();
+        let mut uplinks: Vec<VecDeque<(u64, Vec<EditOp>)>> = (0..n).map(|_| VecDeque::new()).collect();
+
+        for _ in 0..120 {
+            let c = rng.below(n);
+            match rng.below(6) {
+                0 | 1 => {
+                    let ops = edits(rng, docs[c].len_utf16());
+                    clients[c].apply_local(&mut docs[c], &ops).unwrap();
+                }
+                5 => {
+                    // Undo is a local edit too, rebased past remote ones.
+                    docs[c].take_changes();
+                    docs[c].undo();
+                    for record in docs[c].take_changes() {
+                        clients[c].local(&record.ops);
+                    }
+                }
+                2 => {
+                    if let Some(out) = clients[c].send() {
+                        uplinks[c].push_back((out.revision, out.ops));
+                    }
+                }
+                3 => {
+                    if let Some((revision, ops)) = uplinks[c].pop_front() {
+                        server.receive(c, revision, ops, &mut inboxes);
+                    }
+                }
+                _ => {
+                    if let Some(message) = inboxes[c].pop_front() {
+                        deliver(&mut clients[c], &mut docs[c], message);
+                    }
+                }
+            }
+        }
+
+        loop {
+            let mut progress = false;
+            for c in 0..n {
+                if let Some(out) = clients[c].send() {
+                    uplinks[c].push_back((out.revision, out.ops));
+                }
+                while let Some((revision, ops)) = uplinks[c].pop_front() {
+                    server.receive(c, revision, ops, &mut inboxes);
+                    progress = true;
+                }
+            }
+            for c in 0..n {
+                while let Some(message) = inboxes[c].pop_front() {
+                    deliver(&mut clients[c], &mut docs[c], message);
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        for c in 0..n {
+            assert!(clients[c].is_synced(), "seed {seed}");
+            assert_eq!(clients[c].revision() as usize, server.history.len(), "seed {seed}");
+            assert_eq!(text(&docs[c]), text(&server.doc), "seed {seed} client {c}");
+        }
+    });
+}
+
+#[test]
+fn offline_edits_keep_their_caret() {
+    let mut doc = CoreText::from_string("abc");
+    let mut client = SyncClient::new(0);
+    let caret = doc.create_anchor(3, Bias::Right).unwrap();
+    client.apply_local(&mut doc, &[EditOp::Ins { at: 3, text: "X".into() }]).unwrap();
+    doc.set_anchor(caret, 4).unwrap();
+
+    let applied = client.apply_remote(&mut doc, &[EditOp::Ins { at: 0, text: "__".into() }]).unwrap();
+    assert_eq!(applied, vec![EditOp::Ins { at: 0, text: "__".into() }]);
+    assert_eq!(text(&doc), "__abcX");
+    assert_eq!(doc.anchor(caret).unwrap().offset, 6);
+    assert_eq!(client.send().unwrap().ops, vec![EditOp::Ins { at: 5, text: "X".into() }]);
+}
+
+#[test]
+fn remote_ops_are_not_undone_and_end_the_typing_run() {
+    let mut doc = CoreText::from_string("");
+    let mut client = SyncClient::new(0);
+    client.apply_local(&mut doc, &[EditOp::Ins { at: 0, text: "ab".into() }]).unwrap();
+    client.send();
+    client.ack();
+    client.apply_remote(&mut doc, &[EditOp::Ins { at: 0, text: ">> ".into() }]).unwrap();
+    client.apply_local(&mut doc, &[EditOp::Ins { at: 5, text: "c".into() }]).unwrap();
+    assert_eq!(text(&doc), ">> abc");
+
+    // "c" is its own unit, and undoing "ab" leaves the remote text alone.
+    assert!(doc.undo().is_some());
+    assert_eq!(text(&doc), ">> ab");
+    assert!(doc.undo().is_some());
+    assert_eq!(text(&doc), ">> ");
+    assert!(doc.undo().is_none());
+}
+
+#[test]
+fn remote_ops_overlapping_local_changes_drop_them_from_history() {
+    let mut doc = CoreText::from_string("");
+    let mut client = SyncClient::new(0);
+    client.apply_local(&mut doc, &[EditOp::Ins { at: 0, text: "one".into() }]).unwrap();
+    doc.seal_undo();
+    client.apply_local(&mut doc, &[EditOp::Ins { at: 3, text: " two".into() }]).unwrap();
+    client.send();
+    client.ack();
+    // The server deleted part of "one", which can no longer be undone.
+    client.apply_remote(&mut doc, &[EditOp::Del { at: 1, len: 1 }]).unwrap();
+    assert_eq!(text(&doc), "oe two");
+    assert!(doc.undo().is_some());
+    assert_eq!(text(&doc), "oe");
+    assert!(doc.undo().is_none());
+}
+
+#[test]
+fn remote_ops_are_checked_even_in_clamp_mode() {
+    let mut doc = CoreText::from_string("abc");
+    doc.set_clamp_mode(true);
+    let mut client = SyncClient::new(0);
+    assert!(client.apply_remote(&mut doc, &[EditOp::Del { at: 2, len: 5 }]).is_err());
+    assert_eq!(text(&doc), "abc");
+    assert_eq!(client.revision(), 0);
+}
@@ -0,0 +1,84 @@
+//! The incremental token index: the deltas it hands out add up to the
+//! index of a document parsed from scratch, through edits and undo.
+
+mod common;
+
+use std::collections::{BTreeMap, HashMap};
+
+use common::seeded;
+use kn_editor_core::{CoreText, EditOp, TokenEntry};
+
+const PIECES: &[&str] = &["alpha ", "Beta", "x-y_z ", "😀", "é", "\n\n", "\n", " ", "42", "-"];
+
+/// `(token, block start) → positions`, so two documents compare whatever
+/// ids their blocks have.
+type Index = BTreeMap<(String, usize), Vec<usize>>;
+
+fn by_start(doc: &CoreText, postings: &HashMap<(String, String), Vec<usize>>) -> Index {
+    let starts: HashMap<String, usize> = doc.editor_blocks().into_iter().map(|b| (b.id, b.start)).collect();
+    postings.iter().map(|((token, id), positions)| ((token.clone(), starts[id]), positions.clone())).collect()
+}
+
+/// Fold a delta into `postings`; empty positions drop the posting.
+fn take(doc: &mut CoreText, postings: &mut HashMap<(String, String), Vec<usize>>) {
+    for p in doc.take_token_delta() {
+        match p.positions.is_empty() {
+            true => assert!(postings.remove(&(p.token, p.block_id)).is_some()),
+            false => {
+                postings.insert((p.token, p.block_id), p.positions);
+            }
+        }
+    }
+}
+
+#[test]
+fn deltas_add_up_to_a_fresh_index() {
+    seeded(100, |seed, rng| {
+        let mut doc = CoreText::from_string("first block\n\nsecond block");
+        let mut postings = HashMap::new();
+        for step in 0..40 {
+            let len = doc.len_utf16();
+            match rng.below(5) {
+                0 => {
+                    doc.undo();
+                }
+                1 if len > 0 => {
+                    let at = rng.below(len);
+                    doc.apply_ops(&[EditOp::Del { at, len: 1 + rng.below((len - at).min(5)) }]).unwrap();
+                }
+                _ => {
+                    let text = PIECES[rng.below(PIECES.len())];
+                    doc.apply_ops(&[EditOp::Ins { at: rng.below(len + 1), text: text.into() }]).unwrap();
+                }
+            }
+            if rng.below(3) == 0 {
+                take(&mut doc, &mut postings);
+                let mut fresh = CoreText::from_string(&doc.slice(0, doc.len_chars()).unwrap());
+                let mut all = HashMap::new();
+                take(&mut fresh, &mut all);
+                assert_eq!(by_start(&doc, &postings), by_start(&fresh, &all), "seed {seed} step {step}");
+            }
+        }
+    });
+}
+
+#[test]
+fn positions_are_block_relative_utf16() {
+    let mut doc = CoreText::from_string("😀 Word\n\nword-play word");
+    let ids: Vec<String> = doc.editor_blocks().into_iter().map(|b| b.id).collect();
+    assert_eq!(
+        doc.token_positions("WORD"),
+        [
+            TokenEntry { block_id: ids[0].clone(), positions: vec![3] },
+            TokenEntry { block_id: ids[1].clone(), positions: vec![10] },
+        ]
+    );
+    assert_eq!(doc.token_positions("word-play")[0].positions, [0]);
+
+    // A block that only moved keeps its postings and reports nothing.
+    doc.take_token_delta();
+    doc.insert_utf16(0, "new ").unwrap();
+    let delta = doc.take_token_delta();
+    assert!(delta.iter().all(|p| p.block_id == ids[0]), "{delta:?}");
+    assert!(delta.iter().any(|p| p.token == "new"));
+}
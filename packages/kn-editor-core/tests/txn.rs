@@ -0,0 +1,118 @@
+//! Transactions and change records: rollback restores everything, and the
+//! records replay each version from the one before.
+
+mod common;
+
+use common::{edit, seeded, text};
+use kn_editor_core::{Bias, CoreError, CoreText, EditOp, Selection, TextRange};
+
+const PIECES: &[&str] = &["a", "b", "é", "😀", "\n\n", "xy"];
+
+/// Random edits in nested transactions that commit or roll back: every
+/// record replays the previous version into its own, and versions count up
+/// by one per record.
+#[test]
+fn records_replay_version_to_version() {
+    seeded(200, |seed, rng| {
+        let mut doc = CoreText::from_string("hello\n\nworld");
+        let mut replica = CoreText::from_string("hello\n\nworld");
+        // Text at the start of each open transaction.
+        let mut opened: Vec<String> = Vec::new();
+        for _ in 0..60 {
+            match rng.below(6) {
+                0 if opened.len() < 3 => {
+                    opened.push(text(&doc));
+                    doc.begin();
+                }
+                1 if !opened.is_empty() => {
+                    opened.pop();
+                    doc.commit().unwrap();
+                }
+                2 if !opened.is_empty() => {
+                    doc.rollback().unwrap();
+                    assert_eq!(text(&doc), opened.pop().unwrap(), "seed {seed}");
+                }
+                3 => {
+                    doc.undo();
+                }
+                _ => edit(rng, &mut doc, PIECES),
+            }
+            for record in doc.take_changes() {
+                assert_eq!(record.version, replica.version() as u64 + 1, "seed {seed}");
+                replica.apply_ops(&record.ops).unwrap();
+                replica.take_changes();
+                assert!(record.ranges.windows(2).all(|w| w[0].end < w[1].start), "seed {seed} {:?}", record.ranges);
+            }
+            if opened.is_empty() {
+                assert_eq!(text(&replica), text(&doc), "seed {seed}");
+                assert_eq!(replica.version(), doc.version(), "seed {seed}");
+            }
+        }
+    });
+}
+
+#[test]
+fn a_transaction_is_one_record_and_one_undo_unit() {
+    let mut doc = CoreText::from_string("abc");
+    doc.begin();
+    doc.insert_utf16(0, "1").unwrap();
+    doc.delete_utf16(2, 1).unwrap();
+    doc.insert_utf16(3, "\n").unwrap();
+    assert!(doc.take_changes().is_empty());
+    let record = doc.commit().unwrap().unwrap();
+    assert_eq!(record.version, 1);
+    assert_eq!(record.ranges, [TextRange { start: 0, end: 1 }, TextRange { start: 2, end: 2 }, TextRange { start: 3, end: 4 }]);
+    assert_eq!(text(&doc), "1ac\n");
+    assert_eq!(doc.take_changes(), [record]);
+
+    doc.undo();
+    assert_eq!(text(&doc), "abc");
+    assert!(!doc.can_undo());
+}
+
+#[test]
+fn rollback_restores_anchors_selections_and_history() {
+    let mut doc = CoreText::from_string("one two");
+    let anchor = doc.create_anchor(4, Bias::Left).unwrap();
+    doc.set_selections(&[Selection { anchor: 4, head: 7 }]).unwrap();
+    doc.insert_utf16(7, "!").unwrap();
+    doc.undo();
+    assert!(doc.can_redo());
+
+    doc.begin();
+    doc.delete_utf16(0, 4).unwrap();
+    let inner = doc.create_anchor(0, Bias::Right).unwrap();
+    doc.rollback().unwrap();
+
+    assert_eq!(text(&doc), "one two");
+    assert_eq!(doc.anchor(anchor).unwrap().offset, 4);
+    assert!(doc.anchor(inner).is_none());
+    assert_eq!(doc.selections(), [Selection { anchor: 4, head: 7 }]);
+    // A transaction that changed nothing gives the redo stack back.
+    assert!(doc.can_redo());
+    assert_ne!(doc.create_anchor(0, Bias::Right).unwrap(), inner);
+}
+
+#[test]
+fn an_inner_rollback_keeps_the_outer_transaction() {
+    let mut doc = CoreText::from_string("");
+    doc.begin();
+    doc.insert_utf16(0, "outer").unwrap();
+    doc.begin();
+    doc.insert_utf16(5, " inner").unwrap();
+    doc.rollback().unwrap();
+    assert!(doc.in_transaction());
+    let record = doc.commit().unwrap().unwrap();
+    assert_eq!(record.ops, [EditOp::Ins { at: 0, text: "outer".into() }]);
+    assert_eq!(text(&doc), "outer");
+}
+
+#[test]
+fn commit_and_rollback_need_a_transaction() {
+    let mut doc = CoreText::new();
+    assert!(matches!(doc.commit(), Err(CoreError::NoTransaction)));
+    assert!(matches!(doc.rollback(), Err(CoreError::NoTransaction)));
+    doc.begin();
+    assert_eq!(doc.commit().unwrap(), None);
+    assert_eq!(doc.version(), 0.0);
+}
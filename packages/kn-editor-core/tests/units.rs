@@ -0,0 +1,116 @@
+//! Index conversions between chars, bytes, UTF-16 code units and lines,
+//! checked against `str` itself. Run on both backends: `cargo test` and
+//! `cargo test --no-default-features`.
+
+mod common;
+
+use common::{seeded, text};
+use kn_editor_core::{CoreText, EditOp};
+
+// Astral characters (two UTF-16 units, four bytes) next to BMP ones.
+const PIECES: &[&str] = &["a", "é", "中", "😀", "𝄞", "\n", "👩‍👩‍👧", "z\n"];
+
+/// Char index → (byte, UTF-16, line) for every char, plus the end.
+fn expected(s: &str) -> Vec<(usize, usize, usize)> {
+    let mut out = vec![(0, 0, 0)];
+    for c in s.chars() {
+        let (byte, utf16, line) = *out.last().unwrap();
+        out.push((byte + c.len_utf8(), utf16 + c.len_utf16(), line + (c == '\n') as usize));
+    }
+    out
+}
+
+fn check_against_str(doc: &CoreText, context: &str) {
+    let s = text(doc);
+    let table = expected(&s);
+    assert_eq!(doc.len_chars(), table.len() - 1, "{context}");
+    assert_eq!(doc.len_bytes(), s.len(), "{context}");
+    assert_eq!(doc.len_utf16(), s.encode_utf16().count(), "{context}");
+    assert_eq!(doc.len_lines(), s.split('\n').count(), "{context}");
+    for (ch, &(byte, utf16, line)) in table.iter().enumerate() {
+        assert_eq!(doc.char_to_byte(ch).unwrap(), byte, "{context}: char {ch}");
+        assert_eq!(doc.char_to_utf16(ch).unwrap(), utf16, "{context}: char {ch}");
+        assert_eq!(doc.char_to_line(ch).unwrap(), line, "{context}: char {ch}");
+        assert_eq!(doc.byte_to_char(byte).unwrap(), ch, "{context}: byte {byte}");
+        assert_eq!(doc.utf16_to_char(utf16).unwrap(), ch, "{context}: utf16 {utf16}");
+        assert_eq!(doc.line_of_offset(utf16).unwrap(), line, "{context}: utf16 {utf16}");
+    }
+    // Offsets inside a char map to the char they are part of.
+    for (ch, w) in table.windows(2).enumerate() {
+        for byte in w[0].0 + 1..w[1].0 {
+            assert_eq!(doc.byte_to_char(byte).unwrap(), ch, "{context}: byte {byte}");
+        }
+        for utf16 in w[0].1 + 1..w[1].1 {
+            assert_eq!(doc.utf16_to_char(utf16).unwrap(), ch, "{context}: utf16 {utf16}");
+        }
+    }
+    for line in 0..doc.len_lines() {
+        let ch = table.iter().position(|&(_, _, l)| l == line).unwrap();
+        assert_eq!(doc.line_to_char(line).unwrap(), ch, "{context}: line {line}");
+    }
+    assert_eq!(doc.line_to_char(doc.len_lines()).unwrap(), doc.len_chars(), "{context}");
+}
+
+#[test]
+fn astral_text_converts_both_ways() {
+    let doc = CoreText::from_string("a😀b\n𝄞中");
+    assert_eq!((doc.len_chars(), doc.len_bytes(), doc.len_utf16()), (6, 14, 8));
+    assert_eq!(doc.char_to_utf16(2).unwrap(), 3);
+    assert_eq!(doc.char_to_byte(2).unwrap(), 5);
+    assert_eq!(doc.utf16_to_char(3).unwrap(), 2);
+    assert_eq!(doc.char_to_line(4).unwrap(), 1);
+    assert_eq!(doc.slice_utf16(1, 3).unwrap(), "😀");
+    check_against_str(&doc, "fixed");
+}
+
+#[test]
+fn lone_surrogate_offsets_map_to_their_pair() {
+    let mut doc = CoreText::from_string("a😀b");
+    // 2 is the low half of 😀, between its two code units.
+    assert_eq!(doc.utf16_to_char(2).unwrap(), 1);
+    assert_eq!(doc.line_of_offset(2).unwrap(), 0);
+    // Both ends of an edit or slice move before the pair, never into it.
+    doc.insert_utf16(2, "X").unwrap();
+    assert_eq!(text(&doc), "aX😀b");
+    assert_eq!(doc.slice_utf16(2, 3).unwrap(), "");
+    assert_eq!(doc.slice_utf16(3, 5).unwrap(), "😀b");
+    doc.delete_utf16(2, 1).unwrap();
+    assert_eq!(text(&doc), "aX😀b");
+    doc.delete_utf16(3, 1).unwrap();
+    assert_eq!(text(&doc), "aXb");
+}
+
+/// Random edits through the UTF-16 methods, with every conversion checked
+/// against the text after each one.
+#[test]
+fn conversions_round_trip_through_edits() {
+    seeded(60, |seed, rng| {
+        let mut doc = CoreText::from_string("😀\nstart");
+        let mut model = text(&doc);
+        for step in 0..40 {
+            let units: Vec<u16> = model.encode_utf16().collect();
+            // Whole chars only, so the model can splice `str`s directly.
+            let boundary = |at: usize| at == units.len() || !(0xDC00..0xE000).contains(&units[at]);
+            let starts: Vec<usize> = (0..=units.len()).filter(|&at| boundary(at)).collect();
+            let op = if units.is_empty() || rng.below(3) > 0 {
+                EditOp::Ins { at: *rng.pick(&starts), text: (*rng.pick(PIECES)).into() }
+            } else {
+                let from = rng.below(starts.len() - 1);
+                let to = from + 1 + rng.below((starts.len() - from - 1).min(3));
+                EditOp::Del { at: starts[from], len: starts[to] - starts[from] }
+            };
+            match &op {
+                EditOp::Ins { at, text } => doc.insert_utf16(*at, text).unwrap(),
+                EditOp::Del { at, len } => doc.delete_utf16(*at, *len).unwrap(),
+            }
+            let mut next = units.clone();
+            match &op {
+                EditOp::Ins { at, text } => drop(next.splice(at..at, text.encode_utf16())),
+                EditOp::Del { at, len } => drop(next.drain(*at..at + len)),
+            }
+            model = String::from_utf16(&next).unwrap();
+            assert_eq!(text(&doc), model, "seed {seed} step {step}: {op:?}");
+            check_against_str(&doc, &format!("seed {seed} step {step}"));
+        }
+    });
+}
@@ -0,0 +1,81 @@
+//! Word and sentence navigation: line-by-line segmentation agrees with
+//! segmenting the whole document, and the Ctrl+Arrow moves skip to words.
+
+mod common;
+
+use common::seeded;
+use kn_editor_core::{CoreText, TextRange};
+use unicode_segmentation::UnicodeSegmentation;
+
+const PIECES: &[&str] = &["word", "can't", "3.14", " ", "  ", ", ", ". ", "? ", "\n", "\n\n", "😀", "中文", "é", "Mr. ", "U.S."];
+
+/// `(start, end, holds a letter or digit)` of each segment, in UTF-16.
+fn segments<'a>(parts: impl Iterator<Item = &'a str>) -> Vec<(usize, usize, bool)> {
+    let mut at = 0;
+    parts
+        .map(|s| {
+            let start = at;
+            at += s.encode_utf16().count();
+            (start, at, s.chars().any(char::is_alphanumeric))
+        })
+        .collect()
+}
+
+#[test]
+fn boundaries_match_a_whole_document_segmentation() {
+    seeded(40, |seed, rng| {
+        let s: String = (0..40).map(|_| PIECES[rng.below(PIECES.len())]).collect();
+        let doc = CoreText::from_string(&s);
+        let len = doc.len_utf16();
+        let words = segments(s.split_word_bounds());
+        let sentences = segments(s.split_sentence_bounds());
+
+        for offset in 0..=len {
+            let context = format!("seed {seed} offset {offset} in {s:?}");
+            let next = |segs: &[(usize, usize, bool)]| segs.iter().find(|g| g.1 > offset).map_or(len, |g| g.1);
+            let prev = |segs: &[(usize, usize, bool)]| segs.iter().rev().find(|g| g.0 < offset).map_or(0, |g| g.0);
+            assert_eq!(doc.next_word_boundary(offset).unwrap(), next(&words), "{context}");
+            assert_eq!(doc.prev_word_boundary(offset).unwrap(), prev(&words), "{context}");
+            assert_eq!(doc.next_sentence_boundary(offset).unwrap(), next(&sentences), "{context}");
+            assert_eq!(doc.prev_sentence_boundary(offset).unwrap(), prev(&sentences), "{context}");
+
+            let end = words.iter().find(|g| g.2 && g.1 > offset).map_or(len, |g| g.1);
+            let start = words.iter().rev().find(|g| g.2 && g.0 < offset).map_or(0, |g| g.0);
+            assert_eq!(doc.next_word_end(offset).unwrap(), end, "{context}");
+            assert_eq!(doc.prev_word_start(offset).unwrap(), start, "{context}");
+        }
+    });
+}
+
+#[test]
+fn ctrl_arrows_cross_lines_and_punctuation() {
+    let doc = CoreText::from_string("one, two\n\n  three");
+    assert_eq!(doc.next_word_end(0).unwrap(), 3);
+    assert_eq!(doc.next_word_end(3).unwrap(), 8);
+    assert_eq!(doc.next_word_end(8).unwrap(), 17);
+    assert_eq!(doc.next_word_end(17).unwrap(), 17);
+    assert_eq!(doc.prev_word_start(17).unwrap(), 12);
+    assert_eq!(doc.prev_word_start(12).unwrap(), 5);
+    assert_eq!(doc.prev_word_start(5).unwrap(), 0);
+}
+
+#[test]
+fn double_click_prefers_the_word() {
+    let doc = CoreText::from_string("hi, there\nx");
+    assert_eq!(doc.word_at(1).unwrap(), TextRange { start: 0, end: 2 });
+    // On a boundary the word to the right wins, then the word to the left.
+    assert_eq!(doc.word_at(4).unwrap(), TextRange { start: 4, end: 9 });
+    assert_eq!(doc.word_at(2).unwrap(), TextRange { start: 0, end: 2 });
+    assert_eq!(doc.word_at(3).unwrap(), TextRange { start: 3, end: 4 });
+    // A line break selects nothing.
+    assert_eq!(doc.word_at(9).unwrap(), TextRange { start: 4, end: 9 });
+    assert_eq!(CoreText::from_string("a\n\nb").word_at(2).unwrap(), TextRange { start: 2, end: 2 });
+}
+
+#[test]
+fn sentences_stop_before_the_line_break() {
+    let doc = CoreText::from_string("One. Two?\nThree");
+    assert_eq!(doc.sentence_at(1).unwrap(), TextRange { start: 0, end: 5 });
+    assert_eq!(doc.sentence_at(6).unwrap(), TextRange { start: 5, end: 9 });
+    assert_eq!(doc.sentence_at(12).unwrap(), TextRange { start: 10, end: 15 });
+}
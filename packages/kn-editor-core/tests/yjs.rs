@@ -0,0 +1,163 @@
+//! `YjsText` against Yjs's own v1 update bytes.
+//!
+//! `yjs/fixtures.txt` is what yjs 13 writes for the scenarios in
+//! `yjs/fixtures.mjs` (`Y.encodeStateAsUpdate`, `Y.encodeStateVector`, or
+//! the `update` event of one transaction); our updates have to be
+//! byte-equal to them, not just accepted back by us. The other direction is
+//! `rust_updates_decode_in_yjs`, which leaves its updates for
+//! `fixtures.mjs roundtrip` to load into Yjs.
+
+use std::fmt::Write;
+
+use kn_editor_core::{EditOp, YjsText};
+
+const FIXTURES: &str = include_str!("yjs/fixtures.txt");
+
+fn fixture(name: &str) -> Vec<u8> {
+    let hex = FIXTURES
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("no fixture {name}"));
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        write!(out, "{b:02x}").unwrap();
+        out
+    })
+}
+
+#[test]
+fn decodes_yjs_updates() {
+    let mut text = YjsText::new(9, "t");
+    assert_eq!(text.apply_update(&fixture("abc")).unwrap(), vec![EditOp::Ins { at: 0, text: "abc".into() }]);
+    assert_eq!(text.apply_update(&fixture("insert_x")).unwrap(), vec![EditOp::Ins { at: 1, text: "X".into() }]);
+    assert_eq!(text.apply_update(&fixture("delete_b")).unwrap(), vec![EditOp::Del { at: 2, len: 1 }]);
+    assert_eq!(text.text(), "aXc");
+
+    let mut text = YjsText::new(9, "t");
+    text.apply_update(&fixture("abc_without_b")).unwrap();
+    assert_eq!(text.text(), "ac");
+    assert_eq!(text.state_vector(), fixture("abc_without_b_sv"));
+}
+
+#[test]
+fn local_edits_encode_as_yjs_does() {
+    let mut text = YjsText::new(1, "t");
+    text.insert(0, "abc").unwrap();
+    assert_eq!(text.take_update(), fixture("abc"));
+    assert_eq!(text.encode_state_as_update(Some(vec![0x01, 0x01, 0x02])).unwrap(), fixture("abc_after_2"));
+    text.delete(1, 1).unwrap();
+    assert_eq!(text.take_update(), fixture("delete_b"));
+    assert_eq!(text.encode_state_as_update(None).unwrap(), fixture("abc_without_b"));
+}
+
+#[test]
+fn clients_are_written_highest_first() {
+    let mut text = YjsText::new(2, "t");
+    text.apply_update(&fixture("abc")).unwrap();
+    text.insert(1, "X").unwrap();
+    assert_eq!(text.take_update(), fixture("insert_x"));
+    assert_eq!(text.encode_state_as_update(None).unwrap(), fixture("axbc"));
+    assert_eq!(text.state_vector(), fixture("axbc_sv"));
+}
+
+#[test]
+fn deletes_of_other_types_stay_in_the_delete_set() {
+    let mut text = YjsText::new(9, "t");
+    text.apply_update(&fixture("map_entry")).unwrap();
+    assert_eq!(text.apply_update(&fixture("map_delete")).unwrap(), Vec::<EditOp>::new());
+    let state = text.encode_state_as_update(None).unwrap();
+    assert!(state.ends_with(&[0x01, 0x05, 0x01, 0x00, 0x01]), "{state:x?}");
+
+    // A peer syncing from us learns the entry is gone.
+    let mut peer = YjsText::new(10, "t");
+    peer.apply_update(&state).unwrap();
+    assert_eq!(peer.encode_state_as_update(None).unwrap(), state);
+}
+
+#[test]
+fn right_origin_in_another_type_does_not_panic() {
+    let mut text = YjsText::new(9, "t");
+    text.apply_update(&fixture("abc")).unwrap();
+    text.apply_update(&fixture("map_entry")).unwrap();
+    // Client 3: "Z" after "a" of the text, before the map entry. Built by
+    // hand, as no Yjs client would write it.
+    let update = [0x01, 0x01, 0x03, 0x00, 0xc4, 0x01, 0x00, 0x05, 0x00, 0x01, 0x5a, 0x00];
+    let ops = text.apply_update(&update).unwrap();
+    assert_eq!(text.pending_count(), 0);
+    assert_eq!(ops.len(), 1);
+    // As in Yjs, the foreign right origin bounds nothing.
+    assert_eq!(text.text(), "abcZ");
+}
+
+#[test]
+fn empty_update_is_a_no_op() {
+    let mut text = YjsText::new(1, "t");
+    text.apply_update(&fixture("abc")).unwrap();
+    assert_eq!(text.apply_update(&[]).unwrap(), Vec::<EditOp>::new());
+    assert_eq!(text.text(), "abc");
+}
+
+/// Replicas that exchanged every update, as `name field text sv update` lines.
+fn converged_lines(name: &str, replicas: &[YjsText]) -> String {
+    let text = replicas[0].text();
+    let mut out = String::new();
+    for replica in replicas {
+        assert_eq!(replica.text(), text, "{name}");
+        assert_eq!(replica.state_vector(), replicas[0].state_vector(), "{name}");
+        let update = replica.encode_state_as_update(None).unwrap();
+        writeln!(out, "{name} {} {} {} {}", replica.field(), hex(text.as_bytes()), hex(&replica.state_vector()), hex(&update)).unwrap();
+    }
+    out
+}
+
+/// Delivers every pending update of each replica to all the others.
+fn exchange(replicas: &mut [YjsText]) {
+    let updates: Vec<Vec<u8>> = replicas.iter_mut().map(|r| r.take_update()).collect();
+    for (i, replica) in replicas.iter_mut().enumerate() {
+        // Later senders first, so delivery order differs from send order.
+        for (j, update) in updates.iter().enumerate().rev() {
+            if i != j && !update.is_empty() {
+                replica.apply_update(update).unwrap();
+            }
+        }
+        assert_eq!(replica.pending_count(), 0);
+    }
+}
+
+#[test]
+fn rust_updates_decode_in_yjs() {
+    let mut out = String::from("# Written by tests/yjs.rs; checked by `fixtures.mjs roundtrip`.\n");
+
+    // Three clients, concurrent inserts and deletes that span each other's
+    // items, so the delete set has ranges for several clients.
+    let mut replicas = [YjsText::new(1, "t"), YjsText::new(2, "t"), YjsText::new(0xdead_beef, "t")];
+    replicas[0].insert(0, "hello world").unwrap();
+    exchange(&mut replicas);
+    replicas[0].insert(5, ",").unwrap();
+    replicas[1].delete(0, 6).unwrap();
+    replicas[1].insert(0, "Hi ").unwrap();
+    replicas[2].insert(11, "!").unwrap();
+    replicas[2].delete(4, 3).unwrap();
+    exchange(&mut replicas);
+    let len = replicas[1].len_utf16();
+    replicas[1].delete(1, len - 2).unwrap();
+    replicas[2].insert(0, "\u{1f642} ").unwrap();
+    exchange(&mut replicas);
+    assert_eq!(replicas[0].text(), "\u{1f642} H!");
+    out += &converged_lines("three_clients", &replicas);
+
+    // A field other than the default one, deleted down to nothing.
+    let mut replicas = [YjsText::new(7, "content"), YjsText::new(8, "content")];
+    replicas[0].insert(0, "caf\u{e9}").unwrap();
+    exchange(&mut replicas);
+    replicas[1].delete(0, 2).unwrap();
+    replicas[0].delete(2, 2).unwrap();
+    exchange(&mut replicas);
+    assert_eq!(replicas[0].text(), "");
+    out += &converged_lines("emptied", &replicas);
+
+    std::fs::write(std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("yjs-roundtrip.txt"), out).unwrap();
+}
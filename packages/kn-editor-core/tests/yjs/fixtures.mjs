@@ -0,0 +1,120 @@
+// Yjs side of tests/yjs.rs. Needs the app's node_modules (yjs 13).
+//
+//   node packages/kn-editor-core/tests/yjs/fixtures.mjs generate
+//     rewrite fixtures.txt from the scenarios below
+//   node packages/kn-editor-core/tests/yjs/fixtures.mjs check
+//     fail if fixtures.txt is not what this yjs writes
+//   node packages/kn-editor-core/tests/yjs/fixtures.mjs roundtrip [file]
+//     decode the updates `cargo test --features crdt --test yjs` encoded
+//     (default: target/tmp/yjs-roundtrip.txt) and check them in Yjs
+//
+// fixtures.txt holds one `name hex` line per fixture; tests/yjs.rs reads it
+// with include_str!, so a yjs upgrade that changes the bytes shows up as a
+// `check` failure here and a cargo test failure there.
+
+import { readFileSync, writeFileSync } from 'node:fs'
+import { dirname, join } from 'node:path'
+import { fileURLToPath } from 'node:url'
+import * as Y from 'yjs'
+
+const here = dirname(fileURLToPath(import.meta.url))
+const fixturesPath = join(here, 'fixtures.txt')
+const defaultRoundtripPath = join(here, '..', '..', 'target', 'tmp', 'yjs-roundtrip.txt')
+
+const toHex = (bytes) => Buffer.from(bytes).toString('hex')
+const fromHex = (hex) => new Uint8Array(Buffer.from(hex, 'hex'))
+
+function docWithClient(clientID) {
+  const doc = new Y.Doc()
+  doc.clientID = clientID
+  return doc
+}
+
+/** The bytes of the `update` event for one transaction. */
+function captureUpdate(doc, edit) {
+  let update = null
+  const onUpdate = (u) => {
+    update = u
+  }
+  doc.on('update', onUpdate)
+  doc.transact(edit)
+  doc.off('update', onUpdate)
+  return update
+}
+
+function fixtures() {
+  const out = []
+
+  // Client 1 types "abc" into getText('t'), then deletes "b".
+  const one = docWithClient(1)
+  one.getText('t').insert(0, 'abc')
+  out.push(['abc', Y.encodeStateAsUpdate(one)])
+  out.push(['abc_after_2', Y.encodeStateAsUpdate(one, Y.encodeStateVector(new Map([[1, 2]])))])
+  const abc = Y.encodeStateAsUpdate(one)
+  out.push(['delete_b', captureUpdate(one, () => one.getText('t').delete(1, 1))])
+  out.push(['abc_without_b', Y.encodeStateAsUpdate(one)])
+  out.push(['abc_without_b_sv', Y.encodeStateVector(one)])
+
+  // Client 2, synced with "abc", inserts "X" at 1.
+  const two = docWithClient(2)
+  Y.applyUpdate(two, abc)
+  out.push(['insert_x', captureUpdate(two, () => two.getText('t').insert(1, 'X'))])
+  out.push(['axbc', Y.encodeStateAsUpdate(two)])
+  out.push(['axbc_sv', Y.encodeStateVector(two)])
+
+  // Client 5 sets and deletes a key of another root type.
+  const five = docWithClient(5)
+  out.push(['map_entry', captureUpdate(five, () => five.getMap('m').set('k', 'v'))])
+  out.push(['map_delete', captureUpdate(five, () => five.getMap('m').delete('k'))])
+
+  return out
+}
+
+function render() {
+  const lines = ['# Written by fixtures.mjs from yjs; regenerate instead of editing.']
+  for (const [name, bytes] of fixtures()) lines.push(`${name} ${toHex(bytes)}`)
+  return lines.join('\n') + '\n'
+}
+
+function equalBytes(a, b) {
+  return a.length === b.length && a.every((x, i) => x === b[i])
+}
+
+// Each line is `name field text_hex state_vector_hex update_hex`, one per
+// replica of a scenario, all replicas of a scenario already converged.
+function roundtrip(path) {
+  const failures = []
+  const docs = new Map()
+  for (const line of readFileSync(path, 'utf8').split('\n')) {
+    if (!line || line.startsWith('#')) continue
+    const [name, field, textHex, svHex, updateHex] = line.split(' ')
+    const text = Buffer.from(textHex, 'hex').toString('utf8')
+    const update = fromHex(updateHex)
+    const doc = new Y.Doc()
+    Y.applyUpdate(doc, update)
+    const got = doc.getText(field).toString()
+    if (got !== text) failures.push(`${name}: text ${JSON.stringify(got)}, expected ${JSON.stringify(text)}`)
+    if (toHex(Y.encodeStateVector(doc)) !== svHex) failures.push(`${name}: state vector ${toHex(Y.encodeStateVector(doc))}, expected ${svHex}`)
+    if (!equalBytes(Y.encodeStateAsUpdate(doc), update)) failures.push(`${name}: Yjs re-encodes the update differently`)
+    // Replicas of one scenario must agree on inserts and deletes alike.
+    const first = docs.get(name)
+    if (first && !Y.equalSnapshots(Y.snapshot(first), Y.snapshot(doc))) failures.push(`${name}: replicas have different snapshots`)
+    if (!first) docs.set(name, doc)
+  }
+  if (docs.size === 0) failures.push(`${path}: no updates`)
+  return failures
+}
+
+const [mode = 'check', arg] = process.argv.slice(2)
+let failures = []
+if (mode === 'generate') {
+  writeFileSync(fixturesPath, render())
+} else if (mode === 'check') {
+  if (readFileSync(fixturesPath, 'utf8') !== render()) failures.push('fixtures.txt is stale: run `fixtures.mjs generate`')
+} else if (mode === 'roundtrip') {
+  failures = roundtrip(arg ?? defaultRoundtripPath)
+} else {
+  failures.push(`unknown mode ${mode}`)
+}
+for (const failure of failures) console.error(failure)
+process.exit(failures.length === 0 ? 0 : 1)
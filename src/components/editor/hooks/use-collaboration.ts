@@ -6,6 +6,7 @@ import { BroadcastCollaborationProvider } from '@/lib/editor/collaboration/broad
 import type { CollaborationProvider } from '@/lib/editor/collaboration/provider'
 import { WebSocketCollaborationProvider } from '@/lib/editor/collaboration/websocket-provider'
 import { MCPWebSocketCollaborationProvider } from '@/lib/editor/collaboration/mcp-ws-provider'
+import { loadYjsTextWasm, type YjsTextWasmClass } from '@/lib/editor/wasm'
 import { logger } from '@/lib/logger'
 
 export type PresenceState = {
@@ -86,7 +87,24 @@ export function useCollaboration(options: CollaborationOptions) {
       setCollaborationStatus?.('connected')
     }
 
-    const adapter = new YjsEditorAdapter(model, { doc: provider.doc })
+    // NEXT_PUBLIC_EDITOR_CRDT=wasm makes the WASM YjsText the shared text
+    // (the Y.Doc then only carries its updates); the adapter is attached
+    // once it loads, on Y.Text if it does not.
+    let adapter: YjsEditorAdapter | null = null
+    let disposed = false
+    const attachAdapter = (crdt?: YjsTextWasmClass | null) => {
+      if (disposed) return
+      adapter = new YjsEditorAdapter(model, { doc: provider.doc, crdt: crdt ?? undefined })
+      adapterRef.current = adapter
+    }
+    if (process.env.NEXT_PUBLIC_EDITOR_CRDT === 'wasm') {
+      loadYjsTextWasm().then((crdt) => {
+        if (!crdt) logger.warn('[Collaboration] WASM CRDT unavailable, using Y.Text')
+        attachAdapter(crdt)
+      })
+    } else {
+      attachAdapter()
+    }
 
     provider.awareness.setLocalState({ presence: options.presence })
     const updatePresenceDecorations = () => {
@@ -116,14 +134,14 @@ export function useCollaboration(options: CollaborationOptions) {
     provider.doc.on('update', updatePresenceDecorations)
     updatePresenceDecorations()
 
-    adapterRef.current = adapter
     providerRef.current = provider
     setCollaborationProvider?.(provider)
 
     return () => {
       provider.awareness.off('update', updatePresenceDecorations)
       provider.doc.off('update', updatePresenceDecorations)
-      adapter.destroy()
+      disposed = true
+      adapter?.destroy()
       provider.destroy()
       adapterRef.current = null
       providerRef.current = null
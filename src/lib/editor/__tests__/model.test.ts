@@ -28,6 +28,24 @@ describe('EditorModel', () => {
     expect(model.getBlocks().length).toBe(3)
   })
 
+  it('subscribeOps reports the applied edits', () => {
+    const model = new EditorModel('Alpha Beta')
+    const seen: unknown[] = []
+    const unsub = model.subscribeOps((ops) => seen.push(ops))
+    model.replaceRange(6, 10, 'Gamma')
+    model.updateFromText('Alpha Gamma!')
+    model.applyOps([{ t: 'ins', at: 0, text: '> ' }])
+    model.setText('x')
+    unsub()
+    model.replaceRange(0, 1, 'y')
+    expect(seen).toEqual([
+      [{ t: 'del', at: 6, len: 4 }, { t: 'ins', at: 6, text: 'Gamma' }],
+      [{ t: 'ins', at: 11, text: '!' }],
+      [{ t: 'ins', at: 0, text: '> ' }],
+      [{ t: 'del', at: 0, len: 14 }, { t: 'ins', at: 0, text: 'x' }],
+    ])
+  })
+
   it('clears decorations by type', () => {
     const model = new EditorModel('Alpha\n\nBeta')
     const blockId = model.getBlocks()[0].id
@@ -31,6 +31,7 @@ export type EditorChange = {
 }
 
 type Listener = () => void
+type OpsListener = (ops: EditOp[]) => void
 
 export class EditorModel {
   private doc: RopeText
@@ -38,6 +39,7 @@ export class EditorModel {
   private blocks: EditorBlock[] = []
   private textCache: string
   private listeners = new Set<Listener>()
+  private opsListeners = new Set<OpsListener>()
   private snapshot: EditorSnapshot
   private decorations = new Map<string, BlockDecoration[]>()
 
@@ -61,10 +63,14 @@ export class EditorModel {
    */
   setText(text: string): void {
     if (text === this.textCache) return
+    const ops: EditOp[] = []
+    if (this.textCache.length > 0) ops.push({ t: 'del', at: 0, len: this.textCache.length })
+    if (text.length > 0) ops.push({ t: 'ins', at: 0, text })
     this.doc = new RopeText(text)
     this.textCache = text
     this.blocks = segmentText(text)
     this.decorations.clear()
+    this.notifyOps(ops)
     this.bumpVersion({ start: 0, end: text.length })
   }
 
@@ -75,19 +81,24 @@ export class EditorModel {
   replaceRange(start: number, end: number, text: string): void {
     if (end < start) [start, end] = [end, start]
     const deleteLen = Math.max(0, end - start)
+    const ops: EditOp[] = []
     if (deleteLen > 0) {
       this.doc.delete(start, deleteLen)
+      ops.push({ t: 'del', at: start, len: deleteLen })
     }
     if (text.length > 0) {
       this.doc.insert(start, text)
+      ops.push({ t: 'ins', at: start, text })
     }
     this.rebuildState()
+    this.notifyOps(ops)
     this.bumpVersion({ start, end: start + text.length })
   }
 
   applyOps(ops: EditOp[]): void {
     applyOps(this.doc, ops)
     this.rebuildState()
+    this.notifyOps(ops)
     const range = computeOpsRange(ops)
     this.bumpVersion(range ?? { start: 0, end: this.textCache.length })
   }
@@ -101,15 +112,19 @@ export class EditorModel {
     const diff = computeDiff(prev, next)
     if (!diff) return
 
+    const ops: EditOp[] = []
     if (diff.removeLength > 0) {
       this.doc.delete(diff.start, diff.removeLength)
+      ops.push({ t: 'del', at: diff.start, len: diff.removeLength })
     }
     if (diff.inserted.length > 0) {
       this.doc.insert(diff.start, diff.inserted)
+      ops.push({ t: 'ins', at: diff.start, text: diff.inserted })
     }
     this.textCache = next
     this.blocks = segmentText(next)
     this.decorations.clear()
+    this.notifyOps(ops)
     this.bumpVersion({ start: diff.start, end: diff.start + diff.inserted.length })
   }
 
@@ -120,6 +135,17 @@ export class EditorModel {
     }
   }
 
+  /**
+   * Listen for the text edits themselves, as the `EditOp`s that were applied
+   * in order (UTF-16 offsets). Fires before the `subscribe` listeners.
+   */
+  subscribeOps(listener: OpsListener): () => void {
+    this.opsListeners.add(listener)
+    return () => {
+      this.opsListeners.delete(listener)
+    }
+  }
+
   getSnapshot(): EditorSnapshot {
     return this.snapshot
   }
@@ -200,6 +226,11 @@ export class EditorModel {
     for (const listener of this.listeners) listener()
   }
 
+  private notifyOps(ops: EditOp[]): void {
+    if (ops.length === 0) return
+    for (const listener of this.opsListeners) listener(ops)
+  }
+
   private makeSnapshot(): EditorSnapshot {
     return {
       version: this.version,
@@ -1,6 +1,8 @@
 // Lazy WASM loader with JS fallback
 // If the WASM package is not built/available, consumers can use RopeText (JS) directly.
 
+import type { EditOp } from '../rope-text'
+
 export type CoreTextWasm = {
   new: () => any
   fromString: (s: string) => any
@@ -10,6 +12,17 @@ export type CoreTextWasm = {
   slice: (start: number, end: number) => string
 }
 
+// Y.Text replica from the `crdt` build of the package (see build.sh).
+export type YjsTextWasm = {
+  toString: () => string
+  applyOps: (ops: EditOp[]) => void
+  takeUpdate: () => Uint8Array
+  applyUpdate: (update: Uint8Array) => EditOp[]
+  free: () => void
+}
+
+export type YjsTextWasmClass = new (clientId: number, field: string) => YjsTextWasm
+
 export async function loadCoreWasm(): Promise<CoreTextWasm | null> {
   try {
     // eslint-disable-next-line
@@ -20,3 +33,19 @@ export async function loadCoreWasm(): Promise<CoreTextWasm | null> {
     return null
   }
 }
+
+/**
+ * Load and initialise the WASM package and return its `YjsText` class, or
+ * null if the package is missing or was built without the `crdt` feature.
+ */
+export async function loadYjsTextWasm(): Promise<YjsTextWasmClass | null> {
+  try {
+    // eslint-disable-next-line
+    // @ts-ignore - runtime dynamic import
+    const wasm = await import('../../../../packages/kn-editor-core/pkg/kn_editor_core.js')
+    await wasm.default()
+    return wasm.YjsText ?? null
+  } catch (_e) {
+    return null
+  }
+}
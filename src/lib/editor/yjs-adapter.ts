@@ -1,70 +1,130 @@
 import * as Y from 'yjs'
-import { EditorModel, computeDiff } from './model'
+import { EditorModel } from './model'
+import type { EditOp } from './rope-text'
+import type { YjsTextWasm, YjsTextWasmClass } from './wasm'
 
 type AdapterOptions = {
   doc?: Y.Doc
   field?: string
+  /**
+   * `YjsText` from `loadYjsTextWasm()`. When set, the WASM replica owns the
+   * field instead of Y.Text: local edits are applied to it and leave as the
+   * Yjs updates it encodes, remote updates are merged by it and come back as
+   * `EditOp`s. The Y.Doc only carries updates to and from the provider.
+   */
+  crdt?: YjsTextWasmClass
 }
 
 /**
  * YjsEditorAdapter bridges EditorModel with a Yjs document, supporting
- * collaborative editing via CRDT deltas.
+ * collaborative editing via CRDT deltas. The shared text lives either in
+ * the document's Y.Text or, with `crdt`, in the WASM `YjsText`.
  */
 export class YjsEditorAdapter {
   private readonly doc: Y.Doc
   private readonly field: string
-  private readonly text: Y.Text
+  private text?: Y.Text
   private readonly model: EditorModel
   private applyingRemote = false
   private applyingLocal = false
   private unsubscribeModel?: () => void
+  private core?: YjsTextWasm
 
   constructor(model: EditorModel, options: AdapterOptions = {}) {
     this.model = model
     this.doc = options.doc ?? new Y.Doc()
     this.field = options.field ?? 'content'
-    this.text = this.doc.getText(this.field)
 
+    if (options.crdt) {
+      this.attachCore(options.crdt)
+      return
+    }
+
+    const text = this.doc.getText(this.field)
+    this.text = text
     // Bootstrap shared text from either side
-    const yValue = this.text.toString()
+    const yValue = text.toString()
     const modelValue = model.getText()
     if (yValue.length === 0 && modelValue.length > 0) {
       this.doc.transact(() => {
-        this.text.insert(0, modelValue)
+        text.insert(0, modelValue)
       })
     } else if (yValue !== modelValue) {
       model.setText(yValue)
     }
 
-    this.unsubscribeModel = model.subscribe(this.handleModelChange)
-    this.text.observe(this.handleRemoteChange)
+    this.unsubscribeModel = model.subscribeOps(this.handleModelOps)
+    text.observe(this.handleRemoteChange)
   }
 
   getDoc(): Y.Doc {
     return this.doc
   }
 
+  /** The shared text as the source of truth (Y.Text or `YjsText`) has it. */
+  getSharedText(): string {
+    return this.core ? this.core.toString() : this.text!.toString()
+  }
+
   destroy(): void {
     this.unsubscribeModel?.()
-    this.text.unobserve(this.handleRemoteChange)
+    if (this.core) {
+      this.doc.off('update', this.handleRemoteUpdate)
+      this.core.free()
+      this.core = undefined
+      return
+    }
+    this.text?.unobserve(this.handleRemoteChange)
   }
 
-  private handleModelChange = () => {
-    if (this.applyingRemote) return
-    const target = this.text.toString()
-    const next = this.model.getText()
-    if (target === next) return
+  private attachCore(YjsText: YjsTextWasmClass) {
+    // The replica writes as its own Yjs client, so it needs an id of its own.
+    const core = new YjsText(Math.floor(Math.random() * 0xffffffff), this.field)
+    core.applyUpdate(Y.encodeStateAsUpdate(this.doc))
+    this.core = core
 
-    const diff = computeDiff(target, next)
-    if (!diff) return
+    const coreValue = core.toString()
+    const modelValue = this.model.getText()
+    if (coreValue.length === 0 && modelValue.length > 0) {
+      this.pushLocal([{ t: 'ins', at: 0, text: modelValue }])
+    } else if (coreValue !== modelValue) {
+      this.model.setText(coreValue)
+    }
+
+    this.unsubscribeModel = this.model.subscribeOps(this.handleModelOps)
+    this.doc.on('update', this.handleRemoteUpdate)
+  }
 
+  private pushLocal(ops: EditOp[]) {
+    const core = this.core!
+    core.applyOps(ops)
+    this.applyingLocal = true
+    Y.applyUpdate(this.doc, core.takeUpdate(), this)
+    this.applyingLocal = false
+  }
+
+  private handleRemoteUpdate = (update: Uint8Array, origin: unknown) => {
+    if (origin === this || !this.core) return
+    const ops = this.core.applyUpdate(update)
+    if (ops.length === 0) return
+    this.applyingRemote = true
+    this.model.applyOps(ops)
+    this.applyingRemote = false
+  }
+
+  private handleModelOps = (ops: EditOp[]) => {
+    if (this.applyingRemote) return
+    if (this.core) {
+      this.pushLocal(ops)
+      return
+    }
+
+    const text = this.text!
     this.applyingLocal = true
     this.doc.transact(() => {
-      if (diff.removeLength > 0) {
-        this.text.delete(diff.start, diff.removeLength)
-      }
-      if (diff.inserted.length > 0) {
-        this.text.insert(diff.start, diff.inserted)
+      for (const op of ops) {
+        if (op.t === 'ins') text.insert(op.at, op.text)
+        else text.delete(op.at, op.len)
       }
     })
     this.applyingLocal = false